@@ -0,0 +1,369 @@
+use crate::steam_util::SteamUtilError;
+use std::collections::HashMap;
+
+const TYPE_MAP: u8 = 0x00;
+const TYPE_STRING: u8 = 0x01;
+const TYPE_INT32: u8 = 0x02;
+const TYPE_FLOAT32: u8 = 0x03;
+const TYPE_POINTER: u8 = 0x04;
+const TYPE_COLOR: u8 = 0x06;
+const TYPE_UINT64: u8 = 0x07;
+const TYPE_MAP_END: u8 = 0x08;
+const TYPE_INT64: u8 = 0x0A;
+const TYPE_MAP_END_ALTERNATE: u8 = 0x0B;
+
+const APP_INFO_MAGIC_V27: u32 = 0x07564427;
+const APP_INFO_MAGIC_V28: u32 = 0x07564428;
+/// Since December 2024 keys are stored once in a string table at the end of the file.
+const APP_INFO_MAGIC_V29: u32 = 0x07564429;
+
+/// A value of Steam's binary key value format, used by `shortcuts.vdf` and `appinfo.vdf`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryVdfValue {
+    Map(BinaryVdfMap),
+    String(String),
+    Int32(i32),
+    Float32(f32),
+    UInt64(u64),
+    Int64(i64),
+}
+
+/// Entries of a binary VDF map in file order, keys may repeat and differ in case.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BinaryVdfMap(pub Vec<(String, BinaryVdfValue)>);
+
+impl BinaryVdfMap {
+    /// Looks up a key ignoring case, Steam itself isn't consistent, e.g. `AppName` and `appname`.
+    pub fn get(&self, key: &str) -> Option<&BinaryVdfValue> {
+        self.0
+            .iter()
+            .find(|(entry_key, _)| entry_key.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn get_map(&self, key: &str) -> Option<&BinaryVdfMap> {
+        match self.get(key)? {
+            BinaryVdfValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            BinaryVdfValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_i32(&self, key: &str) -> Option<i32> {
+        match self.get(key)? {
+            BinaryVdfValue::Int32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Numbers in `appinfo.vdf` are stored either as integers or as strings.
+    pub fn get_number(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            BinaryVdfValue::Int32(value) => Some(*value as i64),
+            BinaryVdfValue::UInt64(value) => Some(*value as i64),
+            BinaryVdfValue::Int64(value) => Some(*value),
+            BinaryVdfValue::String(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &BinaryVdfValue> {
+        self.0.iter().map(|(_, value)| value)
+    }
+}
+
+/// Reads binary VDF data, which is a sequence of typed, NUL terminated keys.
+pub struct BinaryVdfReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Keys are indices into this table instead of inline strings when set.
+    key_table: Option<&'a [String]>,
+}
+
+impl<'a> BinaryVdfReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            key_table: None,
+        }
+    }
+
+    fn error(&self, message: &str) -> SteamUtilError {
+        SteamUtilError::VdfParsingError(format!("{} at offset {}", message, self.position))
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SteamUtilError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| self.error("Unexpected end of data"))?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, SteamUtilError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SteamUtilError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SteamUtilError> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_key(&mut self) -> Result<String, SteamUtilError> {
+        match self.key_table {
+            Some(key_table) => {
+                let index = self.read_u32()? as usize;
+                key_table
+                    .get(index)
+                    .cloned()
+                    .ok_or_else(|| self.error(&format!("Key index {} out of range", index)))
+            }
+            None => self.read_string(),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, SteamUtilError> {
+        let length = self.data[self.position..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| self.error("Unterminated string"))?;
+        let string = String::from_utf8_lossy(self.read_bytes(length)?).to_string();
+        self.position += 1;
+        Ok(string)
+    }
+
+    /// Reads map entries until the end marker of the current map.
+    pub fn read_map(&mut self) -> Result<BinaryVdfMap, SteamUtilError> {
+        let mut entries: Vec<(String, BinaryVdfValue)> = Vec::new();
+        loop {
+            let value_type = self.read_u8()?;
+            if value_type == TYPE_MAP_END || value_type == TYPE_MAP_END_ALTERNATE {
+                return Ok(BinaryVdfMap(entries));
+            }
+            let key = self.read_key()?;
+            let value = match value_type {
+                TYPE_MAP => BinaryVdfValue::Map(self.read_map()?),
+                TYPE_STRING => BinaryVdfValue::String(self.read_string()?),
+                TYPE_INT32 | TYPE_POINTER | TYPE_COLOR => BinaryVdfValue::Int32(
+                    i32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()),
+                ),
+                TYPE_FLOAT32 => BinaryVdfValue::Float32(f32::from_le_bytes(
+                    self.read_bytes(4)?.try_into().unwrap(),
+                )),
+                TYPE_UINT64 => BinaryVdfValue::UInt64(u64::from_le_bytes(
+                    self.read_bytes(8)?.try_into().unwrap(),
+                )),
+                TYPE_INT64 => BinaryVdfValue::Int64(i64::from_le_bytes(
+                    self.read_bytes(8)?.try_into().unwrap(),
+                )),
+                _ => return Err(self.error(&format!("Unknown value type {:#04x}", value_type))),
+            };
+            entries.push((key, value));
+        }
+    }
+}
+
+/// Parses a whole binary VDF file, whose root is an unterminated map.
+pub fn parse_binary_vdf(data: &[u8]) -> Result<BinaryVdfMap, SteamUtilError> {
+    BinaryVdfReader::new(data).read_map()
+}
+
+/// Reads the sections of the given apps from `appcache/appinfo.vdf`, other apps are skipped
+/// without parsing as the file holds every app the account has ever seen.
+pub fn parse_app_info(
+    data: &[u8],
+    app_ids: &[u64],
+) -> Result<HashMap<u64, BinaryVdfMap>, SteamUtilError> {
+    let mut reader = BinaryVdfReader::new(data);
+    let magic = reader.read_u32()?;
+    let _universe = reader.read_u32()?;
+
+    let key_table: Vec<String>;
+    if magic == APP_INFO_MAGIC_V29 {
+        let key_table_offset = reader.read_u64()? as usize;
+        let mut key_table_reader = BinaryVdfReader::new(data);
+        key_table_reader.position = key_table_offset;
+        let key_count = key_table_reader.read_u32()?;
+        key_table = (0..key_count)
+            .map(|_| key_table_reader.read_string())
+            .collect::<Result<Vec<String>, SteamUtilError>>()?;
+        reader.key_table = Some(&key_table);
+    } else if magic != APP_INFO_MAGIC_V28 && magic != APP_INFO_MAGIC_V27 {
+        return Err(reader.error(&format!("Unsupported appinfo version {:#010x}", magic)));
+    }
+
+    let mut apps: HashMap<u64, BinaryVdfMap> = HashMap::new();
+    loop {
+        let app_id = reader.read_u32()? as u64;
+        if app_id == 0 {
+            break;
+        }
+        let size = reader.read_u32()? as usize;
+        let end = reader.position + size;
+        if app_ids.contains(&app_id) {
+            // info state, last updated, PICS token, text SHA-1 and change number
+            reader.read_bytes(4 + 4 + 8 + 20 + 4)?;
+            if magic != APP_INFO_MAGIC_V27 {
+                // binary SHA-1
+                reader.read_bytes(20)?;
+            }
+            apps.insert(app_id, reader.read_map()?);
+        }
+        if end > data.len() {
+            return Err(reader.error("Unexpected end of data"));
+        }
+        reader.position = end;
+    }
+    Ok(apps)
+}
+
+/// Serializes a map the way Steam writes it, only used to create test files. Keys are added to
+/// `key_table` and written as indices when it is given.
+#[cfg(test)]
+pub fn write_binary_vdf(
+    map: &BinaryVdfMap,
+    output: &mut Vec<u8>,
+    mut key_table: Option<&mut Vec<String>>,
+) {
+    for (key, value) in &map.0 {
+        let (value_type, bytes) = match value {
+            BinaryVdfValue::Map(map) => {
+                let mut bytes = Vec::new();
+                write_binary_vdf(map, &mut bytes, key_table.as_deref_mut());
+                (TYPE_MAP, bytes)
+            }
+            BinaryVdfValue::String(value) => {
+                let mut bytes = value.as_bytes().to_vec();
+                bytes.push(0);
+                (TYPE_STRING, bytes)
+            }
+            BinaryVdfValue::Int32(value) => (TYPE_INT32, value.to_le_bytes().to_vec()),
+            BinaryVdfValue::Float32(value) => (TYPE_FLOAT32, value.to_le_bytes().to_vec()),
+            BinaryVdfValue::UInt64(value) => (TYPE_UINT64, value.to_le_bytes().to_vec()),
+            BinaryVdfValue::Int64(value) => (TYPE_INT64, value.to_le_bytes().to_vec()),
+        };
+        output.push(value_type);
+        match key_table.as_deref_mut() {
+            Some(key_table) => {
+                let index = match key_table.iter().position(|entry| entry == key) {
+                    Some(index) => index,
+                    None => {
+                        key_table.push(key.clone());
+                        key_table.len() - 1
+                    }
+                };
+                output.extend((index as u32).to_le_bytes());
+            }
+            None => {
+                output.extend_from_slice(key.as_bytes());
+                output.push(0);
+            }
+        }
+        output.extend(bytes);
+    }
+    output.push(TYPE_MAP_END);
+}
+
+/// Builds a version 29 `appinfo.vdf`, only used to create test files.
+#[cfg(test)]
+pub fn write_app_info(apps: &[(u64, BinaryVdfMap)]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend(APP_INFO_MAGIC_V29.to_le_bytes());
+    output.extend(1u32.to_le_bytes());
+    // Key table offset, filled in below
+    output.extend(0u64.to_le_bytes());
+    let mut key_table: Vec<String> = Vec::new();
+    for (app_id, app) in apps {
+        let mut section = vec![0; 4 + 4 + 8 + 20 + 4 + 20];
+        write_binary_vdf(app, &mut section, Some(&mut key_table));
+        output.extend((*app_id as u32).to_le_bytes());
+        output.extend((section.len() as u32).to_le_bytes());
+        output.extend(section);
+    }
+    output.extend(0u32.to_le_bytes());
+    let key_table_offset = output.len() as u64;
+    output[8..16].copy_from_slice(&key_table_offset.to_le_bytes());
+    output.extend((key_table.len() as u32).to_le_bytes());
+    for key in key_table {
+        output.extend(key.as_bytes());
+        output.push(0);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_binary_vdf() {
+        let map = BinaryVdfMap(vec![(
+            "shortcuts".to_string(),
+            BinaryVdfValue::Map(BinaryVdfMap(vec![(
+                "0".to_string(),
+                BinaryVdfValue::Map(BinaryVdfMap(vec![
+                    ("appid".to_string(), BinaryVdfValue::Int32(-1234567)),
+                    (
+                        "AppName".to_string(),
+                        BinaryVdfValue::String("RetroArch".to_string()),
+                    ),
+                    (
+                        "LastPlayTime".to_string(),
+                        BinaryVdfValue::UInt64(1700000000),
+                    ),
+                ])),
+            )])),
+        )]);
+        let mut data = Vec::new();
+        write_binary_vdf(&map, &mut data, None);
+
+        let parsed = parse_binary_vdf(&data).expect("Failed to parse binary VDF");
+        assert_eq!(parsed, map);
+        let shortcut = parsed
+            .get_map("Shortcuts")
+            .and_then(|shortcuts| shortcuts.get_map("0"))
+            .expect("Missing shortcut");
+        assert_eq!(shortcut.get_str("appname"), Some("RetroArch"));
+        assert_eq!(shortcut.get_i32("AppId"), Some(-1234567));
+
+        assert!(parse_binary_vdf(&data[..data.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_parse_app_info() {
+        let app = |name: &str| {
+            BinaryVdfMap(vec![(
+                "appinfo".to_string(),
+                BinaryVdfValue::Map(BinaryVdfMap(vec![(
+                    "common".to_string(),
+                    BinaryVdfValue::Map(BinaryVdfMap(vec![(
+                        "name".to_string(),
+                        BinaryVdfValue::String(name.to_string()),
+                    )])),
+                )])),
+            )])
+        };
+        let data = write_app_info(&[(730, app("Counter-Strike 2")), (1145360, app("Hades"))]);
+
+        let apps = parse_app_info(&data, &[1145360]).expect("Failed to parse appinfo");
+        assert_eq!(apps.len(), 1);
+        assert_eq!(apps.get(&1145360), Some(&app("Hades")));
+
+        let apps = parse_app_info(&data, &[730, 1145360, 42]).expect("Failed to parse appinfo");
+        assert_eq!(apps.len(), 2);
+        assert_eq!(apps.get(&730), Some(&app("Counter-Strike 2")));
+    }
+}
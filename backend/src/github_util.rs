@@ -84,6 +84,7 @@ pub async fn list_all_releases(
     Ok(releases)
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum GitHubUtilError {
     RequestError(String),
@@ -1,11 +1,18 @@
+mod binary_vdf;
 mod github_util;
 mod multilogger;
 mod steam_util;
 mod wine_cask;
+mod wine_registry;
 
 use crate::multilogger::MultiLogger;
-use crate::steam_util::SteamUtil;
 use crate::wine_cask::app::{AppState, Request, RequestType, TaskType, UpdaterState, WineCask};
+use crate::wine_cask::backup::list_prefix_backups;
+use crate::wine_cask::settings::Settings;
+use crate::wine_cask::steam_installations::{
+    find_steam_installations, wait_for_steam_installations, watch_steam_process,
+};
+use crate::wine_cask::watcher::watch_steam_directories;
 use futures_channel::mpsc::{unbounded, UnboundedSender};
 use futures_util::{future, pin_mut, stream::TryStreamExt, StreamExt};
 use log::{error, info, Level};
@@ -14,8 +21,7 @@ use std::env;
 use std::fs::OpenOptions;
 use std::io::Error as IoError;
 use std::net::SocketAddr;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
@@ -33,7 +39,17 @@ async fn main() -> Result<(), IoError> {
 
     let state = PeerMap::new(Mutex::new(HashMap::new()));
 
-    let steam_util = SteamUtil::new(get_steam_directory());
+    let settings = Settings::load();
+
+    // Steam writes libraryfolders.vdf only after the first login, start without it
+    let (steam_installations, steam_not_found) = match find_steam_installations() {
+        Ok(steam_installations) => (steam_installations, None),
+        Err(err) => {
+            error!("Failed to find Steam, waiting for it to appear: {}", err);
+            (Vec::new(), Some(err.to_string()))
+        }
+    };
+    let wait_for_steam = steam_not_found.is_some();
 
     let app_state = AsyncAppState::new(Mutex::new(AppState {
         available_flavors: Vec::new(),
@@ -42,17 +58,44 @@ async fn main() -> Result<(), IoError> {
         task_queue: VecDeque::new(),
         updater_state: UpdaterState::Idle,
         updater_last_check: None,
+        compatibility_tools_disk_usage: None,
+        last_deduplication: None,
+        compat_data_prefixes: None,
+        prefix_backups: list_prefix_backups(&settings.get_backup_directory()),
+        prefix_version_warnings: Vec::new(),
+        settings,
+        steam_installations,
+        managed_steam_installations: Vec::new(),
+        steam_not_found,
+        installed_games: Vec::new(),
+        library_folders: Vec::new(),
+        steam_users: Vec::new(),
+        running_steam_installations: Vec::new(),
+        launch_options: None,
+        translation_layers: None,
+        proton_logs: None,
+        prefix_registry: None,
+        history: None,
         available_compat_tools: None,
         flavors: Vec::new(),
+        disk_usage_cache: None,
+        app_info_cache: HashMap::new(),
+        running_task: None,
+        partial_directories: Vec::new(),
     }));
 
     let wine_cask = WineCask {
-        steam_util,
+        managed_steam_installations: RwLock::new(Vec::new()),
         app_state: app_state.clone(),
     };
 
+    wine_cask.update_managed_steam_installations().await;
+
     initialize_app_state(&wine_cask).await;
 
+    // Queued tasks and an install interrupted by a restart pick up where they left off
+    wine_cask.restore_task_state().await;
+
     let wine_cask_arc = ArcWineCask::new(wine_cask);
 
     tokio::spawn(wine_cask::process_queue(
@@ -60,6 +103,20 @@ async fn main() -> Result<(), IoError> {
         state.clone(),
     ));
 
+    tokio::spawn(watch_steam_directories(
+        wine_cask_arc.clone(),
+        state.clone(),
+    ));
+
+    tokio::spawn(watch_steam_process(wine_cask_arc.clone(), state.clone()));
+
+    if wait_for_steam {
+        tokio::spawn(wait_for_steam_installations(
+            wine_cask_arc.clone(),
+            state.clone(),
+        ));
+    }
+
     start_server(addr, wine_cask_arc, state).await;
 
     info!("Exiting...");
@@ -169,21 +226,6 @@ fn get_server_address() -> String {
         .unwrap_or_else(|| "127.0.0.1:8887".to_string())
 }
 
-fn get_steam_directory() -> PathBuf {
-    match env::var("DECKY_USER_HOME") {
-        Ok(value) => {
-            info!("Using DECKY_USER_HOME: {}", value);
-            SteamUtil::find_steam_directory(Some(value)).unwrap() // Todo: Handle if no steam folder is found, although this should never happen
-        }
-        Err(_) => {
-            error!(
-                "Couldn't find environment variable DECKY_USER_HOME, using default steam directory"
-            );
-            SteamUtil::find_steam_directory(None).unwrap()
-        }
-    }
-}
-
 async fn initialize_app_state(wine_cask: &WineCask) {
     wine_cask
         .app_state
@@ -204,10 +246,27 @@ async fn handle_request(wine_cask: &Arc<WineCask>, msg: &str, peer_map: &PeerMap
                     )
                     .await;
                 wine_cask.update_used_by_games(peer_map).await;
+
+                // Measuring tools can take a while, don't hold up the state response
+                let wine_cask = wine_cask.clone();
+                let peer_map = peer_map.clone();
+                tokio::spawn(async move { wine_cask.update_disk_usage(&peer_map).await });
             }
             RequestType::Task => {
                 if let Some(task) = request.task {
-                    if task.r#type == TaskType::InstallCompatibilityTool {
+                    if let Some(reason) = wine_cask.steam_not_found_for_task(&task).await {
+                        wine_cask
+                            .broadcast_notification(
+                                peer_map,
+                                &format!("Steam Not Found: {}", reason),
+                            )
+                            .await;
+                    } else if task.r#type == TaskType::InstallCompatibilityTool
+                        || task.r#type == TaskType::DeduplicateCompatibilityTools
+                        || task.r#type == TaskType::SwapTranslationLayer
+                        || task.r#type == TaskType::BackupCompatDataPrefix
+                        || task.r#type == TaskType::RestoreCompatDataPrefix
+                    {
                         wine_cask.add_to_task_queue(task, peer_map).await;
                     } else if task.r#type == TaskType::CancelCompatibilityToolInstall {
                         wine_cask
@@ -222,6 +281,67 @@ async fn handle_request(wine_cask: &Arc<WineCask>, msg: &str, peer_map: &PeerMap
                             .await;
                     } else if task.r#type == TaskType::CheckForFlavorUpdates {
                         wine_cask.check_for_flavor_updates(peer_map, true).await;
+                    } else if task.r#type == TaskType::ListCompatDataPrefixes {
+                        wine_cask.list_compat_data_prefixes(peer_map).await;
+                        wine_cask.update_prefix_backups(peer_map).await;
+                    } else if task.r#type == TaskType::DeleteCompatDataPrefix {
+                        wine_cask
+                            .delete_compat_data_prefix(task.prefix.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::ResetCompatDataPrefix {
+                        wine_cask
+                            .reset_compat_data_prefix(task.prefix.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::UpdateSettings {
+                        wine_cask
+                            .update_settings(task.settings.unwrap(), peer_map)
+                            .await;
+                        wine_cask.update_prefix_backups(peer_map).await;
+                    } else if task.r#type == TaskType::GetLaunchOptions {
+                        wine_cask
+                            .get_launch_options(task.launch_options.unwrap().app_id, peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::UpdateLaunchOptions {
+                        wine_cask
+                            .update_launch_options(task.launch_options.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::RenameCompatibilityTool {
+                        wine_cask
+                            .rename_compatibility_tool(task.rename.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::CloneCompatibilityTool {
+                        wine_cask
+                            .clone_compatibility_tool(task.clone_tool.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::GetTranslationLayers {
+                        wine_cask
+                            .get_translation_layers(
+                                &task.translation_layers.unwrap().steam_compatibility_tool,
+                                peer_map,
+                            )
+                            .await;
+                    } else if task.r#type == TaskType::ListProtonLogs {
+                        wine_cask.list_proton_logs(peer_map).await;
+                    } else if task.r#type == TaskType::BundleProtonLog {
+                        wine_cask
+                            .bundle_proton_log(task.proton_log.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::GetPrefixRegistry {
+                        wine_cask
+                            .get_prefix_registry(task.prefix_registry.unwrap().app_id, peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::UpdateDllOverrides {
+                        wine_cask
+                            .update_dll_overrides(task.prefix_registry.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::UpdateWindowsVersion {
+                        wine_cask
+                            .update_windows_version(task.prefix_registry.unwrap(), peer_map)
+                            .await;
+                    } else if task.r#type == TaskType::GetHistory {
+                        wine_cask
+                            .get_history(task.history.unwrap_or_default(), peer_map)
+                            .await;
                     }
                 } else {
                     wine_cask
@@ -1,13 +1,19 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::ffi::CString;
 use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
 use std::{env, fmt};
 
-use keyvalues_parser::Vdf;
+use crate::binary_vdf::{parse_app_info, parse_binary_vdf, BinaryVdfMap, BinaryVdfValue};
+use keyvalues_parser::{Obj, Value, Vdf};
 use log::{error, info, warn};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Represents errors that can occur while using `SteamUtil`.
 #[derive(Debug, Clone)]
@@ -28,21 +34,60 @@ pub enum SteamUtilError {
     VdfParsingError(String),
     /// Missing Vdf Entry
     VdfMissingEntry(String),
+    /// The compatdata prefix could not be found.
+    CompatDataPrefixNotFound(u64),
+    /// No user has logged in to Steam yet.
+    SteamUserNotFound,
+    /// The user's local config vdf could not be found.
+    LocalConfigVdfNotFound,
+    /// Writing a Steam config file failed, with the reason.
+    ConfigWriteFailed(String),
+    /// Steam is running with the process id and would overwrite config changes on exit.
+    SteamRunning(u32),
 }
 
 /// Utility for working with Steam directories and settings.
+#[derive(Clone)]
 pub struct SteamUtil {
     steam_path: PathBuf,
+    /// Where running processes are looked up, only replaced by tests.
+    proc_directory: PathBuf,
+}
+
+/// A Steam installation found in the user's home directory.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SteamInstallation {
+    /// The Steam root relative to the home directory it was found under, e.g. `.local/share/Steam`.
+    pub id: String,
+    /// Canonical path of the Steam root.
+    pub path: PathBuf,
+    pub flatpak: bool,
 }
 
 #[derive(Serialize, Clone)]
 pub struct CompatibilityTool {
+    /// The directory holding the tool's `compatibilitytool.vdf`.
     pub path: PathBuf,
+    /// The directory Steam runs the tool from, `install_path` resolved relative to the vdf.
+    pub install_path: PathBuf,
     pub directory_name: String,
     pub internal_name: String,
     pub display_name: String,
+    /// The display name before the tool was renamed.
+    pub original_display_name: Option<String>,
     pub from_os_list: String,
     pub to_os_list: String,
+    /// App id of official tools Steam installs as apps under `steamapps/common`.
+    pub app_id: Option<u64>,
+}
+
+/// A tool's `toolmanifest.vdf`, which tells Steam how to launch it.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ToolManifest {
+    pub commandline: String,
+    /// App id of the runtime the tool runs in, e.g. the Steam Linux Runtime for Proton.
+    pub require_tool_appid: Option<u64>,
+    pub compatmanager_layer_name: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -51,21 +96,112 @@ pub struct SteamApp {
     pub name: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum LibraryFolderStatus {
+    Ok,
+    /// The library folder doesn't exist anymore.
+    Missing,
+    /// The library folder is on a removable drive that isn't mounted.
+    Unmounted,
+    /// The library folder or some of its app manifests couldn't be read.
+    ParseError,
+}
+
+/// A Steam library folder, as listed in `steamapps/libraryfolders.vdf`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LibraryFolder {
+    pub path: PathBuf,
+    pub label: String,
+    pub content_id: Option<String>,
+    /// Size on disk of every app installed in the library, by app id.
+    pub apps: HashMap<u64, u64>,
+    /// Whether the library folder is accessible, e.g. its SD card is inserted.
+    pub mounted: bool,
+    /// Size of the filesystem the library is on in bytes.
+    pub capacity: Option<u64>,
+    /// Bytes available to unprivileged users on the filesystem the library is on.
+    pub free_space: Option<u64>,
+}
+
+/// The installed games of a single library folder.
+pub struct LibraryGames {
+    pub library_folder: LibraryFolder,
+    pub status: LibraryFolderStatus,
+    pub error: Option<String>,
+    pub games: Vec<SteamApp>,
+}
+
+/// A Steam account that logged in on this installation, from `config/loginusers.vdf`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SteamUser {
+    /// 64-bit SteamID, kept as a string as it doesn't fit into a JavaScript number.
+    pub steam_id: String,
+    /// Account ID, the name of the user's directory in `userdata`.
+    pub account_id: u64,
+    pub account_name: String,
+    pub persona_name: String,
+    /// Whether this is the account Steam logged in with last.
+    pub most_recent: bool,
+    pub timestamp: u64,
+}
+
+/// The lowest individual account SteamID, account IDs are relative to it.
+const STEAM_ID_INDIVIDUAL_BASE: u64 = 76561197960265728;
+
+/// Steam Deck compatibility rating of a game.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum SteamDeckCompatibility {
+    #[default]
+    Unknown,
+    Unsupported,
+    Playable,
+    Verified,
+}
+
+/// Metadata Steam caches for an app in `appcache/appinfo.vdf`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct AppInfo {
+    /// Platforms the game is available for, e.g. `windows` and `linux`.
+    pub os_list: Vec<String>,
+    pub steam_deck_compatibility: SteamDeckCompatibility,
+    /// Compatibility tool Valve assigns to the game, e.g. `proton_9`.
+    pub recommended_compat_tool: Option<String>,
+    pub install_dir: Option<String>,
+}
+
+/// Key under which the display name a tool shipped with is kept in `compatibilitytool.vdf` after
+/// renaming it, Steam ignores keys it doesn't know.
+const ORIGINAL_DISPLAY_NAME_KEY: &str = "wine_cellar_original_display_name";
+
+/// The "Steam Play 2.0 Manifests" app, which maps games to the tool Valve tested them with.
+const STEAM_PLAY_MANIFESTS_APP_ID: u64 = 891390;
+
+#[derive(Serialize, Clone)]
+pub struct CompatDataPrefix {
+    pub app_id: u64,
+    pub path: PathBuf,
+    /// Prefix version written by Proton to `compatdata/<appid>/version`.
+    pub prefix_version: Option<String>,
+    /// Version of the tool that last ran the prefix, from the first line of `config_info`.
+    pub tool_version: Option<String>,
+}
+
 impl SteamUtil {
     /// Creates a new instance of `SteamUtil` with the given Steam home directory.
     pub fn new(steam_home: PathBuf) -> Self {
         Self {
             steam_path: steam_home,
+            proc_directory: PathBuf::from("/proc"),
         }
     }
 
-    /// Finds the Steam directory.
-    pub fn find_steam_directory(
+    /// Finds every Steam installation, `.steam/root` and `.steam/steam` are usually symlinks to
+    /// one of the other roots so installations are deduplicated by their canonical path.
+    pub fn find_steam_installations(
         user_home_directory: Option<String>,
-    ) -> Result<PathBuf, SteamUtilError> {
+    ) -> Result<Vec<SteamInstallation>, SteamUtilError> {
         // Possible Steam root directories
         let possible_steam_roots = [
-            // todo: handle multiple installations perhaps a dropdown in frontend if we detect multiple installation
             ".local/share/Steam",
             ".steam/root",
             ".steam/steam",
@@ -79,24 +215,84 @@ impl SteamUtil {
                 .or_else(|| env::var_os("HOME").map(PathBuf::from))
         });
 
-        if let Some(user_profile) = user_profile {
-            info!("Looking for Steam directory in {}", user_profile.display());
-            for steam_dir in &possible_steam_roots {
-                let expanded_steam_dir = user_profile.join(steam_dir);
-                let ct_dir = expanded_steam_dir.join("config");
-                let config_vdf = ct_dir.join("config.vdf"); // this does exist on clean install
-                let libraryfolders_vdf = ct_dir.join("libraryfolders.vdf"); // On a clean install doesn't exist, it's generated after login
-
-                if config_vdf.exists() && libraryfolders_vdf.exists() {
-                    info!("Found Steam directory: {}", expanded_steam_dir.display());
-                    return Ok(expanded_steam_dir);
-                }
-            }
-        } else {
+        let Some(user_profile) = user_profile else {
             return Err(SteamUtilError::HomeDirectoryNotFound);
+        };
+
+        info!(
+            "Looking for Steam directories in {}",
+            user_profile.display()
+        );
+        let mut steam_installations: Vec<SteamInstallation> = Vec::new();
+        let mut waiting_for_login = false;
+        for steam_dir in &possible_steam_roots {
+            let expanded_steam_dir = user_profile.join(steam_dir);
+            let ct_dir = expanded_steam_dir.join("config");
+            let config_vdf = ct_dir.join("config.vdf"); // this does exist on clean install
+            let libraryfolders_vdf = ct_dir.join("libraryfolders.vdf"); // On a clean install doesn't exist, it's generated after login
+
+            if !config_vdf.exists() {
+                continue;
+            }
+            if !libraryfolders_vdf.exists() {
+                waiting_for_login = true;
+                continue;
+            }
+
+            let canonical_steam_dir =
+                fs::canonicalize(&expanded_steam_dir).unwrap_or(expanded_steam_dir);
+            if steam_installations
+                .iter()
+                .any(|installation| installation.path == canonical_steam_dir)
+            {
+                continue;
+            }
+
+            info!("Found Steam directory: {}", canonical_steam_dir.display());
+            steam_installations.push(SteamInstallation {
+                id: steam_dir.to_string(),
+                flatpak: steam_dir.contains("com.valvesoftware.Steam"),
+                path: canonical_steam_dir,
+            });
+        }
+
+        if steam_installations.is_empty() {
+            return Err(if waiting_for_login {
+                SteamUtilError::LibraryFoldersVdfNotFound
+            } else {
+                SteamUtilError::SteamDirectoryNotFound
+            });
         }
+        Ok(steam_installations)
+    }
 
-        Err(SteamUtilError::SteamDirectoryNotFound)
+    /// The directory holding `config.vdf`, Steam's global configuration.
+    pub fn get_steam_config_directory(&self) -> PathBuf {
+        self.steam_path.join("config")
+    }
+
+    /// The files Steam may have written its process id to while it runs. `steam.sh` writes
+    /// `~/.steam/steam.pid` and the Flatpak writes the `.steam` directory of its app directory,
+    /// either way that's the first `.steam` directory above the installation.
+    pub fn get_steam_pid_files(&self) -> Vec<PathBuf> {
+        let mut pid_files: Vec<PathBuf> = self
+            .steam_path
+            .ancestors()
+            .skip(1)
+            .find(|directory| directory.join(".steam").is_dir())
+            .map(|directory| directory.join(".steam").join("steam.pid"))
+            .into_iter()
+            .collect();
+        pid_files.push(self.steam_path.join("steam.pid"));
+        pid_files
+    }
+
+    /// The process id of the Steam client using this installation, `None` if it isn't running.
+    pub fn get_running_steam_pid(&self) -> Option<u32> {
+        self.get_steam_pid_files()
+            .iter()
+            .find_map(|pid_file| get_running_pid(pid_file, &self.proc_directory))
+            .or_else(|| find_steam_process(&self.steam_path, &self.proc_directory))
     }
 
     pub fn get_steam_compatibility_tools_directory(&self) -> PathBuf {
@@ -110,10 +306,11 @@ impl SteamUtil {
         path
     }
 
-    pub fn read_compatibility_tool_from_vdf_path(
+    /// Reads every tool a `compatibilitytool.vdf` declares, a single file may declare several.
+    pub fn read_compatibility_tools_from_vdf_path(
         &self,
-        compat_tool_vdf: &PathBuf,
-    ) -> Result<CompatibilityTool, SteamUtilError> {
+        compat_tool_vdf: &Path,
+    ) -> Result<Vec<CompatibilityTool>, SteamUtilError> {
         // Read the content of the VDF file
         let vdf_text = fs::read_to_string(compat_tool_vdf)
             .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
@@ -122,8 +319,8 @@ impl SteamUtil {
         let vdf = Vdf::parse(&vdf_text)
             .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
 
-        // Extract the compatibility tool object from the parsed VDF
-        let compat_tool_obj = vdf
+        // Extract the compat_tools object from the parsed VDF
+        let compat_tools_obj = vdf
             .value
             .get_obj()
             .and_then(|f| f.values().next())
@@ -146,53 +343,279 @@ impl SteamUtil {
             .ok_or_else(|| SteamUtilError::VdfMissingEntry("Directory name not found".to_string()))?
             .to_string();
 
-        // Extract internal name, display name, from_os_list, and to_os_list from the compatibility tool object
-        let internal_name = compat_tool_obj
-            .keys()
-            .next()
-            .ok_or_else(|| SteamUtilError::VdfMissingEntry("Internal name not found".to_string()))?
-            .to_string();
+        let mut compat_tools: Vec<CompatibilityTool> = Vec::new();
+        for (internal_name, internal_values) in compat_tools_obj.iter() {
+            // Extract display name, from_os_list, to_os_list and install_path of each tool
+            let Some(internal_value) = internal_values.first().and_then(|o| o.get_obj()) else {
+                error!(
+                    "Invalid entry {} in {}",
+                    internal_name,
+                    compat_tool_vdf.display()
+                );
+                continue;
+            };
+            let get_str = |key: &str| {
+                internal_value
+                    .get(key)
+                    .and_then(|o| o.first())
+                    .and_then(|o| o.get_str())
+                    .map(|o| o.to_string())
+            };
+            let (Some(display_name), Some(from_os_list), Some(to_os_list)) = (
+                get_str("display_name"),
+                get_str("from_oslist"),
+                get_str("to_oslist"),
+            ) else {
+                error!(
+                    "Entry {} in {} is missing display_name, from_oslist or to_oslist",
+                    internal_name,
+                    compat_tool_vdf.display()
+                );
+                continue;
+            };
+            // Steam resolves install_path relative to the vdf
+            let install_path = match get_str("install_path") {
+                Some(install_path) => normalize_path(&path.join(install_path)),
+                None => path.clone(),
+            };
+
+            compat_tools.push(CompatibilityTool {
+                path: path.clone(),
+                install_path,
+                directory_name: directory_name.clone(),
+                internal_name: internal_name.to_string(),
+                display_name,
+                original_display_name: get_str(ORIGINAL_DISPLAY_NAME_KEY),
+                from_os_list,
+                to_os_list,
+                app_id: None,
+            });
+        }
+        Ok(compat_tools)
+    }
 
-        let internal_value = compat_tool_obj
-            .values()
-            .next()
-            .and_then(|o| o.first())
-            .and_then(|o| o.get_obj())
-            .ok_or_else(|| {
-                SteamUtilError::VdfMissingEntry("Internal value not found".to_string())
-            })?;
+    /// Renames a tool in its `compatibilitytool.vdf`, remembering the original display name the
+    /// first time. Restores the original display name when `display_name` is `None`.
+    pub fn set_compatibility_tool_display_name(
+        &self,
+        compat_tool_vdf: &Path,
+        internal_name: &str,
+        display_name: Option<&str>,
+    ) -> Result<(), SteamUtilError> {
+        let vdf_text = fs::read_to_string(compat_tool_vdf)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let mut vdf = Vdf::parse(&vdf_text)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let compat_tool_obj = vdf
+            .value
+            .get_mut_obj()
+            .and_then(|f| f.values_mut().next())
+            .and_then(|f| f.first_mut())
+            .and_then(|f| f.get_mut_obj())
+            .and_then(|f| f.get_mut(internal_name))
+            .and_then(|f| f.first_mut())
+            .and_then(|f| f.get_mut_obj())
+            .ok_or_else(|| SteamUtilError::VdfMissingEntry(internal_name.to_string()))?;
+        let get_str = |obj: &Obj, key: &str| {
+            obj.get(key)
+                .and_then(|o| o.first())
+                .and_then(|o| o.get_str())
+                .map(|o| o.to_string())
+        };
+        let current_display_name = get_str(compat_tool_obj, "display_name")
+            .ok_or_else(|| SteamUtilError::VdfMissingEntry("display_name".to_string()))?;
+        let original_display_name = get_str(compat_tool_obj, ORIGINAL_DISPLAY_NAME_KEY);
+
+        let new_display_name = match display_name {
+            Some(display_name) => {
+                if original_display_name.is_none() {
+                    compat_tool_obj.insert(
+                        Cow::from(ORIGINAL_DISPLAY_NAME_KEY),
+                        vec![Value::Str(Cow::from(current_display_name))],
+                    );
+                }
+                display_name.to_string()
+            }
+            None => {
+                compat_tool_obj.remove(ORIGINAL_DISPLAY_NAME_KEY);
+                original_display_name.unwrap_or(current_display_name)
+            }
+        };
+        compat_tool_obj.insert(
+            Cow::from("display_name"),
+            vec![Value::Str(Cow::from(new_display_name))],
+        );
+        write_steam_config_file(compat_tool_vdf, &vdf.to_string())
+    }
 
-        let display_name = internal_value
-            .get("display_name")
-            .and_then(|o| o.first())
-            .and_then(|o| o.get_str())
-            .and_then(|o| Option::from(o.to_string()))
-            .ok_or_else(|| SteamUtilError::VdfMissingEntry("Display name not found".to_string()))?;
+    /// Removes a tool from a `compatibilitytool.vdf` declaring several, keeping the others.
+    pub fn remove_compatibility_tool_from_vdf(
+        &self,
+        compat_tool_vdf: &Path,
+        internal_name: &str,
+    ) -> Result<(), SteamUtilError> {
+        let vdf_text = fs::read_to_string(compat_tool_vdf)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let mut vdf = Vdf::parse(&vdf_text)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let compat_tools_obj = vdf
+            .value
+            .get_mut_obj()
+            .and_then(|f| f.values_mut().next())
+            .and_then(|f| f.first_mut())
+            .and_then(|f| f.get_mut_obj())
+            .ok_or_else(|| SteamUtilError::VdfParsingError("Invalid VDF structure".to_string()))?;
+        if compat_tools_obj.remove(internal_name).is_none() {
+            return Err(SteamUtilError::VdfMissingEntry(internal_name.to_string()));
+        }
+        write_steam_config_file(compat_tool_vdf, &vdf.to_string())
+    }
 
-        let from_os_list = internal_value
-            .get("from_oslist")
-            .and_then(|o| o.first())
-            .and_then(|o| o.get_str())
-            .and_then(|o| Option::from(o.to_string()))
-            .ok_or_else(|| SteamUtilError::VdfMissingEntry("From OS list not found".to_string()))?;
+    pub fn read_tool_manifest(
+        &self,
+        tool_manifest_vdf: &Path,
+    ) -> Result<ToolManifest, SteamUtilError> {
+        let vdf_text = fs::read_to_string(tool_manifest_vdf)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let vdf = Vdf::parse(&vdf_text)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let manifest_obj = vdf
+            .value
+            .get_obj()
+            .ok_or_else(|| SteamUtilError::VdfParsingError("Invalid VDF structure".to_string()))?;
+        let get_str = |key: &str| {
+            manifest_obj
+                .get(key)
+                .and_then(|o| o.first())
+                .and_then(|o| o.get_str())
+                .map(|o| o.to_string())
+        };
 
-        let to_os_list = internal_value
-            .get("to_oslist")
-            .and_then(|o| o.first())
-            .and_then(|o| o.get_str())
-            .and_then(|o| Option::from(o.to_string()))
-            .ok_or_else(|| SteamUtilError::VdfMissingEntry("To OS list not found".to_string()))?;
+        Ok(ToolManifest {
+            commandline: get_str("commandline")
+                .ok_or_else(|| SteamUtilError::VdfMissingEntry("commandline".to_string()))?,
+            require_tool_appid: get_str("require_tool_appid").and_then(|o| o.parse().ok()),
+            compatmanager_layer_name: get_str("compatmanager_layer_name"),
+        })
+    }
 
-        // Create a CompatibilityTool struct and return it
-        let steam_compat_tool = CompatibilityTool {
-            path,
-            directory_name,
-            internal_name,
-            display_name,
-            from_os_list,
-            to_os_list,
+    /// Reads the official tools an app manifest belongs to, none if the app isn't a tool.
+    fn read_official_compatibility_tools(
+        &self,
+        app_manifest: &Path,
+        official_tool_names: &OnceCell<HashMap<u64, String>>,
+    ) -> Result<Vec<CompatibilityTool>, SteamUtilError> {
+        let app_manifest_text = fs::read_to_string(app_manifest)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let vdf = Vdf::parse(&app_manifest_text)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let get_str = |key: &str| {
+            vdf.value
+                .get_obj()
+                .and_then(|f| f.get(key))
+                .and_then(|f| f.first())
+                .and_then(|f| f.get_str())
+                .map(|f| f.to_string())
+                .ok_or_else(|| SteamUtilError::VdfMissingEntry(key.to_string()))
         };
-        Ok(steam_compat_tool)
+        let app_id: u64 = get_str("appid")?
+            .parse()
+            .map_err(|_| SteamUtilError::VdfMissingEntry("appid".to_string()))?;
+        let name = get_str("name")?;
+        let install_dir = get_str("installdir")?;
+
+        let path = app_manifest.with_file_name("common").join(&install_dir);
+        let tool_manifest_vdf = path.join("toolmanifest.vdf");
+        if !tool_manifest_vdf.exists() {
+            return Ok(Vec::new());
+        }
+
+        // Tools built from Valve's Proton repository ship the name Steam maps games to
+        let compat_tool_vdf = path.join("compatibilitytool.vdf");
+        if compat_tool_vdf.exists() {
+            let mut compat_tools = self.read_compatibility_tools_from_vdf_path(&compat_tool_vdf)?;
+            for compat_tool in &mut compat_tools {
+                compat_tool.app_id = Some(app_id);
+            }
+            return Ok(compat_tools);
+        }
+
+        let tool_manifest = self.read_tool_manifest(&tool_manifest_vdf)?;
+        let from_os_list = match tool_manifest.compatmanager_layer_name.as_deref() {
+            Some("proton") => "windows",
+            _ => "linux",
+        };
+        let internal_name = official_tool_names
+            .get_or_init(|| {
+                self.read_official_tool_names().unwrap_or_else(|err| {
+                    warn!("Failed to read official tool names, guessing them: {}", err);
+                    HashMap::new()
+                })
+            })
+            .get(&app_id)
+            .cloned()
+            .unwrap_or_else(|| get_official_tool_internal_name(&name, &install_dir));
+        Ok(vec![CompatibilityTool {
+            internal_name,
+            install_path: path.clone(),
+            path,
+            directory_name: install_dir,
+            display_name: name,
+            original_display_name: None,
+            from_os_list: from_os_list.to_string(),
+            to_os_list: "linux".to_string(),
+            app_id: Some(app_id),
+        }])
+    }
+
+    /// The apps installed in any library folder of this installation, going by their manifests.
+    pub fn list_installed_app_ids(&self) -> Result<HashSet<u64>, SteamUtilError> {
+        let mut app_ids = HashSet::new();
+        for library_folder in self.list_library_folders()? {
+            let Ok(entries) = fs::read_dir(library_folder.path.join("steamapps")) else {
+                continue;
+            };
+            app_ids.extend(entries.filter_map(Result::ok).filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("appmanifest_")?
+                    .strip_suffix(".acf")?
+                    .parse::<u64>()
+                    .ok()
+            }));
+        }
+        Ok(app_ids)
+    }
+
+    /// Lists Valve's tools installed as Steam apps, like Proton and the Steam Linux Runtime.
+    pub fn list_official_compatibility_tools(
+        &self,
+    ) -> Result<Vec<CompatibilityTool>, SteamUtilError> {
+        let mut compat_tools: Vec<CompatibilityTool> = Vec::new();
+        // Only read from appinfo.vdf when a tool doesn't name itself
+        let official_tool_names = OnceCell::new();
+        for library_folder in self.list_library_folders()? {
+            let Ok(entries) = fs::read_dir(library_folder.path.join("steamapps")) else {
+                continue;
+            };
+            for entry in entries
+                .filter_map(Result::ok)
+                .filter(|x| x.path().extension().unwrap_or_default().eq("acf"))
+            {
+                match self.read_official_compatibility_tools(&entry.path(), &official_tool_names) {
+                    Ok(mut official_compat_tools) => {
+                        compat_tools.append(&mut official_compat_tools)
+                    }
+                    Err(err) => error!(
+                        "Error reading app manifest {}: {}",
+                        entry.path().display(),
+                        err
+                    ),
+                }
+            }
+        }
+        Ok(compat_tools)
     }
 
     pub fn list_compatibility_tools(&self) -> Result<Vec<CompatibilityTool>, SteamUtilError> {
@@ -205,10 +628,10 @@ impl SteamUtil {
                 x.metadata().unwrap().is_dir() && x.path().join("compatibilitytool.vdf").exists()
             })
             .flat_map(|x| {
-                self.read_compatibility_tool_from_vdf_path(&x.path().join("compatibilitytool.vdf"))
-                    .map_err(|err| {
+                self.read_compatibility_tools_from_vdf_path(&x.path().join("compatibilitytool.vdf"))
+                    .unwrap_or_else(|err| {
                         error!("Error reading compatibility tool vdf: {}", err);
-                        err
+                        Vec::new()
                     })
             })
             .collect();
@@ -217,7 +640,7 @@ impl SteamUtil {
     }
 
     pub fn get_compatibility_tools_mappings(&self) -> Result<HashMap<u64, String>, SteamUtilError> {
-        let steam_config_file = self.steam_path.join("config").join("config.vdf");
+        let steam_config_file = self.get_steam_config_directory().join("config.vdf");
 
         if !steam_config_file.exists() {
             return Err(SteamUtilError::SteamConfigVdfNotFound);
@@ -287,8 +710,9 @@ impl SteamUtil {
         Ok(compatibility_tools_mappings)
     }
 
-    /// Lists library folders.
-    pub fn list_library_folders(&self) -> Result<Vec<PathBuf>, SteamUtilError> {
+    /// Lists library folders along with their metadata from `libraryfolders.vdf` and the
+    /// filesystem they are on.
+    pub fn list_library_folders(&self) -> Result<Vec<LibraryFolder>, SteamUtilError> {
         let steam_apps_directory = self.steam_path.join("steamapps");
 
         if !steam_apps_directory.exists() {
@@ -302,14 +726,15 @@ impl SteamUtil {
         }
 
         let library_folders_vdf = fs::read_to_string(&library_folders_vdf_file)
-            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))
-            .unwrap();
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
         let vdf = Vdf::parse(&library_folders_vdf)
-            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))
-            .unwrap();
-        let app_state_obj = vdf.value.get_obj().unwrap();
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let app_state_obj = vdf.value.get_obj().ok_or_else(|| {
+            SteamUtilError::VdfMissingEntry("Fail to retrieve libraryfolders object".to_string())
+        })?;
 
-        let mut library_folders: Vec<PathBuf> = Vec::new();
+        let mut library_folders: Vec<LibraryFolder> = Vec::new();
+        let mount_points = read_mount_points(Path::new("/proc/self/mountinfo"));
 
         for value in app_state_obj.values() {
             let key_obj = value.first().and_then(|o| o.get_obj()).ok_or_else(|| {
@@ -323,68 +748,363 @@ impl SteamUtil {
                     SteamUtilError::VdfMissingEntry("Fail to retrieve path".to_string())
                 })?
                 .to_string();
-            if !path.is_empty() {
-                library_folders.push(PathBuf::from(path));
+            if path.is_empty() {
+                continue;
             }
+            let get_str = |key: &str| {
+                key_obj
+                    .get(key)
+                    .and_then(|o| o.first())
+                    .and_then(|o| o.get_str())
+                    .map(|value| value.to_string())
+            };
+            let apps: HashMap<u64, u64> = key_obj
+                .get("apps")
+                .and_then(|o| o.first())
+                .and_then(|o| o.get_obj())
+                .map(|apps| {
+                    apps.iter()
+                        .filter_map(|(app_id, size)| {
+                            Some((
+                                app_id.parse::<u64>().ok()?,
+                                size.first()?.get_str()?.parse::<u64>().ok()?,
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let path = PathBuf::from(path);
+            let mounted = is_library_mounted(&path, REMOVABLE_MEDIA_ROOTS, &mount_points);
+            let (capacity, free_space) = match filesystem_space(&path) {
+                Some((capacity, free_space)) => (Some(capacity), Some(free_space)),
+                None => (None, None),
+            };
+            library_folders.push(LibraryFolder {
+                label: get_str("label").unwrap_or_default(),
+                content_id: get_str("contentid").filter(|content_id| !content_id.is_empty()),
+                apps,
+                mounted,
+                capacity,
+                free_space,
+                path,
+            });
         }
 
         Ok(library_folders)
     }
 
-    /// Lists the installed games across all library folders.
-    pub fn list_installed_games(&self) -> Result<Vec<SteamApp>, SteamUtilError> {
-        // todo: problem is this function can also return partial results because one library folder might be broken but the others might still work properly
-        let mut apps: Vec<SteamApp> = Vec::new();
-        match self.list_library_folders() {
-            Ok(library_folders) => {
-                for library_folder in library_folders {
-                    let library_folder = library_folder.join("steamapps");
-                    if !library_folder.exists() {
-                        error!(
-                            "Library folder {} does not exist",
-                            library_folder.to_str().unwrap()
-                        );
-                        continue;
-                    }
-                    match &mut self.find_installed_games(library_folder.clone()) {
-                        Ok(steam_apps) => apps.append(steam_apps),
-                        Err(err) => {
-                            error!(
-                                "Failed to find installed games in library folder {}: {}",
-                                &library_folder.to_str().unwrap(),
-                                err
-                            );
-                            return Err(err.clone());
-                        }
-                    }
-                }
+    /// Lists the installed games of every library folder separately, so a broken or missing
+    /// library doesn't hide the games of the others.
+    pub fn list_installed_games_by_library(&self) -> Result<Vec<LibraryGames>, SteamUtilError> {
+        let library_folders = self.list_library_folders().map_err(|err| {
+            error!("Failed to list library folders: {}", err);
+            err
+        })?;
+
+        let mut libraries: Vec<LibraryGames> = Vec::new();
+        for library_folder in library_folders {
+            let steam_apps_directory = library_folder.path.join("steamapps");
+            if !steam_apps_directory.exists() {
+                let status =
+                    if !library_folder.mounted && is_on_removable_media(&library_folder.path) {
+                        LibraryFolderStatus::Unmounted
+                    } else {
+                        LibraryFolderStatus::Missing
+                    };
+                error!(
+                    "Library folder {} does not exist ({:?})",
+                    steam_apps_directory.display(),
+                    status
+                );
+                libraries.push(LibraryGames {
+                    library_folder,
+                    status,
+                    error: None,
+                    games: Vec::new(),
+                });
+                continue;
             }
-            Err(err) => {
-                error!("Failed to list library folders: {}", err);
-                return Err(err);
+
+            let (status, error, games) = match self.find_installed_games(steam_apps_directory) {
+                Ok((games, errors)) => match errors.into_iter().next() {
+                    Some(err) => (
+                        LibraryFolderStatus::ParseError,
+                        Some(err.to_string()),
+                        games,
+                    ),
+                    None => (LibraryFolderStatus::Ok, None, games),
+                },
+                Err(err) => {
+                    error!(
+                        "Failed to find installed games in library folder {}: {}",
+                        library_folder.path.display(),
+                        err
+                    );
+                    (
+                        LibraryFolderStatus::ParseError,
+                        Some(err.to_string()),
+                        Vec::new(),
+                    )
+                }
+            };
+            libraries.push(LibraryGames {
+                library_folder,
+                status,
+                error,
+                games,
+            });
+        }
+        Ok(libraries)
+    }
+
+    /// Lists the installed games across all healthy library folders, along with the non-Steam
+    /// shortcuts of `user` if given.
+    pub fn list_installed_games(
+        &self,
+        user: Option<&SteamUser>,
+    ) -> Result<Vec<SteamApp>, SteamUtilError> {
+        let mut apps: Vec<SteamApp> = self
+            .list_installed_games_by_library()?
+            .into_iter()
+            .flat_map(|library| library.games)
+            .collect();
+        // Non-Steam games show up in CompatToolMapping just like regular ones
+        if let Some(user) = user {
+            match self.list_shortcuts(user) {
+                Ok(mut shortcuts) => apps.append(&mut shortcuts),
+                Err(err) => warn!("Failed to list non-Steam shortcuts: {}", err),
             }
         }
         Ok(apps)
     }
 
+    /// Lists the accounts that logged in on this installation.
+    pub fn list_users(&self) -> Result<Vec<SteamUser>, SteamUtilError> {
+        let login_users_file = self.get_steam_config_directory().join("loginusers.vdf");
+        if !login_users_file.exists() {
+            return Ok(Vec::new());
+        }
+
+        let login_users = fs::read_to_string(&login_users_file)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let vdf = Vdf::parse(&login_users)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let users_obj = vdf
+            .value
+            .get_obj()
+            .ok_or_else(|| SteamUtilError::VdfMissingEntry("users".to_string()))?;
+
+        let mut users: Vec<SteamUser> = Vec::new();
+        for (steam_id, value) in users_obj.iter() {
+            let Some(account_id) = steam_id
+                .parse::<u64>()
+                .ok()
+                .and_then(|steam_id| steam_id.checked_sub(STEAM_ID_INDIVIDUAL_BASE))
+            else {
+                warn!("Ignoring invalid SteamID {} in loginusers.vdf", steam_id);
+                continue;
+            };
+            let Some(user_obj) = value.first().and_then(|o| o.get_obj()) else {
+                continue;
+            };
+            let get_str = |key: &str| {
+                user_obj
+                    .get(key)
+                    .and_then(|o| o.first())
+                    .and_then(|o| o.get_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            users.push(SteamUser {
+                steam_id: steam_id.to_string(),
+                account_id,
+                account_name: get_str("AccountName"),
+                persona_name: get_str("PersonaName"),
+                most_recent: get_str("MostRecent") == "1",
+                timestamp: get_str("Timestamp").parse().unwrap_or_default(),
+            });
+        }
+        Ok(users)
+    }
+
+    /// The account Steam logged in with last, or the one with the latest login if none is
+    /// marked as most recent.
+    pub fn get_active_user(&self) -> Result<SteamUser, SteamUtilError> {
+        let users = self.list_users()?;
+        users
+            .iter()
+            .find(|user| user.most_recent)
+            .or_else(|| users.iter().max_by_key(|user| user.timestamp))
+            .cloned()
+            .ok_or(SteamUtilError::SteamUserNotFound)
+    }
+
+    /// The `userdata/<accountid>/config` directory holding a user's Steam configuration.
+    pub fn get_user_config_directory(&self, user: &SteamUser) -> PathBuf {
+        self.steam_path
+            .join("userdata")
+            .join(user.account_id.to_string())
+            .join("config")
+    }
+
+    fn get_local_config_file(&self, user: &SteamUser) -> PathBuf {
+        self.get_user_config_directory(user).join("localconfig.vdf")
+    }
+
+    /// Reads the launch options a user set for a game, `None` if there are none.
+    pub fn get_launch_options(
+        &self,
+        user: &SteamUser,
+        app_id: u64,
+    ) -> Result<Option<String>, SteamUtilError> {
+        Ok(self.list_launch_options(user)?.remove(&app_id))
+    }
+
+    /// Reads the launch options a user set for all of their games, games without any are left out.
+    pub fn list_launch_options(
+        &self,
+        user: &SteamUser,
+    ) -> Result<HashMap<u64, String>, SteamUtilError> {
+        let local_config_file = self.get_local_config_file(user);
+        if !local_config_file.exists() {
+            return Err(SteamUtilError::LocalConfigVdfNotFound);
+        }
+        let local_config = fs::read_to_string(&local_config_file)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let local_config_vdf = Vdf::parse(&local_config)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+
+        let Some(apps_obj) = local_config_vdf
+            .value
+            .get_obj()
+            .and_then(|obj| get_obj_ignore_case(obj, "Software"))
+            .and_then(|obj| get_obj_ignore_case(obj, "Valve"))
+            .and_then(|obj| get_obj_ignore_case(obj, "Steam"))
+            .and_then(|obj| get_obj_ignore_case(obj, "apps"))
+        else {
+            return Ok(HashMap::new());
+        };
+        let launch_options = apps_obj
+            .iter()
+            .filter_map(|(app_id, values)| {
+                let launch_options = values
+                    .first()
+                    .and_then(|o| o.get_obj())
+                    .and_then(|obj| obj.get("LaunchOptions"))
+                    .and_then(|o| o.first())
+                    .and_then(|o| o.get_str())
+                    .filter(|launch_options| !launch_options.is_empty())?;
+                Some((app_id.parse().ok()?, launch_options.to_string()))
+            })
+            .collect();
+        Ok(launch_options)
+    }
+
+    /// Sets the launch options of a game in the user's `localconfig.vdf`. Steam overwrites the
+    /// file when it exits, so it shouldn't be running.
+    pub fn set_launch_options(
+        &self,
+        user: &SteamUser,
+        app_id: u64,
+        launch_options: &str,
+    ) -> Result<(), SteamUtilError> {
+        if let Some(pid) = self.get_running_steam_pid() {
+            return Err(SteamUtilError::SteamRunning(pid));
+        }
+        let local_config_file = self.get_local_config_file(user);
+        if !local_config_file.exists() {
+            return Err(SteamUtilError::LocalConfigVdfNotFound);
+        }
+        let local_config = fs::read_to_string(&local_config_file)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let mut local_config_vdf = Vdf::parse(&local_config)
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+
+        let root_obj = local_config_vdf.value.get_mut_obj().ok_or_else(|| {
+            SteamUtilError::VdfMissingEntry("UserLocalConfigStore object not found".to_string())
+        })?;
+        let software_obj = get_or_insert_obj(root_obj, "Software");
+        let valve_obj = get_or_insert_obj(software_obj, "Valve");
+        let steam_obj = get_or_insert_obj(valve_obj, "Steam");
+        let apps_obj = get_or_insert_obj(steam_obj, "apps");
+        let app_obj = get_or_insert_obj(apps_obj, &app_id.to_string());
+        app_obj.insert(
+            Cow::from("LaunchOptions"),
+            vec![Value::Str(Cow::from(launch_options.to_string()))],
+        );
+
+        write_steam_config_file(&local_config_file, &local_config_vdf.to_string())
+    }
+
+    /// Lists the non-Steam shortcuts of a user.
+    pub fn list_shortcuts(&self, user: &SteamUser) -> Result<Vec<SteamApp>, SteamUtilError> {
+        let shortcuts_vdf = self.get_user_config_directory(user).join("shortcuts.vdf");
+        if !shortcuts_vdf.exists() {
+            return Ok(Vec::new());
+        }
+        Self::read_shortcuts(&shortcuts_vdf)
+    }
+
+    /// Reads the binary `shortcuts.vdf` of a user.
+    pub fn read_shortcuts(path: &Path) -> Result<Vec<SteamApp>, SteamUtilError> {
+        let data =
+            fs::read(path).map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let vdf = parse_binary_vdf(&data)?;
+        let shortcuts = vdf
+            .get_map("shortcuts")
+            .ok_or_else(|| SteamUtilError::VdfMissingEntry("shortcuts".to_string()))?;
+
+        let mut apps: Vec<SteamApp> = Vec::new();
+        for (index, value) in &shortcuts.0 {
+            let BinaryVdfValue::Map(shortcut) = value else {
+                continue;
+            };
+            // One broken shortcut shouldn't hide all the others
+            let Some(name) = shortcut.get_str("AppName") else {
+                warn!(
+                    "Skipping shortcut {} in {}: no AppName",
+                    index,
+                    path.display()
+                );
+                continue;
+            };
+            // Older Steam versions didn't store the app id
+            let app_id = match shortcut.get_i32("appid") {
+                Some(app_id) => app_id as u32,
+                None => compute_shortcut_app_id(shortcut.get_str("Exe").unwrap_or_default(), name),
+            };
+            apps.push(SteamApp {
+                app_id: app_id as u64,
+                name: name.to_string(),
+            });
+        }
+        Ok(apps)
+    }
+
+    /// Reads every app manifest of a `steamapps` directory, returning the games that could be
+    /// read along with the errors of the manifests that couldn't.
     pub fn find_installed_games(
         &self,
         steam_apps_directory: PathBuf,
-    ) -> Result<Vec<SteamApp>, SteamUtilError> {
-        let apps: Vec<SteamApp> = fs::read_dir(steam_apps_directory)
-            .map_err(|_err| SteamUtilError::SteamAppsDirectoryNotFound)
-            .unwrap()
+    ) -> Result<(Vec<SteamApp>, Vec<SteamUtilError>), SteamUtilError> {
+        let mut apps: Vec<SteamApp> = Vec::new();
+        let mut errors: Vec<SteamUtilError> = Vec::new();
+        for file in fs::read_dir(steam_apps_directory)
+            .map_err(|_err| SteamUtilError::SteamAppsDirectoryNotFound)?
             .filter_map(Result::ok)
             .filter(|x| x.path().extension().unwrap_or_default().eq("acf"))
-            .flat_map(|file| {
-                Self::read_app_manifest_to_steam_app(file.path()).map_err(|err| {
+        {
+            match Self::read_app_manifest_to_steam_app(file.path()) {
+                Ok(app) => apps.push(app),
+                Err(err) => {
                     error!("Error reading app manifest: {}", err);
-                    err
-                })
-            })
-            .collect();
+                    errors.push(err);
+                }
+            }
+        }
 
-        Ok(apps)
+        Ok((apps, errors))
     }
 
     pub fn read_app_manifest_to_steam_app(path_buf: PathBuf) -> Result<SteamApp, SteamUtilError> {
@@ -410,6 +1130,379 @@ impl SteamUtil {
             .ok_or_else(|| SteamUtilError::VdfMissingEntry("name".to_string()))?;
         Ok(SteamApp { app_id, name })
     }
+
+    pub fn get_app_info_file(&self) -> PathBuf {
+        self.steam_path.join("appcache").join("appinfo.vdf")
+    }
+
+    /// Reads the metadata of the given apps from Steam's binary app info cache.
+    pub fn read_app_infos(&self, app_ids: &[u64]) -> Result<HashMap<u64, AppInfo>, SteamUtilError> {
+        let data = fs::read(self.get_app_info_file())
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let mut requested_app_ids = app_ids.to_vec();
+        requested_app_ids.push(STEAM_PLAY_MANIFESTS_APP_ID);
+        let apps = parse_app_info(&data, &requested_app_ids)?;
+
+        let app_mappings: HashMap<u64, String> = apps
+            .get(&STEAM_PLAY_MANIFESTS_APP_ID)
+            .and_then(|app| app.get_map("appinfo"))
+            .and_then(|app| app.get_map("extended"))
+            .and_then(|extended| extended.get_map("app_mappings"))
+            .map(|app_mappings| {
+                app_mappings
+                    .values()
+                    .filter_map(|value| match value {
+                        BinaryVdfValue::Map(mapping) => Some((
+                            mapping.get_number("appid")? as u64,
+                            mapping.get_str("tool")?.to_string(),
+                        )),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(app_ids
+            .iter()
+            .filter_map(|app_id| {
+                let app = apps.get(app_id)?.get_map("appinfo")?;
+                Some((*app_id, Self::read_app_info(app, app_mappings.get(app_id))))
+            })
+            .collect())
+    }
+
+    /// The names Steam maps games to for its official tools by app id, from the `compat_tools`
+    /// Valve publishes in the Steam Play manifests app.
+    pub fn read_official_tool_names(&self) -> Result<HashMap<u64, String>, SteamUtilError> {
+        let data = fs::read(self.get_app_info_file())
+            .map_err(|err| SteamUtilError::VdfParsingError(err.to_string()))?;
+        let apps = parse_app_info(&data, &[STEAM_PLAY_MANIFESTS_APP_ID])?;
+        let mut official_tool_names: HashMap<u64, String> = HashMap::new();
+        let compat_tools = apps
+            .get(&STEAM_PLAY_MANIFESTS_APP_ID)
+            .and_then(|app| app.get_map("appinfo"))
+            .and_then(|app| app.get_map("extended"))
+            .and_then(|extended| extended.get_map("compat_tools"));
+        for (internal_name, value) in compat_tools.iter().flat_map(|compat_tools| &compat_tools.0) {
+            let BinaryVdfValue::Map(compat_tool) = value else {
+                continue;
+            };
+            if let Some(app_id) = compat_tool.get_number("appid") {
+                official_tool_names
+                    .entry(app_id as u64)
+                    .or_insert_with(|| internal_name.clone());
+            }
+        }
+        Ok(official_tool_names)
+    }
+
+    fn read_app_info(app: &BinaryVdfMap, recommended_compat_tool: Option<&String>) -> AppInfo {
+        let common = app.get_map("common");
+        let os_list = common
+            .and_then(|common| common.get_str("oslist"))
+            .map(|os_list| {
+                os_list
+                    .split(',')
+                    .map(|os| os.trim().to_string())
+                    .filter(|os| !os.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let steam_deck_compatibility = match common
+            .and_then(|common| common.get_map("steam_deck_compatibility"))
+            .and_then(|compatibility| compatibility.get_number("category"))
+        {
+            Some(1) => SteamDeckCompatibility::Unsupported,
+            Some(2) => SteamDeckCompatibility::Playable,
+            Some(3) => SteamDeckCompatibility::Verified,
+            _ => SteamDeckCompatibility::Unknown,
+        };
+        AppInfo {
+            os_list,
+            steam_deck_compatibility,
+            recommended_compat_tool: recommended_compat_tool.cloned(),
+            install_dir: app
+                .get_map("config")
+                .and_then(|config| config.get_str("installdir"))
+                .map(|install_dir| install_dir.to_string()),
+        }
+    }
+
+    /// The compatdata directory of the main library, where Steam creates new prefixes.
+    pub fn get_compat_data_directory(&self) -> PathBuf {
+        self.steam_path.join("steamapps").join("compatdata")
+    }
+
+    /// Lists the compatdata prefixes across all library folders.
+    pub fn list_compat_data_prefixes(&self) -> Result<Vec<CompatDataPrefix>, SteamUtilError> {
+        let mut prefixes: Vec<CompatDataPrefix> = Vec::new();
+        for library_folder in self.list_library_folders()? {
+            let compat_data_directory = library_folder.path.join("steamapps").join("compatdata");
+            let Ok(entries) = fs::read_dir(&compat_data_directory) else {
+                continue;
+            };
+            prefixes.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|x| x.metadata().map(|m| m.is_dir()).unwrap_or(false))
+                    .filter_map(|x| Self::read_compat_data_prefix(x.path())),
+            );
+        }
+        Ok(prefixes)
+    }
+
+    /// Finds the compatdata prefix of an app in any library folder.
+    pub fn find_compat_data_prefix(&self, app_id: u64) -> Result<CompatDataPrefix, SteamUtilError> {
+        self.list_compat_data_prefixes()?
+            .into_iter()
+            .find(|prefix| prefix.app_id == app_id)
+            .ok_or(SteamUtilError::CompatDataPrefixNotFound(app_id))
+    }
+
+    /// Reads a `compatdata/<appid>` directory, anything not named after an app id is ignored.
+    pub fn read_compat_data_prefix(path: PathBuf) -> Option<CompatDataPrefix> {
+        let app_id = path.file_name()?.to_str()?.parse::<u64>().ok()?;
+        let prefix_version = fs::read_to_string(path.join("version"))
+            .ok()
+            .and_then(|version| parse_proton_version(&version));
+        let tool_version = fs::read_to_string(path.join("config_info"))
+            .ok()
+            .and_then(|config_info| parse_proton_version(&config_info));
+        Some(CompatDataPrefix {
+            app_id,
+            path,
+            prefix_version,
+            tool_version,
+        })
+    }
+
+    /// Checks whether Steam is currently running the given app, by looking for the
+    /// `AppId=<appid>` argument Steam passes to the reaper process of every launched game.
+    pub fn is_app_running(app_id: u64) -> bool {
+        let app_id_argument = format!("AppId={}", app_id);
+        let Ok(processes) = fs::read_dir("/proc") else {
+            return false;
+        };
+        processes
+            .filter_map(Result::ok)
+            .filter(|x| x.file_name().to_string_lossy().parse::<u32>().is_ok())
+            .filter_map(|x| fs::read(x.path().join("cmdline")).ok())
+            .any(|cmdline| {
+                cmdline
+                    .split(|byte| *byte == 0)
+                    .any(|argument| argument == app_id_argument.as_bytes())
+            })
+    }
+}
+
+/// Looks up an object ignoring the case of its key, Steam isn't consistent about it.
+fn get_obj_ignore_case<'b>(obj: &'b Obj<'_>, key: &str) -> Option<&'b Obj<'b>> {
+    obj.iter()
+        .find(|(entry_key, _)| entry_key.eq_ignore_ascii_case(key))
+        .and_then(|(_, values)| values.first())
+        .and_then(|value| value.get_obj())
+}
+
+/// Gets the object under `key` ignoring its case, creating it if it's missing.
+fn get_or_insert_obj<'a, 'b>(obj: &'b mut Obj<'a>, key: &str) -> &'b mut Obj<'a> {
+    let existing_key = obj
+        .keys()
+        .find(|entry_key| entry_key.eq_ignore_ascii_case(key))
+        .cloned()
+        .unwrap_or_else(|| Cow::from(key.to_string()));
+    let values = obj.entry(existing_key).or_default();
+    if !values.first().is_some_and(|value| value.is_obj()) {
+        values.insert(0, Value::Obj(Obj::new()));
+    }
+    values[0].get_mut_obj().unwrap()
+}
+
+/// Writes a Steam config file, keeping a copy of the version from before our first change next
+/// to it as `<file>.wine-cellar-backup`. The new contents are written to a temporary file first
+/// and renamed over the original, so Steam never sees a partially written file.
+pub fn write_steam_config_file(path: &Path, contents: &str) -> Result<(), SteamUtilError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| SteamUtilError::ConfigWriteFailed(path.display().to_string()))?
+        .to_string_lossy()
+        .to_string();
+    let write_error =
+        |err: std::io::Error| SteamUtilError::ConfigWriteFailed(format!("{}: {}", file_name, err));
+
+    // Later writes keep the backup, it's the only copy of the user's original config
+    let backup_file = path.with_file_name(format!("{}.wine-cellar-backup", file_name));
+    if path.exists() && !backup_file.exists() {
+        fs::copy(path, &backup_file).map_err(write_error)?;
+    }
+    let temporary_file = path.with_file_name(format!("{}.wine-cellar-tmp", file_name));
+    let mut file = fs::File::create(&temporary_file).map_err(write_error)?;
+    file.write_all(contents.as_bytes()).map_err(write_error)?;
+    file.sync_all().map_err(write_error)?;
+    fs::rename(&temporary_file, path).map_err(write_error)?;
+    info!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Resolves `.` and `..` components without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Guesses the name Steam maps games to for an official tool missing from `appinfo.vdf`, e.g.
+/// `proton_9` for "Proton 9.0", `proton_513` for "Proton 5.13" and `proton_experimental`.
+fn get_official_tool_internal_name(name: &str, install_dir: &str) -> String {
+    // Branches are only mentioned in the name, e.g. "Proton 9.0 (Beta)"
+    let name = name.split('(').next().unwrap_or(name).trim();
+    if let Some(version) = name.strip_prefix("Proton ") {
+        let version = version.trim_start_matches(['-', ' ']).to_lowercase();
+        return match version.split_once('.') {
+            Some((major, "0")) => format!("proton_{}", major),
+            Some((major, minor)) => format!("proton_{}{}", major, minor),
+            None => format!("proton_{}", version.replace(' ', "_")),
+        };
+    }
+    // The runtimes are mapped by their install directory, e.g. `steamlinuxruntime_sniper`
+    install_dir.to_lowercase().replace(' ', "_")
+}
+
+/// Reads a pid file and checks in `/proc` that the process is still Steam, the file is left
+/// behind when Steam crashes and the pid may have been reused since.
+fn get_running_pid(pid_file: &Path, proc_directory: &Path) -> Option<u32> {
+    let pid: u32 = fs::read_to_string(pid_file).ok()?.trim().parse().ok()?;
+    let command_name =
+        fs::read_to_string(proc_directory.join(pid.to_string()).join("comm")).ok()?;
+    command_name.trim().starts_with("steam").then_some(pid)
+}
+
+/// Looks for a Steam client running from `steam_path` when there's no pid file pointing at it.
+fn find_steam_process(steam_path: &Path, proc_directory: &Path) -> Option<u32> {
+    let steam_path = fs::canonicalize(steam_path).unwrap_or_else(|_| steam_path.to_path_buf());
+    fs::read_dir(proc_directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .find_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let command_name = fs::read_to_string(entry.path().join("comm")).ok()?;
+            if command_name.trim() != "steam" {
+                return None;
+            }
+            ["exe", "cwd"]
+                .iter()
+                .filter_map(|link| fs::read_link(entry.path().join(link)).ok())
+                .any(|target| target.starts_with(&steam_path))
+                .then_some(pid)
+        })
+}
+
+/// Capacity and free space of the filesystem a path is on.
+fn filesystem_space(path: &Path) -> Option<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let fragment_size = stat.f_frsize as u64;
+    Some((
+        stat.f_blocks as u64 * fragment_size,
+        stat.f_bavail as u64 * fragment_size,
+    ))
+}
+
+/// Where removable drives like SD cards are mounted.
+const REMOVABLE_MEDIA_ROOTS: &[&str] = &["/run/media", "/media", "/mnt"];
+
+/// Whether a path lives where removable drives like SD cards are mounted, a missing library
+/// there most likely belongs to a drive that isn't inserted.
+fn is_on_removable_media(path: &Path) -> bool {
+    REMOVABLE_MEDIA_ROOTS
+        .iter()
+        .any(|mount_root| path.starts_with(mount_root))
+}
+
+/// Reads the mount points from a `mountinfo` file, spaces and other special characters in
+/// them are escaped as octal.
+fn read_mount_points(mountinfo: &Path) -> Vec<PathBuf> {
+    let Ok(mountinfo) = fs::read_to_string(mountinfo) else {
+        return Vec::new();
+    };
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|mount_point| {
+            let mut unescaped = Vec::new();
+            let bytes = mount_point.as_bytes();
+            let mut index = 0;
+            while index < bytes.len() {
+                let octal = bytes
+                    .get(index + 1..index + 4)
+                    .and_then(|octal| std::str::from_utf8(octal).ok())
+                    .and_then(|octal| u8::from_str_radix(octal, 8).ok());
+                match octal {
+                    Some(byte) if bytes[index] == b'\\' => {
+                        unescaped.push(byte);
+                        index += 4;
+                    }
+                    _ => {
+                        unescaped.push(bytes[index]);
+                        index += 1;
+                    }
+                }
+            }
+            PathBuf::from(std::ffi::OsStr::from_bytes(&unescaped))
+        })
+        .collect()
+}
+
+/// Whether a library folder can be used. Unmounting a drive leaves its empty mount point behind,
+/// so below a removable media root there has to be a mount between the root and the library,
+/// unless the library is a plain directory on the root filesystem, e.g. `/mnt/games`.
+fn is_library_mounted(
+    path: &Path,
+    removable_media_roots: &[&str],
+    mount_points: &[PathBuf],
+) -> bool {
+    if !path.is_dir() {
+        return false;
+    }
+    match removable_media_roots
+        .iter()
+        .map(Path::new)
+        .find(|mount_root| path.starts_with(mount_root))
+    {
+        Some(mount_root) => {
+            path.join("steamapps").is_dir()
+                || mount_points.iter().any(|mount_point| {
+                    mount_point.starts_with(mount_root) && path.starts_with(mount_point)
+                })
+        }
+        None => true,
+    }
+}
+
+/// The app id Steam generates for a non-Steam shortcut, as used in `CompatToolMapping`.
+pub fn compute_shortcut_app_id(exe: &str, app_name: &str) -> u32 {
+    crc32fast::hash(format!("{}{}", exe, app_name).as_bytes()) | 0x8000_0000
+}
+
+/// Proton version files contain either just the version or a build timestamp followed by the
+/// version, e.g. `1712345678 GE-Proton9-1`, only the first line is relevant.
+pub fn parse_proton_version(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .next()?
+        .split_whitespace()
+        .last()
+        .map(|version| version.to_string())
 }
 
 impl Display for SteamUtilError {
@@ -432,6 +1525,23 @@ impl Display for SteamUtilError {
             SteamUtilError::SteamConfigVdfNotFound => write!(f, "Steam config file not found"),
             SteamUtilError::VdfParsingError(msg) => write!(f, "Failed to parse VDF file: {}", msg),
             SteamUtilError::VdfMissingEntry(msg) => write!(f, "Missing VDF entry: {}", msg),
+            SteamUtilError::CompatDataPrefixNotFound(app_id) => {
+                write!(f, "Compatdata prefix not found for app {}", app_id)
+            }
+            SteamUtilError::SteamUserNotFound => write!(f, "No Steam user has logged in"),
+            SteamUtilError::LocalConfigVdfNotFound => {
+                write!(f, "Steam local config file not found")
+            }
+            SteamUtilError::ConfigWriteFailed(msg) => {
+                write!(f, "Failed to write Steam config file: {}", msg)
+            }
+            SteamUtilError::SteamRunning(pid) => {
+                write!(
+                    f,
+                    "Steam is running (pid {}), close it first or it will overwrite the change",
+                    pid
+                )
+            }
         }
     }
 }
@@ -445,6 +1555,7 @@ impl Error for SteamUtilError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::binary_vdf::{write_app_info, write_binary_vdf, BinaryVdfMap};
     use std::fs;
     use tempfile::{tempdir, TempDir};
 
@@ -591,9 +1702,123 @@ mod tests {
         )
         .expect("Failed to write app manifest file");
 
+        // Create compatdata prefix
+        let compat_data_dir = steamapps_dir.join("compatdata").join("730");
+        fs::create_dir_all(compat_data_dir.join("pfx"))
+            .expect("Failed to create compatdata directory");
+        fs::write(compat_data_dir.join("version"), "GE-Proton9-20\n")
+            .expect("Failed to write prefix version file");
+        fs::write(
+            compat_data_dir.join("config_info"),
+            "1717000000 GE-Proton9-20\n/fonts/\n",
+        )
+        .expect("Failed to write prefix config info file");
+
+        // Create logged in users
+        fs::write(
+            config_dir.join("loginusers.vdf"),
+            r#""users"
+            {
+                "76561198047920049"
+                {
+                    "AccountName"		"other"
+                    "PersonaName"		"Other"
+                    "MostRecent"		"0"
+                    "Timestamp"		"1700000500"
+                }
+                "76561197972611406"
+                {
+                    "AccountName"		"deck"
+                    "PersonaName"		"Steam Deck"
+                    "MostRecent"		"1"
+                    "Timestamp"		"1700000000"
+                }
+            }
+            "#,
+        )
+        .expect("Failed to write login users file");
+
+        // Create non-Steam shortcut
+        let user_config_dir = root_dir.join("userdata").join("12345678").join("config");
+        fs::create_dir_all(&user_config_dir).expect("Failed to create user config directory");
+        let shortcuts = BinaryVdfMap(vec![(
+            "shortcuts".to_string(),
+            BinaryVdfValue::Map(BinaryVdfMap(vec![(
+                "0".to_string(),
+                BinaryVdfValue::Map(BinaryVdfMap(vec![
+                    (
+                        "AppName".to_string(),
+                        BinaryVdfValue::String("RetroArch".to_string()),
+                    ),
+                    (
+                        "Exe".to_string(),
+                        BinaryVdfValue::String("\"/usr/bin/retroarch\"".to_string()),
+                    ),
+                ])),
+            )])),
+        )]);
+        let mut shortcuts_vdf = Vec::new();
+        write_binary_vdf(&shortcuts, &mut shortcuts_vdf, None);
+        fs::write(user_config_dir.join("shortcuts.vdf"), shortcuts_vdf)
+            .expect("Failed to write shortcuts file");
+
         steam_dir
     }
 
+    #[test]
+    fn test_find_steam_installations() {
+        let home_dir = tempdir().expect("Failed to create temporary directory");
+        for steam_root in [
+            ".local/share/Steam",
+            ".var/app/com.valvesoftware.Steam/data/Steam",
+        ] {
+            let config_dir = home_dir.path().join(steam_root).join("config");
+            fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+            fs::write(config_dir.join("config.vdf"), "").expect("Failed to write config");
+            fs::write(config_dir.join("libraryfolders.vdf"), "")
+                .expect("Failed to write library folders");
+        }
+        fs::create_dir_all(home_dir.path().join(".steam")).expect("Failed to create .steam");
+        std::os::unix::fs::symlink(
+            home_dir.path().join(".local/share/Steam"),
+            home_dir.path().join(".steam/root"),
+        )
+        .expect("Failed to create symlink");
+
+        let result = SteamUtil::find_steam_installations(Some(
+            home_dir.path().to_string_lossy().to_string(),
+        ));
+        assert!(result.is_ok());
+        let steam_installations = result.unwrap();
+        assert_eq!(steam_installations.len(), 2);
+        assert_eq!(steam_installations[0].id, ".local/share/Steam");
+        assert!(!steam_installations[0].flatpak);
+        assert!(steam_installations[1].flatpak);
+    }
+
+    #[test]
+    fn test_find_steam_installations_before_first_login() {
+        let home_dir = tempdir().expect("Failed to create temporary directory");
+        let home_directory = home_dir.path().to_string_lossy().to_string();
+        assert!(matches!(
+            SteamUtil::find_steam_installations(Some(home_directory.clone())),
+            Err(SteamUtilError::SteamDirectoryNotFound)
+        ));
+
+        // Steam writes libraryfolders.vdf only after the first login
+        let config_dir = home_dir.path().join(".local/share/Steam/config");
+        fs::create_dir_all(&config_dir).expect("Failed to create config directory");
+        fs::write(config_dir.join("config.vdf"), "").expect("Failed to write config");
+        assert!(matches!(
+            SteamUtil::find_steam_installations(Some(home_directory.clone())),
+            Err(SteamUtilError::LibraryFoldersVdfNotFound)
+        ));
+
+        fs::write(config_dir.join("libraryfolders.vdf"), "")
+            .expect("Failed to write library folders");
+        assert!(SteamUtil::find_steam_installations(Some(home_directory)).is_ok());
+    }
+
     #[test]
     fn test_list_compatibility_tools() {
         // Create emulated Steam directory for the test
@@ -604,8 +1829,256 @@ mod tests {
         assert!(result.is_ok());
         let compat_tools = result.unwrap();
         assert_eq!(compat_tools.len(), 2);
-        assert_eq!(compat_tools[0].display_name, "Sample Compatibility Tool 2");
-        assert_eq!(compat_tools[1].display_name, "Sample Compatibility Tool 1");
+        // Tools are listed in read_dir order, which depends on the filesystem
+        let display_names: Vec<&str> = compat_tools
+            .iter()
+            .map(|tool| tool.display_name.as_str())
+            .collect();
+        assert!(display_names.contains(&"Sample Compatibility Tool 1"));
+        assert!(display_names.contains(&"Sample Compatibility Tool 2"));
+    }
+
+    #[test]
+    fn test_read_compatibility_tools_from_vdf_path() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+        let tool_dir = steam_util
+            .get_steam_compatibility_tools_directory()
+            .join("luxtorpeda");
+        fs::create_dir_all(&tool_dir).unwrap();
+        let compat_tool_vdf = tool_dir.join("compatibilitytool.vdf");
+        fs::write(
+            &compat_tool_vdf,
+            r#""compatibilitytools"
+            {
+              "compat_tools"
+              {
+                "luxtorpeda"
+                {
+                  "install_path" "."
+                  "display_name" "Luxtorpeda"
+                  "from_oslist"  "windows"
+                  "to_oslist"    "linux"
+                }
+                "luxtorpeda_dev"
+                {
+                  "install_path" "./dev"
+                  "display_name" "Luxtorpeda (dev)"
+                  "from_oslist"  "windows"
+                  "to_oslist"    "linux"
+                }
+              }
+            }"#,
+        )
+        .unwrap();
+
+        let compat_tools = steam_util
+            .read_compatibility_tools_from_vdf_path(&compat_tool_vdf)
+            .expect("Failed to read compatibility tools");
+        let tools: Vec<(&str, &Path)> = compat_tools
+            .iter()
+            .map(|tool| (tool.internal_name.as_str(), tool.install_path.as_path()))
+            .collect();
+        assert_eq!(
+            tools,
+            vec![
+                ("luxtorpeda", tool_dir.as_path()),
+                ("luxtorpeda_dev", tool_dir.join("dev").as_path()),
+            ]
+        );
+        assert!(compat_tools.iter().all(|tool| tool.path == tool_dir));
+
+        steam_util
+            .remove_compatibility_tool_from_vdf(&compat_tool_vdf, "luxtorpeda_dev")
+            .expect("Failed to remove compatibility tool");
+        let compat_tools = steam_util
+            .read_compatibility_tools_from_vdf_path(&compat_tool_vdf)
+            .expect("Failed to read compatibility tools");
+        assert_eq!(compat_tools.len(), 1);
+        assert_eq!(compat_tools[0].internal_name, "luxtorpeda");
+        assert_eq!(compat_tools[0].display_name, "Luxtorpeda");
+    }
+
+    #[test]
+    fn test_set_compatibility_tool_display_name() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+        let compat_tool_vdf = steam_util
+            .get_steam_compatibility_tools_directory()
+            .join("compat_tool_1/compatibilitytool.vdf");
+        let read_tool = || {
+            steam_util
+                .read_compatibility_tools_from_vdf_path(&compat_tool_vdf)
+                .expect("Failed to read compatibility tools")
+                .remove(0)
+        };
+
+        for display_name in ["Stable for Elden Ring", "Stable for \"Elden Ring\""] {
+            steam_util
+                .set_compatibility_tool_display_name(
+                    &compat_tool_vdf,
+                    "Sample-Compatibility-Tool-1",
+                    Some(display_name),
+                )
+                .expect("Failed to rename compatibility tool");
+            let compat_tool = read_tool();
+            assert_eq!(compat_tool.internal_name, "Sample-Compatibility-Tool-1");
+            assert_eq!(compat_tool.display_name, display_name);
+            assert_eq!(
+                compat_tool.original_display_name.as_deref(),
+                Some("Sample Compatibility Tool 1")
+            );
+        }
+
+        steam_util
+            .set_compatibility_tool_display_name(
+                &compat_tool_vdf,
+                "Sample-Compatibility-Tool-1",
+                None,
+            )
+            .expect("Failed to restore compatibility tool name");
+        let compat_tool = read_tool();
+        assert_eq!(compat_tool.display_name, "Sample Compatibility Tool 1");
+        assert_eq!(compat_tool.original_display_name, None);
+    }
+
+    #[test]
+    fn test_list_official_compatibility_tools() {
+        let steam_dir = create_test_steam_directory();
+        let root_dir = steam_dir.path().join("root");
+        let steamapps_dir = root_dir.join("steamapps");
+        for (app_id, name, install_dir, tool_manifest) in [
+            (
+                "2805730",
+                "Proton 9.0",
+                "Proton 9.0",
+                r#""manifest" { "commandline" "/proton %verb%" "require_tool_appid" "1628350" "compatmanager_layer_name" "proton" }"#,
+            ),
+            (
+                "1493710",
+                "Proton Experimental",
+                "Proton - Experimental",
+                r#""manifest" { "commandline" "/proton %verb%" "require_tool_appid" "1628350" "compatmanager_layer_name" "proton" }"#,
+            ),
+            (
+                "1628350",
+                "Steam Linux Runtime 3.0 (sniper)",
+                "SteamLinuxRuntime_sniper",
+                r#""manifest" { "commandline" "/_v2-entry-point --verb=%verb% --" "compatmanager_layer_name" "container-runtime" }"#,
+            ),
+        ] {
+            fs::write(
+                steamapps_dir.join(format!("appmanifest_{}.acf", app_id)),
+                format!(
+                    r#""AppState" {{ "appid" "{}" "name" "{}" "installdir" "{}" }}"#,
+                    app_id, name, install_dir
+                ),
+            )
+            .unwrap();
+            let tool_dir = steamapps_dir.join("common").join(install_dir);
+            fs::create_dir_all(&tool_dir).unwrap();
+            fs::write(tool_dir.join("toolmanifest.vdf"), tool_manifest).unwrap();
+        }
+        let steam_util = SteamUtil::new(root_dir.clone());
+
+        // Without appinfo.vdf the names are guessed from the app names
+        let mut compat_tools = steam_util
+            .list_official_compatibility_tools()
+            .expect("Failed to list official compatibility tools");
+        compat_tools.sort_by_key(|tool| tool.app_id);
+        let tools: Vec<(Option<u64>, &str, &str)> = compat_tools
+            .iter()
+            .map(|tool| {
+                (
+                    tool.app_id,
+                    tool.internal_name.as_str(),
+                    tool.from_os_list.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tools,
+            vec![
+                (Some(1493710), "proton_experimental", "windows"),
+                (Some(1628350), "steamlinuxruntime_sniper", "linux"),
+                (Some(2805730), "proton_9", "windows"),
+            ]
+        );
+        assert_eq!(
+            steam_util
+                .read_tool_manifest(&steamapps_dir.join("common/Proton 9.0/toolmanifest.vdf"))
+                .unwrap()
+                .require_tool_appid,
+            Some(1628350)
+        );
+        assert_eq!(
+            get_official_tool_internal_name("Proton 5.13", "Proton 5.13"),
+            "proton_513"
+        );
+        assert_eq!(
+            get_official_tool_internal_name("Proton 9.0 (Beta)", "Proton 9.0 (Beta)"),
+            "proton_9"
+        );
+
+        // Steam's own names take precedence
+        let map = |entries: Vec<(&str, BinaryVdfValue)>| {
+            BinaryVdfValue::Map(BinaryVdfMap(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect(),
+            ))
+        };
+        let string = |value: &str| BinaryVdfValue::String(value.to_string());
+        let app_info = write_app_info(&[(
+            STEAM_PLAY_MANIFESTS_APP_ID,
+            BinaryVdfMap(vec![(
+                "appinfo".to_string(),
+                map(vec![(
+                    "extended",
+                    map(vec![(
+                        "compat_tools",
+                        map(vec![
+                            (
+                                "proton_9",
+                                map(vec![
+                                    ("appid", string("2805730")),
+                                    ("display_name", string("Proton 9.0-4")),
+                                ]),
+                            ),
+                            ("proton_hotfix", map(vec![("appid", string("2180100"))])),
+                            (
+                                "SteamLinuxRuntime_sniper",
+                                map(vec![("appid", string("1628350"))]),
+                            ),
+                        ]),
+                    )]),
+                )]),
+            )]),
+        )]);
+        fs::create_dir_all(root_dir.join("appcache")).unwrap();
+        fs::write(root_dir.join("appcache/appinfo.vdf"), app_info).unwrap();
+        fs::write(
+            steamapps_dir.join("appmanifest_2805730.acf"),
+            r#""AppState" { "appid" "2805730" "name" "Proton 9.0 (Beta)" "installdir" "Proton 9.0" }"#,
+        )
+        .unwrap();
+        let compat_tools = steam_util
+            .list_official_compatibility_tools()
+            .expect("Failed to list official compatibility tools");
+        let proton_9 = compat_tools
+            .iter()
+            .find(|tool| tool.app_id == Some(2805730))
+            .unwrap();
+        assert_eq!(proton_9.internal_name, "proton_9");
+        assert_eq!(proton_9.display_name, "Proton 9.0 (Beta)");
+        assert!(compat_tools
+            .iter()
+            .any(|tool| tool.internal_name == "SteamLinuxRuntime_sniper"));
+        assert_eq!(
+            steam_util.read_official_tool_names().unwrap().get(&2180100),
+            Some(&"proton_hotfix".to_string())
+        );
     }
 
     #[test]
@@ -620,17 +2093,512 @@ mod tests {
         assert_eq!(compat_tools_mappings.len(), 2);
     }
 
+    #[test]
+    fn test_list_library_folders() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+
+        let library_folders = steam_util
+            .list_library_folders()
+            .expect("Failed to list library folders");
+        assert_eq!(library_folders.len(), 1);
+        let library_folder = &library_folders[0];
+        assert_eq!(library_folder.label, "");
+        assert_eq!(library_folder.content_id, None);
+        assert_eq!(library_folder.apps.get(&730), Some(&1234567890));
+        assert_eq!(library_folder.apps.get(&1145360), Some(&987654321));
+        assert!(library_folder.mounted);
+        assert!(library_folder.capacity.unwrap() >= library_folder.free_space.unwrap());
+    }
+
+    #[test]
+    fn test_is_library_mounted() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let media_root = temp_dir.path().join("run/media");
+        let mount_point = media_root.join("deck/sdcard");
+        fs::create_dir_all(&mount_point).unwrap();
+        let mnt_root = temp_dir.path().join("mnt");
+        let plain_directory = mnt_root.join("games");
+        fs::create_dir_all(plain_directory.join("steamapps")).unwrap();
+        let media_roots = [media_root.to_str().unwrap(), mnt_root.to_str().unwrap()];
+        let mountinfo = temp_dir.path().join("mountinfo");
+        fs::write(
+            &mountinfo,
+            format!(
+                "22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw\n\
+                 98 22 179:1 / {}/deck/SD\\040Card rw,nosuid shared:50 - ext4 /dev/mmcblk0p1 rw\n",
+                media_root.display()
+            ),
+        )
+        .unwrap();
+        let mount_points = read_mount_points(&mountinfo);
+        assert_eq!(
+            mount_points,
+            vec![PathBuf::from("/"), media_root.join("deck/SD Card")]
+        );
+
+        // The empty mount point of an unmounted SD card
+        assert!(!is_library_mounted(
+            &mount_point,
+            &media_roots,
+            &mount_points
+        ));
+        assert!(!is_library_mounted(
+            &media_root.join("deck/missing"),
+            &media_roots,
+            &mount_points
+        ));
+        let sd_card = media_root.join("deck/SD Card");
+        fs::create_dir_all(&sd_card).unwrap();
+        assert!(is_library_mounted(&sd_card, &media_roots, &mount_points));
+        // A library on the root filesystem below a removable media root
+        assert!(is_library_mounted(
+            &plain_directory,
+            &media_roots,
+            &mount_points
+        ));
+        // Libraries outside the removable media roots only have to exist
+        assert!(is_library_mounted(
+            temp_dir.path(),
+            &media_roots,
+            &mount_points
+        ));
+    }
+
+    #[test]
+    fn test_list_installed_games_by_library() {
+        let steam_dir = create_test_steam_directory();
+        let root_dir = steam_dir.path().join("root");
+        let steam_util = SteamUtil::new(root_dir.clone());
+
+        let missing_library = steam_dir.path().join("missing");
+        fs::write(
+            root_dir.join("steamapps").join("libraryfolders.vdf"),
+            format!(
+                r#""libraryfolders"
+                {{
+                    "0" {{ "path" "{}" }}
+                    "1" {{ "path" "{}" }}
+                    "2" {{ "path" "/run/media/deck/sdcard" }}
+                }}"#,
+                root_dir.display(),
+                missing_library.display()
+            ),
+        )
+        .expect("Failed to write library folders VDF file");
+        fs::write(
+            root_dir.join("steamapps").join("appmanifest_42.acf"),
+            r#""AppState" { "name" "Broken" }"#,
+        )
+        .expect("Failed to write app manifest file");
+
+        let libraries = steam_util
+            .list_installed_games_by_library()
+            .expect("Failed to list libraries");
+        assert_eq!(libraries.len(), 3);
+        assert_eq!(libraries[0].status, LibraryFolderStatus::ParseError);
+        assert!(libraries[0].error.is_some());
+        assert_eq!(libraries[0].games.len(), 2);
+        assert_eq!(libraries[1].status, LibraryFolderStatus::Missing);
+        assert_eq!(libraries[2].status, LibraryFolderStatus::Unmounted);
+
+        // Games of the readable library are still listed
+        let installed_games = steam_util
+            .list_installed_games(None)
+            .expect("Failed to list installed games");
+        assert_eq!(installed_games.len(), 2);
+    }
+
     #[test]
     fn test_list_installed_games() {
         // Create emulated Steam directory for the test
         let steam_dir = create_test_steam_directory();
         let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
 
-        let result = steam_util.list_installed_games();
+        let user = steam_util
+            .get_active_user()
+            .expect("Failed to get active user");
+        let result = steam_util.list_installed_games(Some(&user));
         assert!(result.is_ok());
         let installed_games = result.unwrap();
-        assert_eq!(installed_games.len(), 2);
-        assert_eq!(installed_games[0].name, "Hades");
-        assert_eq!(installed_games[1].name, "Counter-Strike: Global Offensive");
+        assert_eq!(installed_games.len(), 3);
+        // Manifests are listed in read_dir order, which depends on the filesystem
+        let names: Vec<&str> = installed_games
+            .iter()
+            .map(|game| game.name.as_str())
+            .collect();
+        assert!(names.contains(&"Hades"));
+        assert!(names.contains(&"Counter-Strike: Global Offensive"));
+        assert_eq!(names[2], "RetroArch");
+    }
+
+    #[test]
+    fn test_read_app_infos() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+
+        let map = |entries: Vec<(&str, BinaryVdfValue)>| {
+            BinaryVdfValue::Map(BinaryVdfMap(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect(),
+            ))
+        };
+        let string = |value: &str| BinaryVdfValue::String(value.to_string());
+        let app = |entries: Vec<(&str, BinaryVdfValue)>| {
+            BinaryVdfMap(vec![("appinfo".to_string(), map(entries))])
+        };
+        let app_info = write_app_info(&[
+            (
+                730,
+                app(vec![
+                    (
+                        "common",
+                        map(vec![
+                            ("oslist", string("windows,linux")),
+                            (
+                                "steam_deck_compatibility",
+                                map(vec![("category", BinaryVdfValue::Int32(3))]),
+                            ),
+                        ]),
+                    ),
+                    (
+                        "config",
+                        map(vec![(
+                            "installdir",
+                            string("Counter-Strike Global Offensive"),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                1145360,
+                app(vec![(
+                    "common",
+                    map(vec![
+                        ("oslist", string("windows")),
+                        (
+                            "steam_deck_compatibility",
+                            map(vec![("category", string("2"))]),
+                        ),
+                    ]),
+                )]),
+            ),
+            (
+                STEAM_PLAY_MANIFESTS_APP_ID,
+                app(vec![(
+                    "extended",
+                    map(vec![(
+                        "app_mappings",
+                        map(vec![(
+                            "1145360",
+                            map(vec![
+                                ("appid", string("1145360")),
+                                ("tool", string("proton_9")),
+                            ]),
+                        )]),
+                    )]),
+                )]),
+            ),
+        ]);
+        let appcache_dir = steam_dir.path().join("root").join("appcache");
+        fs::create_dir_all(&appcache_dir).expect("Failed to create appcache directory");
+        fs::write(appcache_dir.join("appinfo.vdf"), app_info).expect("Failed to write app info");
+
+        let app_infos = steam_util
+            .read_app_infos(&[730, 1145360])
+            .expect("Failed to read app infos");
+        let counter_strike = app_infos.get(&730).expect("Missing app info");
+        assert_eq!(counter_strike.os_list, vec!["windows", "linux"]);
+        assert_eq!(
+            counter_strike.steam_deck_compatibility,
+            SteamDeckCompatibility::Verified
+        );
+        assert_eq!(
+            counter_strike.install_dir.as_deref(),
+            Some("Counter-Strike Global Offensive")
+        );
+        assert_eq!(counter_strike.recommended_compat_tool, None);
+        let hades = app_infos.get(&1145360).expect("Missing app info");
+        assert_eq!(
+            hades.steam_deck_compatibility,
+            SteamDeckCompatibility::Playable
+        );
+        assert_eq!(hades.recommended_compat_tool.as_deref(), Some("proton_9"));
+    }
+
+    #[test]
+    fn test_list_users() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+
+        let users = steam_util.list_users().expect("Failed to list users");
+        assert_eq!(users.len(), 2);
+        let user = steam_util
+            .get_active_user()
+            .expect("Failed to get active user");
+        assert_eq!(user.steam_id, "76561197972611406");
+        assert_eq!(user.account_id, 12345678);
+        assert_eq!(user.account_name, "deck");
+        assert_eq!(user.persona_name, "Steam Deck");
+        assert!(user.most_recent);
+        assert_eq!(
+            steam_util.get_user_config_directory(&user),
+            steam_dir.path().join("root/userdata/12345678/config")
+        );
+    }
+
+    #[test]
+    fn test_get_running_pid() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let pid_file = temp_dir.path().join("steam.pid");
+        let proc_directory = temp_dir.path().join("proc");
+        assert_eq!(get_running_pid(&pid_file, &proc_directory), None);
+
+        fs::write(&pid_file, "4242\n").unwrap();
+        assert_eq!(get_running_pid(&pid_file, &proc_directory), None);
+
+        fs::create_dir_all(proc_directory.join("4242")).unwrap();
+        fs::write(proc_directory.join("4242/comm"), "bash\n").unwrap();
+        assert_eq!(get_running_pid(&pid_file, &proc_directory), None);
+
+        fs::write(proc_directory.join("4242/comm"), "steam\n").unwrap();
+        assert_eq!(get_running_pid(&pid_file, &proc_directory), Some(4242));
+    }
+
+    #[test]
+    fn test_set_launch_options() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+        let user = steam_util
+            .get_active_user()
+            .expect("Failed to get active user");
+        let local_config_file = steam_util
+            .get_user_config_directory(&user)
+            .join("localconfig.vdf");
+        fs::write(
+            &local_config_file,
+            r#""UserLocalConfigStore"
+            {
+                "Software"
+                {
+                    "Valve"
+                    {
+                        "Steam"
+                        {
+                            "apps"
+                            {
+                                "730"
+                                {
+                                    "LaunchOptions"		"-novid"
+                                    "Playtime"		"42"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            "#,
+        )
+        .expect("Failed to write local config file");
+
+        assert_eq!(
+            steam_util
+                .get_launch_options(&user, 730)
+                .unwrap()
+                .as_deref(),
+            Some("-novid")
+        );
+        assert_eq!(steam_util.get_launch_options(&user, 1145360).unwrap(), None);
+
+        steam_util
+            .set_launch_options(&user, 730, "PROTON_LOG=1 %command% -novid")
+            .expect("Failed to set launch options");
+        steam_util
+            .set_launch_options(&user, 1145360, "DXVK_HUD=\"fps\" %command%")
+            .expect("Failed to set launch options");
+        assert_eq!(
+            steam_util
+                .get_launch_options(&user, 730)
+                .unwrap()
+                .as_deref(),
+            Some("PROTON_LOG=1 %command% -novid")
+        );
+        assert_eq!(
+            steam_util
+                .get_launch_options(&user, 1145360)
+                .unwrap()
+                .as_deref(),
+            Some("DXVK_HUD=\"fps\" %command%")
+        );
+        // Other settings are kept and the original file is backed up, later writes keep it
+        let local_config = fs::read_to_string(&local_config_file).unwrap();
+        assert!(local_config.contains("\"Playtime\"\t\"42\""));
+        let backup = fs::read_to_string(
+            local_config_file.with_file_name("localconfig.vdf.wine-cellar-backup"),
+        )
+        .unwrap();
+        assert!(backup.contains("\"LaunchOptions\"\t\t\"-novid\""));
+        assert!(!backup.contains("PROTON_LOG=1"));
+        assert!(!backup.contains("DXVK_HUD"));
+    }
+
+    #[test]
+    fn test_set_launch_options_refused_while_steam_runs() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+        let user = steam_util
+            .get_active_user()
+            .expect("Failed to get active user");
+        fs::write(
+            steam_util
+                .get_user_config_directory(&user)
+                .join("localconfig.vdf"),
+            "\"UserLocalConfigStore\"\n{\n}\n",
+        )
+        .expect("Failed to write local config file");
+
+        // steam.sh writes the pid to ~/.steam, the test Steam root lives in the home directory
+        let proc_directory = steam_dir.path().join("proc");
+        fs::create_dir_all(proc_directory.join("4242")).unwrap();
+        fs::write(proc_directory.join("4242/comm"), "steam\n").unwrap();
+        fs::create_dir_all(steam_dir.path().join(".steam")).unwrap();
+        fs::write(steam_dir.path().join(".steam/steam.pid"), "4242\n").unwrap();
+        let steam_util = SteamUtil {
+            proc_directory,
+            ..steam_util
+        };
+
+        assert!(matches!(
+            steam_util.set_launch_options(&user, 730, "%command% -novid"),
+            Err(SteamUtilError::SteamRunning(4242))
+        ));
+        assert_eq!(steam_util.get_launch_options(&user, 730).unwrap(), None);
+    }
+
+    #[test]
+    fn test_get_running_steam_pid() {
+        let home = tempfile::tempdir().expect("Failed to create temp dir");
+        let native_root = home.path().join(".local/share/Steam");
+        let flatpak_app = home.path().join(".var/app/com.valvesoftware.Steam");
+        let flatpak_root = flatpak_app.join("data/Steam");
+        fs::create_dir_all(&native_root).unwrap();
+        fs::create_dir_all(&flatpak_root).unwrap();
+        fs::create_dir_all(home.path().join(".steam")).unwrap();
+        fs::create_dir_all(flatpak_app.join(".steam")).unwrap();
+
+        let proc_directory = home.path().join("proc");
+        let fake_process = |pid: u32, command_name: &str, cwd: &Path| {
+            let process_directory = proc_directory.join(pid.to_string());
+            fs::create_dir_all(&process_directory).unwrap();
+            fs::write(
+                process_directory.join("comm"),
+                format!("{}\n", command_name),
+            )
+            .unwrap();
+            std::os::unix::fs::symlink(cwd, process_directory.join("cwd")).unwrap();
+        };
+        let steam_util = |steam_path: &Path| SteamUtil {
+            steam_path: steam_path.to_path_buf(),
+            proc_directory: proc_directory.clone(),
+        };
+
+        assert_eq!(
+            steam_util(&native_root).get_steam_pid_files(),
+            vec![
+                home.path().join(".steam/steam.pid"),
+                native_root.join("steam.pid")
+            ]
+        );
+        assert_eq!(
+            steam_util(&flatpak_root).get_steam_pid_files()[0],
+            flatpak_app.join(".steam/steam.pid")
+        );
+
+        fake_process(100, "steam", &native_root);
+        fs::write(home.path().join(".steam/steam.pid"), "100\n").unwrap();
+        assert_eq!(steam_util(&native_root).get_running_steam_pid(), Some(100));
+        assert_eq!(steam_util(&flatpak_root).get_running_steam_pid(), None);
+
+        // Without a pid file the process is found by its working directory
+        fake_process(200, "steam", &flatpak_root.join("ubuntu12_32"));
+        fake_process(300, "bash", &flatpak_root);
+        assert_eq!(steam_util(&flatpak_root).get_running_steam_pid(), Some(200));
+    }
+
+    #[test]
+    fn test_list_shortcuts() {
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+
+        let user = steam_util
+            .get_active_user()
+            .expect("Failed to get active user");
+        let shortcuts = steam_util
+            .list_shortcuts(&user)
+            .expect("Failed to list shortcuts");
+        assert_eq!(shortcuts.len(), 1);
+        assert_eq!(shortcuts[0].name, "RetroArch");
+        let app_id = compute_shortcut_app_id("\"/usr/bin/retroarch\"", "RetroArch");
+        assert_eq!(shortcuts[0].app_id, app_id as u64);
+        // Shortcut app ids always have the high bit set
+        assert!(app_id >= 0x8000_0000);
+    }
+
+    #[test]
+    fn test_read_shortcuts_skips_broken_entries() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let string = |value: &str| BinaryVdfValue::String(value.to_string());
+        let shortcuts = BinaryVdfMap(vec![(
+            "shortcuts".to_string(),
+            BinaryVdfValue::Map(BinaryVdfMap(vec![
+                (
+                    "0".to_string(),
+                    BinaryVdfValue::Map(BinaryVdfMap(vec![
+                        ("AppName".to_string(), BinaryVdfValue::Int32(42)),
+                        ("Exe".to_string(), string("\"/usr/bin/broken\"")),
+                    ])),
+                ),
+                (
+                    "1".to_string(),
+                    BinaryVdfValue::Map(BinaryVdfMap(vec![(
+                        "Exe".to_string(),
+                        string("\"/usr/bin/nameless\""),
+                    )])),
+                ),
+                (
+                    "2".to_string(),
+                    BinaryVdfValue::Map(BinaryVdfMap(vec![
+                        ("AppName".to_string(), string("Heroic")),
+                        ("Exe".to_string(), string("\"/usr/bin/heroic\"")),
+                    ])),
+                ),
+            ])),
+        )]);
+        let mut shortcuts_vdf = Vec::new();
+        write_binary_vdf(&shortcuts, &mut shortcuts_vdf, None);
+        let shortcuts_file = temp_dir.path().join("shortcuts.vdf");
+        fs::write(&shortcuts_file, shortcuts_vdf).expect("Failed to write shortcuts file");
+
+        let shortcuts =
+            SteamUtil::read_shortcuts(&shortcuts_file).expect("Failed to read shortcuts");
+        assert_eq!(shortcuts.len(), 1);
+        assert_eq!(shortcuts[0].name, "Heroic");
+    }
+
+    #[test]
+    fn test_list_compat_data_prefixes() {
+        // Create emulated Steam directory for the test
+        let steam_dir = create_test_steam_directory();
+        let steam_util = SteamUtil::new(steam_dir.path().join("root").to_path_buf());
+
+        let result = steam_util.list_compat_data_prefixes();
+        assert!(result.is_ok());
+        let prefixes = result.unwrap();
+        assert_eq!(prefixes.len(), 1);
+        assert_eq!(prefixes[0].app_id, 730);
+        assert_eq!(prefixes[0].prefix_version.as_deref(), Some("GE-Proton9-20"));
+        assert_eq!(prefixes[0].tool_version.as_deref(), Some("GE-Proton9-20"));
     }
 }
@@ -1,19 +1,37 @@
-use crate::steam_util::SteamUtil;
+use crate::steam_util::{SteamInstallation, SteamUtil};
+use crate::wine_cask::backup::PrefixBackup;
+use crate::wine_cask::clone::CloneCompatibilityTool;
+use crate::wine_cask::dedup::DeduplicationReport;
+use crate::wine_cask::dependencies::find_missing_dependency;
+use crate::wine_cask::disk_usage::{apply_disk_usage_cache, DiskUsage, DiskUsageCache};
 use crate::wine_cask::flavors::{
     CompatibilityToolFlavor, Flavor, SteamClientCompatToolInfo, SteamCompatibilityTool,
 };
+use crate::wine_cask::games::{AppInfoCache, SteamGame, SteamLibraryFolder};
+use crate::wine_cask::history::{HistoryPage, HistoryQuery};
 use crate::wine_cask::install::{Install, QueueCompatibilityTool, QueueCompatibilityToolState};
+use crate::wine_cask::launch_options::GameLaunchOptions;
+use crate::wine_cask::prefix_registry::{PrefixRegistry, PrefixRegistryRequest};
+use crate::wine_cask::prefixes::{
+    read_tool_prefix_version, Prefix, PrefixVersionWarning, SteamCompatDataPrefix,
+};
+use crate::wine_cask::proton_logs::{ProtonLog, ProtonLogRequest};
+use crate::wine_cask::rename::Rename;
+use crate::wine_cask::settings::Settings;
+use crate::wine_cask::steam_installations::{get_active_user, SteamInstallationUser};
+use crate::wine_cask::translation_layers::{ToolTranslationLayers, TranslationLayerRequest};
 use crate::wine_cask::uninstall::Uninstall;
 use crate::PeerMap;
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::Message;
 
 pub struct WineCask {
-    pub steam_util: SteamUtil,
+    pub managed_steam_installations: RwLock<Vec<SteamInstallation>>,
     pub app_state: Arc<Mutex<AppState>>,
 }
 
@@ -25,10 +43,40 @@ pub struct AppState {
     pub task_queue: VecDeque<Task>,
     pub updater_state: UpdaterState,
     pub updater_last_check: Option<u64>,
+    pub compatibility_tools_disk_usage: Option<DiskUsage>,
+    pub last_deduplication: Option<DeduplicationReport>,
+    pub compat_data_prefixes: Option<Vec<SteamCompatDataPrefix>>,
+    pub prefix_backups: Vec<PrefixBackup>,
+    pub prefix_version_warnings: Vec<PrefixVersionWarning>,
+    pub settings: Settings,
+    pub steam_installations: Vec<SteamInstallation>,
+    pub managed_steam_installations: Vec<String>,
+    /// Why no Steam installation could be used, the backend keeps looking while this is set.
+    pub steam_not_found: Option<String>,
+    pub installed_games: Vec<SteamGame>,
+    pub library_folders: Vec<SteamLibraryFolder>,
+    pub steam_users: Vec<SteamInstallationUser>,
+    /// Ids of the managed Steam installations whose client is running, their config can't be
+    /// changed until it exits.
+    pub running_steam_installations: Vec<String>,
+    pub launch_options: Option<GameLaunchOptions>,
+    pub translation_layers: Option<ToolTranslationLayers>,
+    pub proton_logs: Option<Vec<ProtonLog>>,
+    pub prefix_registry: Option<PrefixRegistry>,
+    pub history: Option<HistoryPage>,
     #[serde(skip)]
     pub available_compat_tools: Option<Vec<SteamClientCompatToolInfo>>,
     #[serde(skip)]
     pub flavors: Vec<Flavor>,
+    #[serde(skip)]
+    pub disk_usage_cache: Option<DiskUsageCache>,
+    #[serde(skip)]
+    pub app_info_cache: HashMap<String, AppInfoCache>,
+    /// Task taken from the queue and being processed, saved with the queue.
+    #[serde(skip)]
+    pub running_task: Option<Task>,
+    #[serde(skip)]
+    pub partial_directories: Vec<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -50,14 +98,42 @@ pub struct Task {
     pub r#type: TaskType,
     pub install: Option<Install>,
     pub uninstall: Option<Uninstall>,
+    pub prefix: Option<Prefix>,
+    pub settings: Option<Settings>,
+    pub launch_options: Option<GameLaunchOptions>,
+    pub rename: Option<Rename>,
+    pub clone_tool: Option<CloneCompatibilityTool>,
+    pub translation_layers: Option<TranslationLayerRequest>,
+    pub proton_log: Option<ProtonLogRequest>,
+    pub prefix_registry: Option<PrefixRegistryRequest>,
+    pub history: Option<HistoryQuery>,
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum TaskType {
     CheckForFlavorUpdates,
     InstallCompatibilityTool,
     CancelCompatibilityToolInstall,
     UninstallCompatibilityTool,
+    DeduplicateCompatibilityTools,
+    ListCompatDataPrefixes,
+    DeleteCompatDataPrefix,
+    ResetCompatDataPrefix,
+    BackupCompatDataPrefix,
+    RestoreCompatDataPrefix,
+    UpdateSettings,
+    GetLaunchOptions,
+    UpdateLaunchOptions,
+    RenameCompatibilityTool,
+    CloneCompatibilityTool,
+    GetTranslationLayers,
+    SwapTranslationLayer,
+    ListProtonLogs,
+    BundleProtonLog,
+    GetPrefixRegistry,
+    UpdateDllOverrides,
+    UpdateWindowsVersion,
+    GetHistory,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -69,30 +145,31 @@ pub struct Request {
     pub app_state: Option<AppState>,
 }
 
-// Internal only
-#[derive(Serialize, Deserialize, Clone)]
-pub struct VirtualCompatibilityToolMetadata {
-    r#virtual: bool,
-    virtual_original: String,
-}
-
 impl WineCask {
     pub(crate) async fn task_queue_pop_front(&self) -> Option<Task> {
-        self.app_state.lock().await.task_queue.pop_front()
+        let mut app_state = self.app_state.lock().await;
+        let task = app_state.task_queue.pop_front()?;
+        app_state.running_task = Some(task.clone());
+        drop(app_state);
+        self.save_task_state().await;
+        Some(task)
     }
 
     pub async fn add_to_task_queue(&self, task: Task, peer_map: &PeerMap) {
         self.app_state.lock().await.task_queue.push_back(task);
+        self.save_task_state().await;
         self.broadcast_app_state(peer_map).await;
     }
 
     pub async fn remove_or_cancel_from_task_queue(&self, task: Task, peer_map: &PeerMap) {
         let mut app_state = self.app_state.lock().await;
         if let Some(position) = app_state.task_queue.iter().position(|x| {
-            x.install.clone().unwrap().release.url == task.install.clone().unwrap().release.url
+            x.install.as_ref().map(|install| &install.release.url)
+                == task.install.as_ref().map(|install| &install.release.url)
         }) {
             app_state.task_queue.remove(position);
             drop(app_state);
+            self.save_task_state().await;
             self.broadcast_app_state(peer_map).await;
             self.broadcast_notification(
                 peer_map,
@@ -155,17 +232,20 @@ impl WineCask {
         }
     }
 
-    fn get_used_by_games(&self, display_name: &str, internal_name: &str) -> Vec<String> {
-        let compat_tools_mapping = self
-            .steam_util
+    fn get_used_by_games(
+        &self,
+        steam_util: &SteamUtil,
+        display_name: &str,
+        internal_name: &str,
+    ) -> Vec<String> {
+        let compat_tools_mapping = steam_util
             .get_compatibility_tools_mappings()
             .unwrap_or_else(|err| {
                 warn!("Failed to get compatibility tools mappings: {}", err);
                 HashMap::new()
             });
-        let installed_games = self
-            .steam_util
-            .list_installed_games()
+        let installed_games = steam_util
+            .list_installed_games(get_active_user(steam_util).as_ref())
             .unwrap_or_else(|err| {
                 warn!("Failed to get list of installed games: {}", err);
                 Vec::new()
@@ -191,35 +271,89 @@ impl WineCask {
     pub async fn update_used_by_games(&self, peer_map: &PeerMap) {
         for compat_tool in &mut self.app_state.lock().await.installed_compatibility_tools {
             compat_tool.used_by_games =
-                self.get_used_by_games(&compat_tool.display_name, &compat_tool.internal_name);
+                match self.steam_util_for_installation(&compat_tool.steam_installation) {
+                    Some(steam_util) => self.get_used_by_games(
+                        &steam_util,
+                        &compat_tool.display_name,
+                        &compat_tool.internal_name,
+                    ),
+                    None => Vec::new(),
+                };
         }
+        self.update_prefix_version_warnings().await;
+        self.update_installed_games().await;
         self.broadcast_app_state(peer_map).await;
     }
 
     pub fn list_compatibility_tools(&self) -> Option<Vec<SteamCompatibilityTool>> {
-        let compat_tools = self
-            .steam_util
-            .list_compatibility_tools()
-            .expect("Failed to get list of compatibility tools");
-
         let mut compatibility_tools: Vec<SteamCompatibilityTool> = Vec::new();
 
-        for compat_tool in &compat_tools {
-            let used_by_games: Vec<String> =
-                self.get_used_by_games(&compat_tool.display_name, &compat_tool.internal_name);
-            //let metadata = self.lookup_virtual_compatibility_tool_metadata(compat_tool);
-            compatibility_tools.push(SteamCompatibilityTool {
-                path: compat_tool.path.to_string_lossy().to_string(),
-                //directory_name: compat_tool.directory_name.to_string(),
-                display_name: compat_tool.display_name.to_string(),
-                internal_name: compat_tool.internal_name.to_string(),
-                used_by_games,
-                flavor: CompatibilityToolFlavor::Unknown,
-                github_release: None,
-                requires_restart: false,
-                //r#virtual: metadata.r#virtual,
-                //virtual_original: metadata.virtual_original,
-            })
+        for steam_installation in self.managed_steam_installations() {
+            let steam_util = SteamUtil::new(steam_installation.path.clone());
+            let mut compat_tools = match steam_util.list_compatibility_tools() {
+                Ok(compat_tools) => compat_tools,
+                Err(err) => {
+                    error!(
+                        "Failed to get list of compatibility tools for {}: {}",
+                        steam_installation.id, err
+                    );
+                    continue;
+                }
+            };
+            match steam_util.list_official_compatibility_tools() {
+                Ok(mut official_compat_tools) => compat_tools.append(&mut official_compat_tools),
+                Err(err) => error!(
+                    "Failed to get list of official compatibility tools for {}: {}",
+                    steam_installation.id, err
+                ),
+            }
+            let installed_app_ids = match steam_util.list_installed_app_ids() {
+                Ok(installed_app_ids) => Some(installed_app_ids),
+                Err(err) => {
+                    warn!(
+                        "Failed to get list of installed apps for {}: {}",
+                        steam_installation.id, err
+                    );
+                    None
+                }
+            };
+
+            for compat_tool in &compat_tools {
+                let used_by_games: Vec<String> = self.get_used_by_games(
+                    &steam_util,
+                    &compat_tool.display_name,
+                    &compat_tool.internal_name,
+                );
+                //let metadata = self.lookup_virtual_compatibility_tool_metadata(compat_tool);
+                compatibility_tools.push(SteamCompatibilityTool {
+                    path: compat_tool.path.to_string_lossy().to_string(),
+                    install_path: compat_tool.install_path.to_string_lossy().to_string(),
+                    //directory_name: compat_tool.directory_name.to_string(),
+                    display_name: compat_tool.display_name.to_string(),
+                    original_display_name: compat_tool.original_display_name.clone(),
+                    internal_name: compat_tool.internal_name.to_string(),
+                    from_os_list: compat_tool.from_os_list.clone(),
+                    to_os_list: compat_tool.to_os_list.clone(),
+                    used_by_games,
+                    flavor: CompatibilityToolFlavor::Unknown,
+                    github_release: None,
+                    requires_restart: false,
+                    disk_usage: None,
+                    prefix_version: read_tool_prefix_version(&compat_tool.install_path),
+                    steam_installation: steam_installation.id.clone(),
+                    app_id: compat_tool.app_id,
+                    read_only: compat_tool.app_id.is_some(),
+                    missing_dependency: installed_app_ids.as_ref().and_then(|installed_app_ids| {
+                        find_missing_dependency(
+                            &steam_util,
+                            installed_app_ids,
+                            &compat_tool.install_path,
+                        )
+                    }),
+                    //r#virtual: metadata.r#virtual,
+                    //virtual_original: metadata.virtual_original,
+                })
+            }
         }
 
         Some(compatibility_tools)
@@ -241,20 +375,25 @@ impl WineCask {
         let mut app_state = self.app_state.lock().await;
         app_state.installed_compatibility_tools = self.list_compatibility_tools().unwrap();
 
-        let available_compat_tools = app_state.available_compat_tools.clone().unwrap();
+        // The frontend reports the tools Steam has loaded with its first state request
+        if let Some(available_compat_tools) = app_state.available_compat_tools.clone() {
+            let available_tools_map: HashMap<String, &SteamClientCompatToolInfo> =
+                available_compat_tools
+                    .iter()
+                    .map(|tool| (tool.str_tool_name.clone(), tool))
+                    .collect();
 
-        let available_tools_map: HashMap<String, &SteamClientCompatToolInfo> =
-            available_compat_tools
-                .iter()
-                .map(|tool| (tool.str_tool_name.clone(), tool))
-                .collect();
-
-        for tool in &mut app_state.installed_compatibility_tools {
-            tool.requires_restart = !available_tools_map.contains_key(&tool.internal_name);
+            for tool in &mut app_state.installed_compatibility_tools {
+                // Steam doesn't offer runtimes as tools, but it has them loaded all the same
+                tool.requires_restart =
+                    !tool.read_only && !available_tools_map.contains_key(&tool.internal_name);
+            }
         }
+        apply_disk_usage_cache(&mut app_state);
         drop(app_state);
         self.update_compatibility_tools_and_available_flavors()
             .await;
+        self.update_prefix_version_warnings().await;
     }
 
     pub async fn check_for_flavor_updates(&self, peer_map: &PeerMap, renew_cache: bool) {
@@ -0,0 +1,526 @@
+use crate::steam_util::SteamUtil;
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_cask::prefixes::Prefix;
+use crate::wine_cask::recursive_delete_dir_entry;
+use crate::PeerMap;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MANIFEST_FILE_NAME: &str = "wine-cellar-backup.json";
+const ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+/// Stored as the first entry of every backup archive.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefixBackupManifest {
+    pub app_id: u64,
+    pub created: u64,
+    pub prefix_version: Option<String>,
+    pub tool_version: Option<String>,
+    pub exclude_caches: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefixBackup {
+    pub file_name: String,
+    pub app_id: u64,
+    pub created: u64,
+    pub size: u64,
+}
+
+/// Caches and temporary files Wine or the game will happily recreate.
+fn is_cache_entry(relative_path: &Path) -> bool {
+    let path = relative_path.to_string_lossy().to_lowercase();
+    let file_name = relative_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    path == "drive_c/windows/temp"
+        || path == "drive_c/users/steamuser/temp"
+        || path == "drive_c/users/steamuser/appdata/local/temp"
+        || file_name == "d3dscache"
+        || file_name == "dxcache"
+        || file_name == "glcache"
+        || file_name == "shadercache"
+        || file_name.ends_with(".dxvk-cache")
+        || file_name.ends_with(".vkd3d-proton.cache")
+        || file_name.ends_with(".vkd3d-proton.cache.write")
+}
+
+fn append_directory<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    base: &Path,
+    directory: &Path,
+    exclude_caches: bool,
+) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    for entry_path in entries {
+        let relative_path = entry_path.strip_prefix(base).unwrap();
+        if exclude_caches && is_cache_entry(relative_path) {
+            continue;
+        }
+        builder.append_path_with_name(&entry_path, Path::new("pfx").join(relative_path))?;
+        if fs::symlink_metadata(&entry_path)?.is_dir() {
+            append_directory(builder, base, &entry_path, exclude_caches)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Archives `compatdata/<appid>/pfx` together with its `version` file into a gzipped tarball.
+/// The archive is written next to its destination first, so a partial backup never shows up,
+/// and an existing backup at `archive_path` is never overwritten.
+pub fn create_prefix_backup_archive(
+    compat_data_path: &Path,
+    archive_path: &Path,
+    manifest: &PrefixBackupManifest,
+) -> io::Result<()> {
+    let partial_path = archive_path.with_extension("partial");
+    let result = (|| {
+        let encoder = GzEncoder::new(File::create(&partial_path)?, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        // Prefixes link to the host filesystem, those links must stay links
+        builder.follow_symlinks(false);
+
+        let manifest_json = serde_json::to_vec_pretty(manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST_FILE_NAME, manifest_json.as_slice())?;
+
+        let version_file = compat_data_path.join("version");
+        if version_file.exists() {
+            builder.append_path_with_name(&version_file, "version")?;
+        }
+
+        let prefix_path = compat_data_path.join("pfx");
+        builder.append_path_with_name(&prefix_path, "pfx")?;
+        append_directory(
+            &mut builder,
+            &prefix_path,
+            &prefix_path,
+            manifest.exclude_caches,
+        )?;
+
+        builder.into_inner()?.finish()?;
+        // Unlike rename, linking fails if the destination exists
+        fs::hard_link(&partial_path, archive_path)?;
+        fs::remove_file(&partial_path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial_path);
+    }
+    result
+}
+
+fn open_archive(archive_path: &Path) -> io::Result<tar::Archive<GzDecoder<BufReader<File>>>> {
+    let file = File::open(archive_path)?;
+    Ok(tar::Archive::new(GzDecoder::new(BufReader::new(file))))
+}
+
+fn is_safe_relative_path(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Reads the whole archive, which also validates the gzip checksum, and makes sure it is a
+/// backup of the expected app which only contains paths inside the prefix.
+pub fn verify_prefix_backup_archive(
+    archive_path: &Path,
+    app_id: u64,
+) -> io::Result<PrefixBackupManifest> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut manifest: Option<PrefixBackupManifest> = None;
+
+    for entry in open_archive(archive_path)?.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        if !is_safe_relative_path(&path) {
+            return Err(invalid(format!(
+                "Unsafe path in archive: {}",
+                path.display()
+            )));
+        }
+
+        if path == Path::new(MANIFEST_FILE_NAME) {
+            let mut json = String::new();
+            entry.read_to_string(&mut json)?;
+            manifest = Some(serde_json::from_str(&json)?);
+        } else if path == Path::new("version") || path.starts_with("pfx") {
+            io::copy(&mut entry, &mut io::sink())?;
+        } else {
+            return Err(invalid(format!(
+                "Unexpected entry in archive: {}",
+                path.display()
+            )));
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| invalid("Backup manifest not found".to_string()))?;
+    if manifest.app_id != app_id {
+        return Err(invalid(format!(
+            "Backup belongs to app {}, not {}",
+            manifest.app_id, app_id
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Restores a verified archive into `compat_data_path`. The prefix is unpacked next to the
+/// current one and swapped in with renames, so the game never sees a half restored prefix.
+pub fn restore_prefix_backup_archive(
+    archive_path: &Path,
+    compat_data_path: &Path,
+) -> io::Result<()> {
+    let prefix_path = compat_data_path.join("pfx");
+    let staging_path = compat_data_path.join("pfx.wine-cellar-restore");
+    let old_path = compat_data_path.join("pfx.wine-cellar-old");
+    let version_staging_path = compat_data_path.join("version.wine-cellar-restore");
+
+    for leftover in [&staging_path, &old_path, &version_staging_path] {
+        if fs::symlink_metadata(leftover).is_ok() {
+            recursive_delete_dir_entry(leftover)?;
+        }
+    }
+    fs::create_dir_all(&staging_path)?;
+
+    let mut version: Option<Vec<u8>> = None;
+    let unpacked = (|| {
+        for entry in open_archive(archive_path)?.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_path_buf();
+            if path == Path::new("version") {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+                version = Some(contents);
+            } else if let Ok(relative_path) = path.strip_prefix("pfx") {
+                if relative_path.as_os_str().is_empty() {
+                    continue;
+                }
+                entry.unpack_in(&staging_path)?;
+                // unpack_in keeps the leading pfx directory, move it into place below
+            }
+        }
+        Ok::<(), io::Error>(())
+    })();
+    let unpacked = unpacked.and_then(|_| match &version {
+        Some(version) => fs::write(&version_staging_path, version),
+        None => Ok(()),
+    });
+    if let Err(err) = unpacked {
+        let _ = recursive_delete_dir_entry(&staging_path);
+        let _ = fs::remove_file(&version_staging_path);
+        return Err(err);
+    }
+
+    let restored_path = staging_path.join("pfx");
+    if !restored_path.exists() {
+        fs::create_dir_all(&restored_path)?;
+    }
+
+    let had_prefix = fs::symlink_metadata(&prefix_path).is_ok();
+    if had_prefix {
+        fs::rename(&prefix_path, &old_path)?;
+    }
+    if let Err(err) = fs::rename(&restored_path, &prefix_path) {
+        if had_prefix {
+            fs::rename(&old_path, &prefix_path)?;
+        }
+        let _ = recursive_delete_dir_entry(&staging_path);
+        let _ = fs::remove_file(&version_staging_path);
+        return Err(err);
+    }
+
+    if version.is_some() {
+        fs::rename(&version_staging_path, compat_data_path.join("version"))?;
+    }
+
+    recursive_delete_dir_entry(&staging_path)?;
+    if had_prefix {
+        recursive_delete_dir_entry(&old_path)?;
+    }
+    Ok(())
+}
+
+/// Backups are named `<appid>-<timestamp>.tar.gz`, further backups within the same second get
+/// a counter: `<appid>-<timestamp>-<n>.tar.gz`.
+fn backup_file_name(app_id: u64, created: u64, index: u32) -> String {
+    match index {
+        0 => format!("{}-{}{}", app_id, created, ARCHIVE_EXTENSION),
+        _ => format!("{}-{}-{}{}", app_id, created, index, ARCHIVE_EXTENSION),
+    }
+}
+
+fn parse_backup_file_name(file_name: &str) -> Option<(u64, u64)> {
+    let (app_id, created) = file_name.strip_suffix(ARCHIVE_EXTENSION)?.split_once('-')?;
+    let created = match created.split_once('-') {
+        Some((created, index)) => {
+            index.parse::<u32>().ok()?;
+            created
+        }
+        None => created,
+    };
+    Some((app_id.parse().ok()?, created.parse().ok()?))
+}
+
+pub fn list_prefix_backups(backup_directory: &Path) -> Vec<PrefixBackup> {
+    let Ok(entries) = fs::read_dir(backup_directory) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<PrefixBackup> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let (app_id, created) = parse_backup_file_name(&file_name)?;
+            Some(PrefixBackup {
+                file_name,
+                app_id,
+                created,
+                size: entry.metadata().ok()?.len(),
+            })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+    backups
+}
+
+impl WineCask {
+    pub async fn update_prefix_backups(&self, peer_map: &PeerMap) {
+        let backup_directory = self.app_state.lock().await.settings.get_backup_directory();
+        self.app_state.lock().await.prefix_backups = list_prefix_backups(&backup_directory);
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    pub async fn backup_compat_data_prefix(&self, prefix: Prefix, peer_map: &PeerMap) {
+        let compat_data_prefix = match self.find_compat_data_prefix(prefix.app_id) {
+            Ok(compat_data_prefix) => compat_data_prefix,
+            Err(err) => {
+                let error_message = format!("Failed to backup prefix: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+                return;
+            }
+        };
+        if SteamUtil::is_app_running(prefix.app_id) {
+            // Files might change halfway through, but a snapshot is still better than nothing
+            warn!(
+                "App {} is running while backing up its prefix",
+                prefix.app_id
+            );
+        }
+
+        let backup_directory = self.app_state.lock().await.settings.get_backup_directory();
+        if let Err(err) = fs::create_dir_all(&backup_directory) {
+            let error_message = format!("Failed to create backup directory: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to calculate duration")
+            .as_secs();
+        let archive_path = (0..)
+            .map(|index| backup_directory.join(backup_file_name(prefix.app_id, created, index)))
+            .find(|path| fs::symlink_metadata(path).is_err())
+            .unwrap();
+        let manifest = PrefixBackupManifest {
+            app_id: prefix.app_id,
+            created,
+            prefix_version: compat_data_prefix.prefix_version.clone(),
+            tool_version: compat_data_prefix.tool_version.clone(),
+            exclude_caches: prefix.exclude_caches,
+        };
+
+        self.broadcast_notification(peer_map, &format!("Backing up prefix: {}", prefix.app_id))
+            .await;
+        let archive_path_clone = archive_path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            create_prefix_backup_archive(&compat_data_prefix.path, &archive_path_clone, &manifest)
+        })
+        .await
+        .unwrap();
+
+        match result {
+            Ok(_) => {
+                let message = format!("Backup Completed: {}", archive_path.display());
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+            }
+            Err(err) => {
+                let error_message = format!("Error during prefix backup: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
+        }
+        self.update_prefix_backups(peer_map).await;
+    }
+
+    pub async fn restore_compat_data_prefix(&self, prefix: Prefix, peer_map: &PeerMap) {
+        let history_entry = HistoryEntry::start(
+            TaskType::RestoreCompatDataPrefix,
+            Some(self.get_history_game_name(prefix.app_id).await),
+            prefix.backup_file_name.clone(),
+        );
+        let result = self.restore_prefix_backup(&prefix).await;
+        match &result {
+            Ok(_) => {
+                let message = format!(
+                    "Restore Completed: {}",
+                    prefix.backup_file_name.unwrap_or_default()
+                );
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+            }
+            Err(error_message) => {
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, error_message).await;
+            }
+        }
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+    }
+
+    async fn restore_prefix_backup(&self, prefix: &Prefix) -> Result<(), String> {
+        let Some(file_name) = prefix.backup_file_name.clone() else {
+            return Err("Error: No backup selected to restore".to_string());
+        };
+        // Only archives from the backup directory may be restored
+        if parse_backup_file_name(&file_name).is_none() || file_name.contains('/') {
+            return Err(format!("Invalid backup file name: {}", file_name));
+        }
+        if SteamUtil::is_app_running(prefix.app_id) {
+            return Err(format!(
+                "App {} is running, close it before restoring its prefix",
+                prefix.app_id
+            ));
+        }
+
+        let archive_path = self
+            .app_state
+            .lock()
+            .await
+            .settings
+            .get_backup_directory()
+            .join(&file_name);
+        // Restoring a deleted prefix puts it back into the main library of the first installation
+        let compat_data_path = match self.find_compat_data_prefix(prefix.app_id) {
+            Ok(compat_data_prefix) => compat_data_prefix.path,
+            Err(err) => match self.steam_utils().first() {
+                Some(steam_util) => steam_util
+                    .get_compat_data_directory()
+                    .join(prefix.app_id.to_string()),
+                None => return Err(format!("Failed to restore prefix: {}", err)),
+            },
+        };
+
+        let app_id = prefix.app_id;
+        tokio::task::spawn_blocking(move || {
+            verify_prefix_backup_archive(&archive_path, app_id)?;
+            fs::create_dir_all(&compat_data_path)?;
+            restore_prefix_backup_archive(&archive_path, &compat_data_path)
+        })
+        .await
+        .unwrap()
+        .map_err(|err| format!("Error during prefix restore: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_backup_and_restore_prefix() {
+        let steam_dir = tempdir().expect("Failed to create temporary directory");
+        let compat_data_path = steam_dir.path().join("compatdata").join("730");
+        let prefix_path = compat_data_path.join("pfx");
+        let save_directory = prefix_path.join("drive_c/users/steamuser/Saved Games");
+        let temp_directory = prefix_path.join("drive_c/windows/temp");
+        fs::create_dir_all(&save_directory).expect("Failed to create save directory");
+        fs::create_dir_all(&temp_directory).expect("Failed to create temp directory");
+        fs::create_dir_all(prefix_path.join("dosdevices")).expect("Failed to create dosdevices");
+        fs::write(save_directory.join("save.dat"), b"level 1").expect("Failed to write save");
+        fs::write(temp_directory.join("junk.tmp"), b"junk").expect("Failed to write temp file");
+        symlink("/", prefix_path.join("dosdevices/z:")).expect("Failed to create symlink");
+        fs::write(compat_data_path.join("version"), "GE-Proton9-20\n")
+            .expect("Failed to write version file");
+
+        let archive_path = steam_dir.path().join("730-1.tar.gz");
+        let manifest = PrefixBackupManifest {
+            app_id: 730,
+            created: 1,
+            prefix_version: Some("GE-Proton9-20".to_string()),
+            tool_version: None,
+            exclude_caches: true,
+        };
+        create_prefix_backup_archive(&compat_data_path, &archive_path, &manifest)
+            .expect("Failed to create backup");
+        assert!(create_prefix_backup_archive(&compat_data_path, &archive_path, &manifest).is_err());
+        assert!(!archive_path.with_extension("partial").exists());
+
+        assert!(verify_prefix_backup_archive(&archive_path, 730).is_ok());
+        assert!(verify_prefix_backup_archive(&archive_path, 440).is_err());
+
+        fs::write(save_directory.join("save.dat"), b"level 2").expect("Failed to write save");
+        fs::write(compat_data_path.join("version"), "GE-Proton9-22\n")
+            .expect("Failed to write version file");
+        restore_prefix_backup_archive(&archive_path, &compat_data_path)
+            .expect("Failed to restore backup");
+
+        assert_eq!(
+            fs::read(save_directory.join("save.dat")).unwrap(),
+            b"level 1"
+        );
+        assert!(!temp_directory.exists());
+        assert_eq!(
+            fs::read_link(prefix_path.join("dosdevices/z:")).unwrap(),
+            PathBuf::from("/")
+        );
+        assert!(!compat_data_path.join("pfx.wine-cellar-restore").exists());
+        assert!(!compat_data_path.join("pfx.wine-cellar-old").exists());
+        assert_eq!(
+            fs::read_to_string(compat_data_path.join("version")).unwrap(),
+            "GE-Proton9-20\n"
+        );
+        assert!(!compat_data_path
+            .join("version.wine-cellar-restore")
+            .exists());
+    }
+
+    #[test]
+    fn test_parse_backup_file_name() {
+        assert_eq!(
+            parse_backup_file_name(&backup_file_name(730, 1700000000, 0)),
+            Some((730, 1700000000))
+        );
+        assert_eq!(
+            parse_backup_file_name(&backup_file_name(730, 1700000000, 2)),
+            Some((730, 1700000000))
+        );
+        assert_eq!(backup_file_name(730, 1, 2), "730-1-2.tar.gz");
+        assert_eq!(parse_backup_file_name("730-1-x.tar.gz"), None);
+        assert_eq!(parse_backup_file_name("730.tar.gz"), None);
+    }
+}
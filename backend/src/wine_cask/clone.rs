@@ -0,0 +1,262 @@
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::flavors::SteamCompatibilityTool;
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_cask::{recursive_delete_dir_entry, write_compatibility_tool_vdf};
+use crate::PeerMap;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CloneCompatibilityTool {
+    pub steam_compatibility_tool: SteamCompatibilityTool,
+    /// Internal name of the copy, also used as its directory name.
+    pub internal_name: String,
+    pub display_name: String,
+}
+
+/// Internal names end up as directory names and in Steam's config, keep them simple.
+fn is_valid_internal_name(internal_name: &str) -> bool {
+    !internal_name.is_empty()
+        && internal_name != "."
+        && internal_name != ".."
+        && internal_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+/// Recreates `source` at `destination` with hardlinks to the original files, falling back to
+/// copies where linking isn't possible, e.g. from a library on another filesystem.
+fn link_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let destination_path = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            link_dir(&entry_path, &destination_path)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&entry_path)?, &destination_path)?;
+        } else if fs::hard_link(&entry_path, &destination_path).is_err() {
+            fs::copy(&entry_path, &destination_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Links the tool's files into `destination` and gives the copy its own `compatibilitytool.vdf`.
+fn create_tool_copy(
+    source: &Path,
+    destination: &Path,
+    tool: &SteamCompatibilityTool,
+    internal_name: &str,
+    display_name: &str,
+) -> io::Result<()> {
+    link_dir(source, destination)?;
+    // The vdf is linked to the original, writing through it would rename the original as well
+    for file_name in [
+        "compatibilitytool.vdf",
+        "compatibilitytool.vdf.wine-cellar-backup",
+    ] {
+        let path = destination.join(file_name);
+        if fs::symlink_metadata(&path).is_ok() {
+            fs::remove_file(&path)?;
+        }
+    }
+    write_compatibility_tool_vdf(
+        &destination.join("compatibilitytool.vdf"),
+        internal_name,
+        display_name,
+        &tool.from_os_list,
+        &tool.to_os_list,
+    )
+}
+
+impl WineCask {
+    /// Copies an installed tool to a new directory under `compatibilitytools.d`, the copy is a
+    /// custom tool of its own which can be changed without affecting the original.
+    pub async fn clone_compatibility_tool(
+        &self,
+        clone_tool: CloneCompatibilityTool,
+        peer_map: &PeerMap,
+    ) {
+        self.broadcast_notification(
+            peer_map,
+            &format!(
+                "Cloning {}...",
+                clone_tool.steam_compatibility_tool.display_name
+            ),
+        )
+        .await;
+
+        let history_entry = HistoryEntry::start(
+            TaskType::CloneCompatibilityTool,
+            Some(clone_tool.steam_compatibility_tool.display_name.clone()),
+            Some(clone_tool.display_name.trim().to_string()),
+        );
+        let result = self.create_clone(&clone_tool).await.map(|_| ());
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+        if let Err(err) = result {
+            let error_message = format!("Failed to clone compatibility tool: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+
+        let message = format!(
+            "Compatibility Tool Cloned: {}, restart Steam to use it",
+            clone_tool.display_name.trim()
+        );
+        info!("{}", message);
+        self.broadcast_notification(peer_map, &message).await;
+        self.sync_backend_with_installed_compat_tools().await;
+        self.update_used_by_games(peer_map).await;
+        self.invalidate_disk_usage_cache().await;
+        self.update_disk_usage(peer_map).await;
+    }
+
+    async fn create_clone(&self, clone_tool: &CloneCompatibilityTool) -> Result<PathBuf, String> {
+        let internal_name = clone_tool.internal_name.trim().to_string();
+        let display_name = clone_tool.display_name.trim().to_string();
+        if !is_valid_internal_name(&internal_name) {
+            return Err(format!(
+                "Invalid internal name '{}', use letters, digits, '-', '_' and '.'",
+                internal_name
+            ));
+        }
+        if display_name.is_empty() {
+            return Err("The display name can't be empty".to_string());
+        }
+
+        // Only clone tools we know about, the path comes from the frontend
+        let app_state = self.app_state.lock().await;
+        let tool = app_state
+            .installed_compatibility_tools
+            .iter()
+            .find(|tool| {
+                tool.path == clone_tool.steam_compatibility_tool.path
+                    && tool.internal_name == clone_tool.steam_compatibility_tool.internal_name
+            })
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Compatibility tool not found: {}",
+                    clone_tool.steam_compatibility_tool.display_name
+                )
+            })?;
+        let name_taken = app_state.installed_compatibility_tools.iter().any(|other| {
+            other.steam_installation == tool.steam_installation
+                && other.internal_name == internal_name
+        });
+        drop(app_state);
+
+        let steam_util = self
+            .steam_util_for_installation(&tool.steam_installation)
+            .ok_or_else(|| format!("Steam installation not found: {}", tool.steam_installation))?;
+        let destination = steam_util
+            .get_steam_compatibility_tools_directory()
+            .join(&internal_name);
+        if name_taken || fs::symlink_metadata(&destination).is_ok() {
+            return Err(format!(
+                "A compatibility tool named {} already exists",
+                internal_name
+            ));
+        }
+
+        let source = PathBuf::from(&tool.install_path);
+        let result_destination = destination.clone();
+        tokio::task::spawn_blocking(move || {
+            create_tool_copy(&source, &destination, &tool, &internal_name, &display_name)
+                .inspect_err(|_| {
+                    // Don't leave a half copied tool behind for Steam to pick up
+                    let _ = recursive_delete_dir_entry(&destination);
+                })
+        })
+        .await
+        .unwrap()
+        .map_err(|err| err.to_string())?;
+        Ok(result_destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_create_tool_copy() {
+        let compatibility_tools_dir = tempdir().expect("Failed to create temporary directory");
+        let source = compatibility_tools_dir.path().join("GE-Proton9-20");
+        let destination = compatibility_tools_dir.path().join("GE-Proton9-20-dxvk");
+        fs::create_dir_all(source.join("files/lib64/wine")).unwrap();
+        fs::write(source.join("files/lib64/wine/d3d11.dll"), "dxvk").unwrap();
+        fs::write(source.join("version"), "1717000000 GE-Proton9-20\n").unwrap();
+        std::os::unix::fs::symlink("files/lib64", source.join("lib64")).unwrap();
+        crate::wine_cask::generate_compatibility_tool_vdf(
+            source.join("compatibilitytool.vdf"),
+            "GE-Proton9-20",
+            "GE-Proton9-20",
+        );
+        let tool = SteamCompatibilityTool {
+            path: source.to_string_lossy().to_string(),
+            install_path: source.to_string_lossy().to_string(),
+            display_name: "GE-Proton9-20".to_string(),
+            original_display_name: None,
+            internal_name: "GE-Proton9-20".to_string(),
+            from_os_list: "windows".to_string(),
+            to_os_list: "linux".to_string(),
+            used_by_games: Vec::new(),
+            requires_restart: false,
+            flavor: crate::wine_cask::flavors::CompatibilityToolFlavor::ProtonGE,
+            github_release: None,
+            disk_usage: None,
+            prefix_version: None,
+            steam_installation: String::new(),
+            app_id: None,
+            read_only: false,
+            missing_dependency: None,
+        };
+
+        create_tool_copy(
+            &source,
+            &destination,
+            &tool,
+            "GE-Proton9-20-dxvk",
+            "GE-Proton9-20 (DXVK \"git\")",
+        )
+        .expect("Failed to copy tool");
+
+        let dll_metadata =
+            |tool_dir: &Path| fs::metadata(tool_dir.join("files/lib64/wine/d3d11.dll")).unwrap();
+        assert_eq!(
+            dll_metadata(&source).ino(),
+            dll_metadata(&destination).ino()
+        );
+        assert_eq!(
+            fs::read_link(destination.join("lib64")).unwrap(),
+            Path::new("files/lib64")
+        );
+        let original_vdf = fs::read_to_string(source.join("compatibilitytool.vdf")).unwrap();
+        assert!(!original_vdf.contains("GE-Proton9-20-dxvk"));
+        let copy_vdf = fs::read_to_string(destination.join("compatibilitytool.vdf")).unwrap();
+        assert!(copy_vdf.contains("\"GE-Proton9-20-dxvk\""));
+        assert!(copy_vdf.contains("\"GE-Proton9-20 (DXVK \\\"git\\\")\""));
+
+        // Existing directories are never merged into
+        assert!(create_tool_copy(&source, &destination, &tool, "x", "x").is_err());
+    }
+
+    #[test]
+    fn test_is_valid_internal_name() {
+        assert!(is_valid_internal_name("GE-Proton9-20_dxvk.2"));
+        assert!(!is_valid_internal_name(""));
+        assert!(!is_valid_internal_name(".."));
+        assert!(!is_valid_internal_name("../GE-Proton9-20"));
+        assert!(!is_valid_internal_name("GE Proton"));
+    }
+}
@@ -0,0 +1,445 @@
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::history::HistoryEntry;
+use crate::PeerMap;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::{File, Metadata, OpenOptions};
+use std::io;
+use std::io::{BufReader, Read};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+// _IOWR('f', 11, struct fiemap)
+const FS_IOC_FIEMAP: u64 = 0xC020_660B;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct DeduplicationReport {
+    pub files_scanned: u64,
+    pub files_hardlinked: u64,
+    pub files_reflinked: u64,
+    pub bytes_reclaimed: u64,
+}
+
+#[derive(PartialEq)]
+enum LinkMethod {
+    Hardlink,
+    Reflink,
+}
+
+type Inode = (u64, u64);
+
+fn inode(metadata: &Metadata) -> Inode {
+    (metadata.dev(), metadata.ino())
+}
+
+fn collect_regular_files(path: &Path, files: &mut Vec<(PathBuf, Metadata)>) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        let metadata = fs::symlink_metadata(&entry_path)?;
+        if metadata.is_dir() {
+            collect_regular_files(&entry_path, files)?;
+        } else if metadata.is_file() && metadata.len() > 0 {
+            files.push((entry_path, metadata));
+        }
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Byte for byte comparison, so a hash collision can never link two different files.
+fn files_are_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut reader_a = BufReader::new(File::open(a)?);
+    let mut reader_b = BufReader::new(File::open(b)?);
+    let mut buffer_a = [0u8; 64 * 1024];
+    let mut buffer_b = [0u8; 64 * 1024];
+    loop {
+        let read = reader_a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(reader_b.read(&mut buffer_b)? == 0);
+        }
+        if reader_b.read_exact(&mut buffer_b[..read]).is_err()
+            || buffer_a[..read] != buffer_b[..read]
+        {
+            return Ok(false);
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct FiemapExtent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+    fm_extents: [FiemapExtent; 1],
+}
+
+/// Physical location of the first extent of a file, if the filesystem is willing to tell us.
+fn first_physical_extent(path: &Path) -> Option<u64> {
+    let file = File::open(path).ok()?;
+    let mut fiemap = Fiemap {
+        fm_length: u64::MAX,
+        fm_extent_count: 1,
+        ..Default::default()
+    };
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap) };
+    if result != 0 || fiemap.fm_mapped_extents == 0 || fiemap.fm_extents[0].fe_physical == 0 {
+        return None;
+    }
+    Some(fiemap.fm_extents[0].fe_physical)
+}
+
+const TEMPORARY_LINK_SUFFIX: &str = ".wine-cellar-dedup";
+
+fn temporary_link_path(path: &Path) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}{}", file_name, TEMPORARY_LINK_SUFFIX))
+}
+
+/// Removes a temporary link left behind by an interrupted run, it would block linking the file.
+fn remove_stale_temporary_link(temporary: &Path) -> io::Result<()> {
+    match fs::remove_file(temporary) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the temporary links an interrupted run left below `directory`, returns how many.
+pub fn remove_temporary_links(directory: &Path) -> io::Result<u64> {
+    let mut removed = 0;
+    for entry in fs::read_dir(directory)? {
+        let entry_path = entry?.path();
+        let metadata = fs::symlink_metadata(&entry_path)?;
+        if metadata.is_dir() {
+            removed += remove_temporary_links(&entry_path)?;
+        } else if entry_path
+            .file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().ends_with(TEMPORARY_LINK_SUFFIX))
+        {
+            debug!("Removing leftover {}", entry_path.display());
+            fs::remove_file(&entry_path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Replaces `duplicate` with a reflink of `original`, the copy keeps its own inode and permissions.
+fn reflink_file(original: &Path, duplicate: &Path, metadata: &Metadata) -> io::Result<()> {
+    let temporary = temporary_link_path(duplicate);
+    remove_stale_temporary_link(&temporary)?;
+    let source = File::open(original)?;
+    let destination = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(metadata.permissions().mode())
+        .open(&temporary)?;
+
+    let result = unsafe {
+        libc::ioctl(
+            destination.as_raw_fd(),
+            libc::FICLONE as _,
+            source.as_raw_fd(),
+        )
+    };
+    if result != 0 {
+        let err = io::Error::last_os_error();
+        drop(destination);
+        let _ = fs::remove_file(&temporary);
+        return Err(err);
+    }
+
+    fs::rename(&temporary, duplicate).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+/// Replaces `duplicate` with a hardlink to `original` by renaming a new link over it, so the
+/// duplicate path is never missing even if we are interrupted.
+fn hardlink_file(original: &Path, duplicate: &Path) -> io::Result<()> {
+    let temporary = temporary_link_path(duplicate);
+    remove_stale_temporary_link(&temporary)?;
+    fs::hard_link(original, &temporary)?;
+    fs::rename(&temporary, duplicate).inspect_err(|_| {
+        let _ = fs::remove_file(&temporary);
+    })
+}
+
+fn is_reflink_unsupported(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EOPNOTSUPP) | Some(libc::EXDEV) | Some(libc::EINVAL) | Some(libc::ENOTTY)
+    )
+}
+
+/// Finds byte identical files below `directories` and makes them share their data, using reflinks
+/// where the filesystem supports them and hardlinks otherwise. Files which already share their
+/// data are left alone, so running this again only picks up new duplicates.
+pub fn deduplicate_directories(directories: &[PathBuf]) -> io::Result<DeduplicationReport> {
+    let mut report = DeduplicationReport::default();
+
+    let mut files: Vec<(PathBuf, Metadata)> = Vec::new();
+    for directory in directories {
+        let removed = remove_temporary_links(directory)?;
+        if removed > 0 {
+            info!(
+                "Removed {} leftover files of an interrupted deduplication",
+                removed
+            );
+        }
+        collect_regular_files(directory, &mut files)?;
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    report.files_scanned = files.len() as u64;
+
+    // Only files of the same size on the same device can be linked together
+    let mut candidates: HashMap<(u64, u64), Vec<(PathBuf, Metadata)>> = HashMap::new();
+    for (path, metadata) in files {
+        candidates
+            .entry((metadata.dev(), metadata.len()))
+            .or_default()
+            .push((path, metadata));
+    }
+
+    // Remaining links of every inode we replace, its data is only freed once none are left
+    let mut remaining_links: HashMap<Inode, u64> = HashMap::new();
+    let mut reflink_unsupported: HashSet<u64> = HashSet::new();
+
+    for (_, group) in candidates {
+        let distinct_inodes: HashSet<Inode> = group.iter().map(|(_, m)| inode(m)).collect();
+        if distinct_inodes.len() < 2 {
+            continue;
+        }
+
+        let mut hashes: HashMap<Inode, [u8; 32]> = HashMap::new();
+        let mut by_hash: HashMap<[u8; 32], Vec<(PathBuf, Metadata)>> = HashMap::new();
+        for (path, metadata) in group {
+            let hash = match hashes.get(&inode(&metadata)) {
+                Some(hash) => *hash,
+                None => match hash_file(&path) {
+                    Ok(hash) => {
+                        hashes.insert(inode(&metadata), hash);
+                        hash
+                    }
+                    Err(err) => {
+                        warn!("Failed to hash {}: {}", path.display(), err);
+                        continue;
+                    }
+                },
+            };
+            by_hash.entry(hash).or_default().push((path, metadata));
+        }
+
+        for (_, duplicates) in by_hash {
+            // Link everything to the inode with the most links, that needs the fewest replacements
+            let Some((original, original_metadata)) = duplicates
+                .iter()
+                .max_by_key(|(path, metadata)| (metadata.nlink(), std::cmp::Reverse(path.clone())))
+                .cloned()
+            else {
+                continue;
+            };
+            let original_first_extent = first_physical_extent(&original);
+
+            for (duplicate, metadata) in &duplicates {
+                if inode(metadata) == inode(&original_metadata) {
+                    continue;
+                }
+                // Reflinked on a previous run
+                if original_first_extent.is_some()
+                    && original_first_extent == first_physical_extent(duplicate)
+                {
+                    continue;
+                }
+                match files_are_identical(&original, duplicate) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        warn!("Failed to compare {}: {}", duplicate.display(), err);
+                        continue;
+                    }
+                }
+
+                let mut method = LinkMethod::Reflink;
+                if reflink_unsupported.contains(&metadata.dev()) {
+                    method = LinkMethod::Hardlink;
+                } else if let Err(err) = reflink_file(&original, duplicate, metadata) {
+                    if is_reflink_unsupported(&err) {
+                        debug!("Reflinks are not supported, falling back to hardlinks");
+                        reflink_unsupported.insert(metadata.dev());
+                        method = LinkMethod::Hardlink;
+                    } else {
+                        warn!("Failed to reflink {}: {}", duplicate.display(), err);
+                        continue;
+                    }
+                }
+
+                if method == LinkMethod::Hardlink {
+                    // Hardlinks share permissions and ownership, so those have to match already
+                    if metadata.mode() != original_metadata.mode()
+                        || metadata.uid() != original_metadata.uid()
+                        || metadata.gid() != original_metadata.gid()
+                    {
+                        continue;
+                    }
+                    if let Err(err) = hardlink_file(&original, duplicate) {
+                        warn!("Failed to hardlink {}: {}", duplicate.display(), err);
+                        continue;
+                    }
+                    report.files_hardlinked += 1;
+                } else {
+                    report.files_reflinked += 1;
+                }
+
+                let links = remaining_links
+                    .entry(inode(metadata))
+                    .or_insert(metadata.nlink());
+                *links -= 1;
+                if *links == 0 {
+                    report.bytes_reclaimed += metadata.blocks() * 512;
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+impl WineCask {
+    pub async fn deduplicate_compatibility_tools(&self, peer_map: &PeerMap) {
+        let compatibility_tools_directories: Vec<PathBuf> = self
+            .steam_utils()
+            .iter()
+            .map(|steam_util| steam_util.get_steam_compatibility_tools_directory())
+            .collect();
+
+        self.broadcast_notification(peer_map, "Deduplicating compatibility tools...")
+            .await;
+        let history_entry =
+            HistoryEntry::start(TaskType::DeduplicateCompatibilityTools, None, None);
+
+        let result = tokio::task::spawn_blocking(move || {
+            deduplicate_directories(&compatibility_tools_directories)
+        })
+        .await
+        .unwrap();
+        self.record_history(
+            history_entry.finish(&result.as_ref().map(|_| ()).map_err(ToString::to_string)),
+            peer_map,
+        )
+        .await;
+
+        match result {
+            Ok(report) => {
+                let message = format!(
+                    "Deduplication Completed: {} files linked, {:.1} MiB reclaimed",
+                    report.files_hardlinked + report.files_reflinked,
+                    report.bytes_reclaimed as f64 / (1024.0 * 1024.0)
+                );
+                info!("{}", message);
+                self.app_state.lock().await.last_deduplication = Some(report);
+                self.broadcast_notification(peer_map, &message).await;
+            }
+            Err(err) => {
+                let error_message = format!("Error during deduplication: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
+        }
+
+        self.invalidate_disk_usage_cache().await;
+        self.update_disk_usage(peer_map).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_deduplicate_directory_is_idempotent() {
+        let directory = tempdir().expect("Failed to create temporary directory");
+        let tool_1 = directory.path().join("GE-Proton9-1");
+        let tool_2 = directory.path().join("GE-Proton9-2");
+        fs::create_dir_all(&tool_1).expect("Failed to create tool directory");
+        fs::create_dir_all(&tool_2).expect("Failed to create tool directory");
+
+        fs::write(tool_1.join("font.ttf"), vec![7u8; 8192]).expect("Failed to write file");
+        fs::write(tool_2.join("font.ttf"), vec![7u8; 8192]).expect("Failed to write file");
+        fs::write(tool_1.join("proton"), b"version 1").expect("Failed to write file");
+        fs::write(tool_2.join("proton"), b"version 2").expect("Failed to write file");
+
+        let report = deduplicate_directories(&[directory.path().to_path_buf()])
+            .expect("Failed to deduplicate");
+        assert_eq!(report.files_scanned, 4);
+        assert_eq!(report.files_hardlinked + report.files_reflinked, 1);
+        assert_eq!(fs::read(tool_2.join("font.ttf")).unwrap(), vec![7u8; 8192]);
+        assert_eq!(fs::read(tool_2.join("proton")).unwrap(), b"version 2");
+
+        let report = deduplicate_directories(&[directory.path().to_path_buf()])
+            .expect("Failed to deduplicate");
+        assert_eq!(report.files_hardlinked + report.files_reflinked, 0);
+        assert_eq!(report.bytes_reclaimed, 0);
+    }
+
+    #[test]
+    fn test_deduplicate_after_interrupted_run() {
+        let directory = tempdir().expect("Failed to create temporary directory");
+        let tool_1 = directory.path().join("GE-Proton9-1");
+        let tool_2 = directory.path().join("GE-Proton9-2");
+        fs::create_dir_all(&tool_1).expect("Failed to create tool directory");
+        fs::create_dir_all(&tool_2).expect("Failed to create tool directory");
+        fs::write(tool_1.join("font.ttf"), vec![7u8; 8192]).expect("Failed to write file");
+        fs::write(tool_2.join("font.ttf"), vec![7u8; 8192]).expect("Failed to write file");
+
+        // Left behind by a run that was interrupted before renaming the link into place
+        let leftover = temporary_link_path(&tool_2.join("font.ttf"));
+        fs::hard_link(tool_1.join("font.ttf"), &leftover).expect("Failed to link file");
+
+        let report = deduplicate_directories(&[directory.path().to_path_buf()])
+            .expect("Failed to deduplicate");
+        assert_eq!(report.files_scanned, 2);
+        assert_eq!(report.files_hardlinked + report.files_reflinked, 1);
+        assert!(!leftover.exists());
+
+        // A leftover created while scanning doesn't block linking either
+        fs::remove_file(tool_2.join("font.ttf")).expect("Failed to remove file");
+        fs::write(tool_2.join("font.ttf"), vec![7u8; 8192]).expect("Failed to write file");
+        fs::write(&leftover, b"partial").expect("Failed to write file");
+        hardlink_file(&tool_1.join("font.ttf"), &tool_2.join("font.ttf"))
+            .expect("Failed to hardlink");
+        assert!(!leftover.exists());
+    }
+}
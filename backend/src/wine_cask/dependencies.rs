@@ -0,0 +1,115 @@
+use crate::steam_util::SteamUtil;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Runtimes tools commonly require, Steam doesn't tell their names before they're installed.
+const KNOWN_RUNTIMES: &[(u64, &str)] = &[
+    (1070560, "Steam Linux Runtime 1.0 (scout)"),
+    (1391110, "Steam Linux Runtime 2.0 (soldier)"),
+    (1628350, "Steam Linux Runtime 3.0 (sniper)"),
+];
+
+/// An app a tool requires through `require_tool_appid` in its `toolmanifest.vdf`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ToolDependency {
+    pub app_id: u64,
+    pub name: String,
+}
+
+impl ToolDependency {
+    fn new(app_id: u64) -> ToolDependency {
+        let name = KNOWN_RUNTIMES
+            .iter()
+            .find(|(runtime_app_id, _)| *runtime_app_id == app_id)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| format!("App {}", app_id));
+        ToolDependency { app_id, name }
+    }
+}
+
+/// The app a tool requires that isn't among `installed_app_ids` of the tool's Steam installation.
+pub fn find_missing_dependency(
+    steam_util: &SteamUtil,
+    installed_app_ids: &HashSet<u64>,
+    tool_path: &Path,
+) -> Option<ToolDependency> {
+    let tool_manifest_vdf = tool_path.join("toolmanifest.vdf");
+    if !tool_manifest_vdf.exists() {
+        return None;
+    }
+    let require_tool_app_id = match steam_util.read_tool_manifest(&tool_manifest_vdf) {
+        Ok(tool_manifest) => tool_manifest.require_tool_appid?,
+        Err(err) => {
+            warn!(
+                "Failed to read tool manifest {}: {}",
+                tool_manifest_vdf.display(),
+                err
+            );
+            return None;
+        }
+    };
+    if installed_app_ids.contains(&require_tool_app_id) {
+        return None;
+    }
+    let dependency = ToolDependency::new(require_tool_app_id);
+    warn!(
+        "{} requires {} ({}) which isn't installed",
+        tool_path.display(),
+        dependency.name,
+        dependency.app_id
+    );
+    Some(dependency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_missing_dependency() {
+        let steam_dir = tempdir().expect("Failed to create temporary directory");
+        let steamapps_dir = steam_dir.path().join("steamapps");
+        let tool_dir = steam_dir.path().join("compatibilitytools.d/GE-Proton9-20");
+        fs::create_dir_all(&steamapps_dir).unwrap();
+        fs::create_dir_all(&tool_dir).unwrap();
+        fs::write(
+            steamapps_dir.join("libraryfolders.vdf"),
+            format!(
+                r#""libraryfolders" {{ "0" {{ "path" "{}" }} }}"#,
+                steam_dir.path().display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            tool_dir.join("toolmanifest.vdf"),
+            r#""manifest" { "commandline" "/proton %verb%" "require_tool_appid" "1628350" }"#,
+        )
+        .unwrap();
+        let steam_util = SteamUtil::new(steam_dir.path().to_path_buf());
+
+        let installed_app_ids = steam_util.list_installed_app_ids().unwrap();
+        assert_eq!(
+            find_missing_dependency(&steam_util, &installed_app_ids, &tool_dir),
+            Some(ToolDependency {
+                app_id: 1628350,
+                name: "Steam Linux Runtime 3.0 (sniper)".to_string(),
+            })
+        );
+
+        fs::write(
+            steamapps_dir.join("appmanifest_1628350.acf"),
+            r#""AppState" { "appid" "1628350" "name" "Steam Linux Runtime 3.0 (sniper)" }"#,
+        )
+        .unwrap();
+        let installed_app_ids = steam_util.list_installed_app_ids().unwrap();
+        assert_eq!(installed_app_ids, HashSet::from([1628350]));
+        assert_eq!(
+            find_missing_dependency(&steam_util, &installed_app_ids, &tool_dir),
+            None
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::wine_cask::app::{AppState, WineCask};
+use crate::PeerMap;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::fs::Metadata;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Size of a directory tree on disk.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct DiskUsage {
+    /// Sum of the file lengths, like `du --apparent-size`.
+    pub apparent_bytes: u64,
+    /// Sum of the blocks actually allocated, like `du`.
+    pub allocated_bytes: u64,
+}
+
+// Internal only
+#[derive(Clone)]
+pub struct DiskUsageCache {
+    signature: Vec<(PathBuf, Option<SystemTime>)>,
+    compatibility_tools: HashMap<String, DiskUsage>,
+    total: DiskUsage,
+}
+
+impl DiskUsage {
+    fn add(&mut self, metadata: &Metadata) {
+        self.apparent_bytes += metadata.len();
+        // st_blocks is always expressed in 512-byte units
+        self.allocated_bytes += metadata.blocks() * 512;
+    }
+}
+
+/// Accumulates the disk usage of a directory tree, counting every inode only once.
+#[derive(Default)]
+struct DiskUsageCounter {
+    usage: DiskUsage,
+    seen_inodes: HashSet<(u64, u64)>,
+}
+
+impl DiskUsageCounter {
+    fn count(&mut self, metadata: &Metadata) {
+        // Hardlinked files share an inode, only the first link we encounter is counted
+        if metadata.nlink() > 1
+            && !metadata.is_dir()
+            && !self.seen_inodes.insert((metadata.dev(), metadata.ino()))
+        {
+            return;
+        }
+        self.usage.add(metadata);
+    }
+}
+
+/// Walks `path` without following symlinks, calling `visit` for every entry including `path` itself.
+fn walk_dir_entries(path: &Path, visit: &mut impl FnMut(&Metadata)) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    visit(&metadata);
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            walk_dir_entries(&entry?.path(), visit)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Measures a single directory tree, counting hardlinked files once.
+pub fn measure_directory(path: &Path) -> io::Result<DiskUsage> {
+    let mut counter = DiskUsageCounter::default();
+    walk_dir_entries(path, &mut |metadata| counter.count(metadata))?;
+    Ok(counter.usage)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Modification times of the compatibility tools directories and every tool in them, any change
+/// to these means a tool was added, removed or had its top level contents touched.
+fn disk_usage_signature(
+    compatibility_tools_directories: &[PathBuf],
+    compatibility_tool_paths: &[PathBuf],
+) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut signature: Vec<(PathBuf, Option<SystemTime>)> = compatibility_tools_directories
+        .iter()
+        .chain(compatibility_tool_paths)
+        .map(|path| (path.clone(), modified(path)))
+        .collect();
+    signature.sort_by(|a, b| a.0.cmp(&b.0));
+    signature
+}
+
+/// Measures every compatibility tool separately as well as the whole compatibility tools
+/// directories, where files hardlinked between tools are only counted once.
+fn measure_compatibility_tools(
+    compatibility_tools_directories: &[PathBuf],
+    compatibility_tool_paths: &[PathBuf],
+) -> io::Result<(HashMap<String, DiskUsage>, DiskUsage)> {
+    let mut total = DiskUsageCounter::default();
+    let mut compatibility_tools: HashMap<String, DiskUsage> = HashMap::new();
+
+    for compatibility_tools_directory in compatibility_tools_directories {
+        total.count(&fs::symlink_metadata(compatibility_tools_directory)?);
+
+        for entry in fs::read_dir(compatibility_tools_directory)? {
+            let entry_path = entry?.path();
+            if compatibility_tool_paths.contains(&entry_path) {
+                let mut tool = DiskUsageCounter::default();
+                walk_dir_entries(&entry_path, &mut |metadata| {
+                    tool.count(metadata);
+                    total.count(metadata);
+                })?;
+                compatibility_tools.insert(entry_path.to_string_lossy().to_string(), tool.usage);
+            } else {
+                walk_dir_entries(&entry_path, &mut |metadata| total.count(metadata))?;
+            }
+        }
+    }
+
+    Ok((compatibility_tools, total.usage))
+}
+
+/// Copies the cached disk usage onto the installed compatibility tools.
+pub fn apply_disk_usage_cache(app_state: &mut AppState) {
+    if let Some(cache) = &app_state.disk_usage_cache {
+        for tool in &mut app_state.installed_compatibility_tools {
+            tool.disk_usage = cache.compatibility_tools.get(&tool.path).copied();
+        }
+        app_state.compatibility_tools_disk_usage = Some(cache.total);
+    }
+}
+
+impl WineCask {
+    /// Recomputes the disk usage of the installed compatibility tools unless the cached result
+    /// is still valid, measuring happens on a blocking thread since large trees take a while.
+    pub async fn update_disk_usage(&self, peer_map: &PeerMap) {
+        let compatibility_tools_directories: Vec<PathBuf> = self
+            .steam_utils()
+            .iter()
+            .map(|steam_util| steam_util.get_steam_compatibility_tools_directory())
+            .collect();
+        let compatibility_tool_paths: Vec<PathBuf> = self
+            .app_state
+            .lock()
+            .await
+            .installed_compatibility_tools
+            .iter()
+            .map(|tool| PathBuf::from(&tool.path))
+            .collect();
+
+        let signature =
+            disk_usage_signature(&compatibility_tools_directories, &compatibility_tool_paths);
+
+        if let Some(cache) = &self.app_state.lock().await.disk_usage_cache {
+            if cache.signature == signature {
+                debug!("Disk usage cache is still valid");
+                return;
+            }
+        }
+
+        let measured = tokio::task::spawn_blocking(move || {
+            measure_compatibility_tools(&compatibility_tools_directories, &compatibility_tool_paths)
+        })
+        .await
+        .unwrap();
+
+        match measured {
+            Ok((compatibility_tools, total)) => {
+                let mut app_state = self.app_state.lock().await;
+                app_state.disk_usage_cache = Some(DiskUsageCache {
+                    signature,
+                    compatibility_tools,
+                    total,
+                });
+                apply_disk_usage_cache(&mut app_state);
+                drop(app_state);
+                self.broadcast_app_state(peer_map).await;
+            }
+            Err(err) => {
+                error!("Failed to measure compatibility tools disk usage: {}", err);
+            }
+        }
+    }
+
+    /// Forces the next `update_disk_usage` to measure again, for changes that don't touch the
+    /// modification time of the tool directories.
+    pub async fn invalidate_disk_usage_cache(&self) {
+        self.app_state.lock().await.disk_usage_cache = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_measure_compatibility_tools_counts_hardlinks_once() {
+        let compatibility_tools_directory =
+            tempdir().expect("Failed to create temporary directory");
+        let tool_1 = compatibility_tools_directory.path().join("tool_1");
+        let tool_2 = compatibility_tools_directory.path().join("tool_2");
+        fs::create_dir_all(&tool_1).expect("Failed to create tool directory");
+        fs::create_dir_all(&tool_2).expect("Failed to create tool directory");
+
+        fs::write(tool_1.join("file"), vec![0u8; 4096]).expect("Failed to write file");
+        fs::hard_link(tool_1.join("file"), tool_1.join("link")).expect("Failed to link file");
+        fs::hard_link(tool_1.join("file"), tool_2.join("link")).expect("Failed to link file");
+        fs::write(tool_2.join("other"), vec![0u8; 100]).expect("Failed to write file");
+
+        let dir_size = |path: &Path| fs::symlink_metadata(path).unwrap().len();
+
+        let (compatibility_tools, total) = measure_compatibility_tools(
+            &[compatibility_tools_directory.path().to_path_buf()],
+            &[tool_1.clone(), tool_2.clone()],
+        )
+        .expect("Failed to measure disk usage");
+
+        let tool_1_usage = compatibility_tools[&tool_1.to_string_lossy().to_string()];
+        let tool_2_usage = compatibility_tools[&tool_2.to_string_lossy().to_string()];
+        assert_eq!(tool_1_usage.apparent_bytes, dir_size(&tool_1) + 4096);
+        assert_eq!(tool_2_usage.apparent_bytes, dir_size(&tool_2) + 4096 + 100);
+        assert_eq!(
+            total.apparent_bytes,
+            dir_size(compatibility_tools_directory.path())
+                + dir_size(&tool_1)
+                + dir_size(&tool_2)
+                + 4096
+                + 100
+        );
+    }
+}
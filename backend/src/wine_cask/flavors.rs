@@ -1,6 +1,8 @@
 use crate::github_util;
 use crate::github_util::Release;
 use crate::wine_cask::app::WineCask;
+use crate::wine_cask::dependencies::ToolDependency;
+use crate::wine_cask::disk_usage::DiskUsage;
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -37,13 +39,38 @@ pub struct Flavor {
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SteamCompatibilityTool {
     pub path: String,
+    /// Where Steam runs the tool from, differs from `path` for tools sharing a directory.
+    #[serde(default)]
+    pub install_path: String,
     //pub directory_name: String,
     pub display_name: String,
+    /// The display name the tool shipped with, set once it was renamed.
+    #[serde(default)]
+    pub original_display_name: Option<String>,
     pub internal_name: String,
+    #[serde(default)]
+    pub from_os_list: String,
+    #[serde(default)]
+    pub to_os_list: String,
     pub used_by_games: Vec<String>,
     pub requires_restart: bool,
     pub flavor: CompatibilityToolFlavor,
     pub github_release: Option<Release>,
+    pub disk_usage: Option<DiskUsage>,
+    /// Prefix version this tool writes, used to warn about prefix downgrades.
+    pub prefix_version: Option<String>,
+    /// Id of the Steam installation the tool is installed in.
+    #[serde(default)]
+    pub steam_installation: String,
+    /// App id of official tools Steam installs and updates as apps.
+    #[serde(default)]
+    pub app_id: Option<u64>,
+    /// Official tools are managed by Steam and can't be uninstalled or changed.
+    #[serde(default)]
+    pub read_only: bool,
+    /// The runtime the tool requires but that isn't installed, games using it fail to launch.
+    #[serde(default)]
+    pub missing_dependency: Option<ToolDependency>,
     //pub r#virtual: bool,
     //pub virtual_original: String, // Display name or Internal name or name?
 }
@@ -132,13 +159,17 @@ impl WineCask {
             let github_releases = flavor.releases.clone();
 
             for steam_compat_tool in &mut installed_compatibility_tools {
+                // Renamed tools are still recognized by the name they shipped with
+                let display_name = steam_compat_tool
+                    .original_display_name
+                    .clone()
+                    .unwrap_or(steam_compat_tool.display_name.clone());
                 if let Some(release) = github_releases.iter().find(|gh| {
                     if compatibility_tool_flavor == CompatibilityToolFlavor::ProtonGE {
                         steam_compat_tool.internal_name == gh.tag_name
-                            || steam_compat_tool.display_name == gh.tag_name
+                            || display_name == gh.tag_name
                     } else {
-                        steam_compat_tool.display_name
-                            == compatibility_tool_flavor.to_string() + " " + &gh.tag_name
+                        display_name == compatibility_tool_flavor.to_string() + " " + &gh.tag_name
                             || steam_compat_tool.internal_name
                                 == compatibility_tool_flavor.to_string() + &gh.tag_name
                     }
@@ -154,10 +185,14 @@ impl WineCask {
                 .iter()
                 .filter(|gh| {
                     !installed_compatibility_tools.iter().any(|tool| {
+                        let display_name = tool
+                            .original_display_name
+                            .as_ref()
+                            .unwrap_or(&tool.display_name);
                         if compatibility_tool_flavor == CompatibilityToolFlavor::ProtonGE {
-                            tool.internal_name == gh.tag_name || tool.display_name == gh.tag_name
+                            tool.internal_name == gh.tag_name || *display_name == gh.tag_name
                         } else {
-                            tool.display_name
+                            *display_name
                                 == compatibility_tool_flavor.to_string() + " " + &gh.tag_name
                                 || tool.internal_name
                                     == compatibility_tool_flavor.to_string() + &gh.tag_name
@@ -173,7 +208,7 @@ impl WineCask {
         }
     }
 
-    async fn get_releases(
+    pub async fn get_releases(
         &self,
         owner: &str,
         repository: &str,
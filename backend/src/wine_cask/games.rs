@@ -0,0 +1,146 @@
+use crate::steam_util::{AppInfo, LibraryFolder, LibraryFolderStatus, SteamApp, SteamUtil};
+use crate::wine_cask::app::WineCask;
+use crate::wine_cask::steam_installations::{get_active_user, SteamInstallationUser};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::time::SystemTime;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SteamGame {
+    pub app_id: u64,
+    pub name: String,
+    pub steam_installation: String,
+    /// Whether the game has a Linux build and runs without a compatibility tool.
+    pub native_linux: bool,
+    /// Tool the game is mapped to in Steam.
+    pub compatibility_tool: Option<String>,
+    /// Missing for non-Steam shortcuts and games Steam hasn't cached yet.
+    pub app_info: Option<AppInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SteamLibraryFolder {
+    #[serde(flatten)]
+    pub library_folder: LibraryFolder,
+    pub steam_installation: String,
+    pub status: LibraryFolderStatus,
+    pub error: Option<String>,
+}
+
+/// Parsed `appinfo.vdf` of a Steam installation, the file is large and rarely changes.
+#[derive(Clone)]
+pub struct AppInfoCache {
+    modified: Option<SystemTime>,
+    app_ids: Vec<u64>,
+    app_infos: HashMap<u64, AppInfo>,
+}
+
+fn read_app_infos_cached(
+    steam_util: &SteamUtil,
+    app_ids: &[u64],
+    cache: Option<AppInfoCache>,
+) -> AppInfoCache {
+    let modified = fs::metadata(steam_util.get_app_info_file())
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if let Some(cache) = cache {
+        if cache.modified == modified && app_ids.iter().all(|app_id| cache.app_ids.contains(app_id))
+        {
+            return cache;
+        }
+    }
+    let app_infos = steam_util.read_app_infos(app_ids).unwrap_or_else(|err| {
+        warn!("Failed to read app info: {}", err);
+        HashMap::new()
+    });
+    AppInfoCache {
+        modified,
+        app_ids: app_ids.to_vec(),
+        app_infos,
+    }
+}
+
+impl WineCask {
+    /// Lists the installed games of every managed Steam installation with their metadata, along
+    /// with the state of the library folders they were found in.
+    pub async fn update_installed_games(&self) {
+        let mut installed_games: Vec<SteamGame> = Vec::new();
+        let mut library_folders: Vec<SteamLibraryFolder> = Vec::new();
+        let mut steam_users: Vec<SteamInstallationUser> = Vec::new();
+        for steam_installation in self.managed_steam_installations() {
+            let steam_util = SteamUtil::new(steam_installation.path.clone());
+            let active_user = get_active_user(&steam_util);
+            match steam_util.list_users() {
+                Ok(users) => {
+                    steam_users.extend(users.into_iter().map(|user| SteamInstallationUser {
+                        steam_installation: steam_installation.id.clone(),
+                        active: active_user.as_ref() == Some(&user),
+                        user,
+                    }))
+                }
+                Err(err) => warn!("Failed to list Steam users: {}", err),
+            }
+            let mut games: Vec<SteamApp> = Vec::new();
+            match steam_util.list_installed_games_by_library() {
+                Ok(libraries) => {
+                    for library in libraries {
+                        library_folders.push(SteamLibraryFolder {
+                            library_folder: library.library_folder,
+                            steam_installation: steam_installation.id.clone(),
+                            status: library.status,
+                            error: library.error,
+                        });
+                        games.extend(library.games);
+                    }
+                }
+                Err(err) => warn!("Failed to get list of installed games: {}", err),
+            }
+            if let Some(active_user) = &active_user {
+                match steam_util.list_shortcuts(active_user) {
+                    Ok(shortcuts) => games.extend(shortcuts),
+                    Err(err) => warn!("Failed to list non-Steam shortcuts: {}", err),
+                }
+            }
+            let compat_tools_mapping = steam_util
+                .get_compatibility_tools_mappings()
+                .unwrap_or_else(|err| {
+                    warn!("Failed to get compatibility tools mappings: {}", err);
+                    HashMap::new()
+                });
+            let app_ids: Vec<u64> = games.iter().map(|game| game.app_id).collect();
+
+            let cache = self
+                .app_state
+                .lock()
+                .await
+                .app_info_cache
+                .remove(&steam_installation.id);
+            let cache = read_app_infos_cached(&steam_util, &app_ids, cache);
+
+            for game in games {
+                let app_info = cache.app_infos.get(&game.app_id).cloned();
+                installed_games.push(SteamGame {
+                    app_id: game.app_id,
+                    name: game.name,
+                    steam_installation: steam_installation.id.clone(),
+                    native_linux: app_info
+                        .as_ref()
+                        .is_some_and(|app_info| app_info.os_list.iter().any(|os| os == "linux")),
+                    compatibility_tool: compat_tools_mapping.get(&game.app_id).cloned(),
+                    app_info,
+                });
+            }
+            self.app_state
+                .lock()
+                .await
+                .app_info_cache
+                .insert(steam_installation.id.clone(), cache);
+        }
+        let mut app_state = self.app_state.lock().await;
+        app_state.installed_games = installed_games;
+        app_state.library_folders = library_folders;
+        app_state.steam_users = steam_users;
+    }
+}
@@ -0,0 +1,377 @@
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::PeerMap;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, io};
+
+/// Older entries are dropped once the history grows past this.
+const MAX_HISTORY_ENTRIES: usize = 1000;
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+/// Tasks finishing at the same time would otherwise race for the same id.
+static HISTORY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum HistoryResult {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// The outcome of a task that changed installed tools.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub task_type: TaskType,
+    /// Flavor or display name of the tool the task worked on.
+    pub tool: Option<String>,
+    pub release: Option<String>,
+    pub started: u64,
+    pub finished: u64,
+    pub result: HistoryResult,
+    pub error: Option<String>,
+    pub bytes_downloaded: u64,
+}
+
+/// Which page of the history to show, filters left empty match everything.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub offset: usize,
+    /// Number of entries per page, defaults to 50.
+    pub limit: Option<usize>,
+    pub task_type: Option<TaskType>,
+    pub result: Option<HistoryResult>,
+    /// Matches tool and release names containing it, ignoring case.
+    pub search: Option<String>,
+    /// Only entries started at or after this time, in seconds since the epoch.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+/// A page of history entries, newest first.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HistoryPage {
+    pub query: HistoryQuery,
+    pub entries: Vec<HistoryEntry>,
+    /// Number of entries matching the filters across all pages.
+    pub total: usize,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to calculate duration")
+        .as_secs()
+}
+
+fn get_history_file() -> PathBuf {
+    PathBuf::from(
+        env::var("DECKY_PLUGIN_RUNTIME_DIR").unwrap_or("/tmp/decky-wine-cellar".to_string()),
+    )
+    .join("history.jsonl")
+}
+
+impl HistoryEntry {
+    /// Starts timing a task, the entry is completed with `finish`.
+    pub fn start(task_type: TaskType, tool: Option<String>, release: Option<String>) -> Self {
+        HistoryEntry {
+            id: 0,
+            task_type,
+            tool,
+            release,
+            started: now(),
+            finished: 0,
+            result: HistoryResult::Succeeded,
+            error: None,
+            bytes_downloaded: 0,
+        }
+    }
+
+    pub fn finish(mut self, result: &Result<(), String>) -> Self {
+        self.finished = now();
+        match result {
+            Ok(_) => self.result = HistoryResult::Succeeded,
+            Err(err) => {
+                self.result = HistoryResult::Failed;
+                self.error = Some(err.clone());
+            }
+        }
+        self
+    }
+
+    pub fn cancel(mut self) -> Self {
+        self.finished = now();
+        self.result = HistoryResult::Cancelled;
+        self
+    }
+
+    fn matches(&self, query: &HistoryQuery) -> bool {
+        let search = query.search.as_deref().map(str::to_lowercase);
+        let contains_search = |value: &Option<String>| match (&search, value) {
+            (Some(search), Some(value)) => value.to_lowercase().contains(search),
+            _ => false,
+        };
+        query
+            .task_type
+            .as_ref()
+            .is_none_or(|task_type| &self.task_type == task_type)
+            && query.result.is_none_or(|result| self.result == result)
+            && (search.is_none() || contains_search(&self.tool) || contains_search(&self.release))
+            && query.since.is_none_or(|since| self.started >= since)
+            && query.until.is_none_or(|until| self.started <= until)
+    }
+}
+
+/// Reads the history file, skipping lines that can't be parsed rather than losing the rest.
+pub fn read_history(path: &Path) -> Vec<HistoryEntry> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            serde_json::from_str(line)
+                .map_err(|err| warn!("Skipping unreadable history entry: {}", err))
+                .ok()
+        })
+        .collect()
+}
+
+/// Appends an entry to the history file, one json object per line, and returns it with its id.
+pub fn append_history_entry(path: &Path, mut entry: HistoryEntry) -> io::Result<HistoryEntry> {
+    let _lock = HISTORY_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    let mut entries = read_history(path);
+    entry.id = entries.last().map(|last| last.id + 1).unwrap_or(1);
+
+    if entries.len() >= MAX_HISTORY_ENTRIES {
+        entries.drain(..entries.len() + 1 - MAX_HISTORY_ENTRIES);
+        entries.push(entry.clone());
+        let mut contents = String::new();
+        for entry in &entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        // Write to a temporary file first so a crash can't leave a truncated history behind
+        let temporary_file = path.with_extension("jsonl.tmp");
+        fs::write(&temporary_file, contents)?;
+        fs::rename(&temporary_file, path)?;
+    } else {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    }
+    Ok(entry)
+}
+
+pub fn query_history(entries: &[HistoryEntry], query: &HistoryQuery) -> HistoryPage {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let matching: Vec<&HistoryEntry> = entries
+        .iter()
+        .rev()
+        .filter(|entry| entry.matches(query))
+        .collect();
+    HistoryPage {
+        query: HistoryQuery {
+            limit: Some(limit),
+            ..query.clone()
+        },
+        total: matching.len(),
+        entries: matching
+            .into_iter()
+            .skip(query.offset)
+            .take(limit)
+            .cloned()
+            .collect(),
+    }
+}
+
+impl WineCask {
+    /// Saves the outcome of a task, and refreshes the page the frontend is showing.
+    pub async fn record_history(&self, entry: HistoryEntry, peer_map: &PeerMap) {
+        let result =
+            tokio::task::spawn_blocking(move || append_history_entry(&get_history_file(), entry))
+                .await
+                .unwrap();
+        if let Err(err) = result {
+            error!("Failed to save history entry: {}", err);
+            return;
+        }
+
+        let query = self
+            .app_state
+            .lock()
+            .await
+            .history
+            .as_ref()
+            .map(|history| history.query.clone());
+        if let Some(query) = query {
+            self.get_history(query, peer_map).await;
+        }
+    }
+
+    /// Names a game in the history, games that aren't installed anymore by their app id.
+    pub(crate) async fn get_history_game_name(&self, app_id: u64) -> String {
+        self.app_state
+            .lock()
+            .await
+            .installed_games
+            .iter()
+            .find(|game| game.app_id == app_id)
+            .map(|game| game.name.clone())
+            .unwrap_or_else(|| app_id.to_string())
+    }
+
+    pub async fn get_history(&self, query: HistoryQuery, peer_map: &PeerMap) {
+        let history = tokio::task::spawn_blocking(move || {
+            query_history(&read_history(&get_history_file()), &query)
+        })
+        .await
+        .unwrap();
+        self.app_state.lock().await.history = Some(history);
+        self.broadcast_app_state(peer_map).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(task_type: TaskType, tool: &str, release: &str, started: u64) -> HistoryEntry {
+        HistoryEntry {
+            started,
+            finished: started + 10,
+            ..HistoryEntry::start(task_type, Some(tool.to_string()), Some(release.to_string()))
+        }
+    }
+
+    #[test]
+    fn test_append_and_query_history() {
+        let runtime_dir = tempdir().expect("Failed to create temporary directory");
+        let history_file = runtime_dir.path().join("history.jsonl");
+
+        let installed = append_history_entry(
+            &history_file,
+            entry(
+                TaskType::InstallCompatibilityTool,
+                "ProtonGE",
+                "GE-Proton9-20",
+                100,
+            ),
+        )
+        .unwrap();
+        assert_eq!(installed.id, 1);
+        append_history_entry(
+            &history_file,
+            entry(
+                TaskType::InstallCompatibilityTool,
+                "ProtonGE",
+                "GE-Proton9-21",
+                200,
+            )
+            .finish(&Err("Connection Error".to_string())),
+        )
+        .unwrap();
+        fs::write(
+            &history_file,
+            fs::read_to_string(&history_file).unwrap() + "{ not json\n",
+        )
+        .unwrap();
+        append_history_entry(
+            &history_file,
+            entry(
+                TaskType::UninstallCompatibilityTool,
+                "GE-Proton9-20",
+                "",
+                300,
+            ),
+        )
+        .unwrap();
+
+        let entries = read_history(&history_file);
+        assert_eq!(
+            entries.iter().map(|entry| entry.id).collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+
+        let page = query_history(&entries, &HistoryQuery::default());
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries[0].id, 3);
+        assert_eq!(page.query.limit, Some(DEFAULT_PAGE_SIZE));
+
+        let page = query_history(
+            &entries,
+            &HistoryQuery {
+                offset: 1,
+                limit: Some(1),
+                ..HistoryQuery::default()
+            },
+        );
+        assert_eq!(page.total, 3);
+        assert_eq!(
+            page.entries
+                .iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<u64>>(),
+            vec![2]
+        );
+
+        let page = query_history(
+            &entries,
+            &HistoryQuery {
+                result: Some(HistoryResult::Failed),
+                ..HistoryQuery::default()
+            },
+        );
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].error.as_deref(), Some("Connection Error"));
+
+        let page = query_history(
+            &entries,
+            &HistoryQuery {
+                task_type: Some(TaskType::InstallCompatibilityTool),
+                search: Some("proton9-20".to_string()),
+                since: Some(50),
+                until: Some(150),
+                ..HistoryQuery::default()
+            },
+        );
+        assert_eq!(
+            page.entries
+                .iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<u64>>(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_history_is_trimmed() {
+        let runtime_dir = tempdir().expect("Failed to create temporary directory");
+        let history_file = runtime_dir.path().join("history.jsonl");
+        for started in 0..MAX_HISTORY_ENTRIES as u64 + 5 {
+            append_history_entry(
+                &history_file,
+                entry(TaskType::DeduplicateCompatibilityTools, "", "", started),
+            )
+            .unwrap();
+        }
+        let entries = read_history(&history_file);
+        assert_eq!(entries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(entries.first().unwrap().id, 6);
+        assert_eq!(entries.last().unwrap().id, MAX_HISTORY_ENTRIES as u64 + 5);
+    }
+}
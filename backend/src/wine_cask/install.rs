@@ -1,15 +1,17 @@
 use crate::github_util::{Asset, Release};
-use crate::wine_cask::app::WineCask;
+use crate::wine_cask::app::{TaskType, WineCask};
 use crate::wine_cask::flavors::CompatibilityToolFlavor;
+use crate::wine_cask::history::HistoryEntry;
 use crate::wine_cask::{copy_dir, generate_compatibility_tool_vdf, recursive_delete_dir_entry};
 use crate::PeerMap;
 use flate2::bufread::GzDecoder;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
+use ruzstd::decoding::StreamingDecoder;
 use serde::{Deserialize, Serialize};
 use std::env;
 use std::fs::create_dir_all;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read};
 use std::path::{Path, PathBuf};
 use xz2::bufread::XzDecoder;
 
@@ -41,79 +43,136 @@ pub enum QueueCompatibilityToolState {
 pub enum CompressionType {
     Gzip,
     Xz,
+    Zstd,
     Unknown,
 }
 
 impl WineCask {
     // Why is this task queue here? Well because steam deck will die if someone tries to queue up 50 installs at once.
     pub async fn install_compatibility_tool(&self, install: Install, peer_map: &PeerMap) {
+        let mut history_entry = HistoryEntry::start(
+            TaskType::InstallCompatibilityTool,
+            Some(install.flavor.to_string()),
+            Some(install.release.tag_name.clone()),
+        );
         if let Some(mut queue_compatibility_tool) = look_for_compressed_archive(&install) {
-            // Mark as downloading...
-            queue_compatibility_tool.state = QueueCompatibilityToolState::Downloading;
-            queue_compatibility_tool.progress = 0;
-            self.app_state.lock().await.in_progress = Some(queue_compatibility_tool.clone());
-            self.broadcast_app_state(peer_map).await;
-
-            // Starting download compatibility tool
-            let client = reqwest::Client::new();
-            let response_wrapped = client.get(&queue_compatibility_tool.url).send().await;
-            let response = response_wrapped.unwrap();
-            let total_size = response.content_length().unwrap_or(0);
-
-            let mut downloaded_bytes = Vec::new();
-            let mut downloaded_size = 0;
-            let mut body = response.bytes_stream();
-
-            while let Some(chunk_result) = body.next().await {
-                // Check if we need to cancel the download
-                if self
-                    .app_state
-                    .lock()
-                    .await
-                    .in_progress
-                    .clone()
-                    .unwrap()
-                    .state
-                    == QueueCompatibilityToolState::Cancelling
-                {
-                    self.app_state.lock().await.in_progress = None;
-                    self.broadcast_app_state(peer_map).await;
+            let downloaded_bytes = match self
+                .download_archive(&mut queue_compatibility_tool, &mut history_entry, peer_map)
+                .await
+            {
+                Ok(Some(downloaded_bytes)) => downloaded_bytes,
+                Ok(None) => {
+                    self.record_history(history_entry.cancel(), peer_map).await;
                     return; // We stop the function here
                 }
-                if let Ok(chunk) = chunk_result {
-                    downloaded_bytes.extend_from_slice(&chunk);
-                    downloaded_size += chunk.len() as u64;
-
-                    let progress = ((downloaded_size as f64 / total_size as f64) * 100.0) as u8;
-                    if queue_compatibility_tool.progress != progress {
-                        // Update progress...
-                        queue_compatibility_tool.progress = progress;
-                        self.app_state.lock().await.in_progress =
-                            Some(queue_compatibility_tool.clone());
-                        self.broadcast_app_state(peer_map).await;
-                    }
-                } else {
-                    let error_message =
-                        "Connection Error: Download in progress failed!".to_string();
+                Err(error_message) => {
                     error!("{}", error_message);
-                    self.app_state.lock().await.in_progress = None;
-                    self.broadcast_app_state(peer_map).await;
-                    self.broadcast_notification(peer_map, error_message.as_str())
+                    self.broadcast_notification(peer_map, &error_message).await;
+                    self.record_history(history_entry.finish(&Err(error_message)), peer_map)
                         .await;
                     return;
                 }
-            }
+            };
 
             let reader = Cursor::new(downloaded_bytes);
 
-            self.extract_generate_and_move(
-                peer_map,
-                &install,
-                &mut queue_compatibility_tool,
-                reader,
-            )
+            let result = self
+                .extract_generate_and_move(
+                    peer_map,
+                    &install,
+                    &mut queue_compatibility_tool,
+                    reader,
+                )
+                .await;
+            self.record_history(history_entry.finish(&result), peer_map)
+                .await;
+        } else {
+            let error_message = format!("No archive found in release: {}", install.release.name);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            self.record_history(history_entry.finish(&Err(error_message)), peer_map)
+                .await;
+        }
+    }
+
+    /// Downloads an archive into memory while reporting progress in `in_progress`. Returns
+    /// `None` if the download was cancelled, `in_progress` is cleared unless it succeeded.
+    pub(crate) async fn download_archive(
+        &self,
+        queue_compatibility_tool: &mut QueueCompatibilityTool,
+        history_entry: &mut HistoryEntry,
+        peer_map: &PeerMap,
+    ) -> Result<Option<Vec<u8>>, String> {
+        // Mark as downloading...
+        queue_compatibility_tool.state = QueueCompatibilityToolState::Downloading;
+        queue_compatibility_tool.progress = 0;
+        self.app_state.lock().await.in_progress = Some(queue_compatibility_tool.clone());
+        self.broadcast_app_state(peer_map).await;
+
+        let result = self
+            .stream_archive(queue_compatibility_tool, history_entry, peer_map)
             .await;
-        } 
+        if !matches!(result, Ok(Some(_))) {
+            self.app_state.lock().await.in_progress = None;
+            self.broadcast_app_state(peer_map).await;
+        }
+        result
+    }
+
+    async fn stream_archive(
+        &self,
+        queue_compatibility_tool: &mut QueueCompatibilityTool,
+        history_entry: &mut HistoryEntry,
+        peer_map: &PeerMap,
+    ) -> Result<Option<Vec<u8>>, String> {
+        let client = reqwest::Client::new();
+        let response = client
+            .get(&queue_compatibility_tool.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("Connection Error: {}", err))?;
+        let total_size = response.content_length().unwrap_or(0);
+
+        let mut downloaded_bytes = Vec::new();
+        let mut body = response.bytes_stream();
+
+        while let Some(chunk_result) = body.next().await {
+            // Check if we need to cancel the download
+            if self
+                .app_state
+                .lock()
+                .await
+                .in_progress
+                .as_ref()
+                .is_some_and(|in_progress| {
+                    in_progress.state == QueueCompatibilityToolState::Cancelling
+                })
+            {
+                return Ok(None);
+            }
+            let chunk = chunk_result
+                .map_err(|_| "Connection Error: Download in progress failed!".to_string())?;
+            downloaded_bytes.extend_from_slice(&chunk);
+            history_entry.bytes_downloaded = downloaded_bytes.len() as u64;
+
+            let progress = ((downloaded_bytes.len() as f64 / total_size as f64) * 100.0) as u8;
+            if queue_compatibility_tool.progress != progress {
+                // Update progress...
+                queue_compatibility_tool.progress = progress;
+                self.app_state.lock().await.in_progress = Some(queue_compatibility_tool.clone());
+                self.broadcast_app_state(peer_map).await;
+            }
+        }
+
+        if total_size != 0 && downloaded_bytes.len() as u64 != total_size {
+            return Err(format!(
+                "Connection Error: Downloaded {} of {} bytes",
+                downloaded_bytes.len(),
+                total_size
+            ));
+        }
+        Ok(Some(downloaded_bytes))
     }
 
     pub async fn extract_generate_and_move(
@@ -122,31 +181,29 @@ impl WineCask {
         install: &Install,
         queue_compatibility_tool: &mut QueueCompatibilityTool,
         reader: Cursor<Vec<u8>>,
-    ) {
-        if let Some(temp_dir) = prepare_temp_directory() {
+    ) -> Result<(), String> {
+        let result = if let Some(temp_dir) = prepare_temp_directory() {
             // Mark as extracting...
             queue_compatibility_tool.state = QueueCompatibilityToolState::Extracting;
             queue_compatibility_tool.progress = 0;
             self.app_state.lock().await.in_progress = Some(queue_compatibility_tool.clone());
             self.broadcast_app_state(peer_map).await;
 
-            let steam_compatibility_tools_directory =
-                self.steam_util.get_steam_compatibility_tools_directory();
+            let steam_compatibility_tools_directories: Vec<PathBuf> = self
+                .steam_utils()
+                .iter()
+                .map(|steam_util| steam_util.get_steam_compatibility_tools_directory())
+                .collect();
             // Spawn a new thread for the extraction process
             // Why do we need this turns out unpack process is blocking, because of this async function doesn't yield control back to Rust runtime until the extraction is finished.
             let queue_compatibility_tool_clone = queue_compatibility_tool.clone(); // Clone the queue_compatibility_tool
             let temp_dir_clone = temp_dir.clone();
-            tokio::task::spawn_blocking(move || {
-                let decompressed: Box<dyn Read> =
-                    if queue_compatibility_tool_clone.compress_type == CompressionType::Gzip {
-                        Box::new(GzDecoder::new(reader))
-                    } else if queue_compatibility_tool_clone.compress_type == CompressionType::Xz {
-                        Box::new(XzDecoder::new(reader))
-                    } else {
-                        Box::new(reader) // fixme: explosion
-                    };
-                let mut tar = tar::Archive::new(decompressed);
-                tar.unpack(temp_dir_clone).unwrap();
+            let unpacked = tokio::task::spawn_blocking(move || {
+                unpack_archive(
+                    reader,
+                    &queue_compatibility_tool_clone.compress_type,
+                    &temp_dir_clone,
+                )
             })
             .await
             .unwrap();
@@ -165,7 +222,9 @@ impl WineCask {
                 .map(|x| x.path())
                 .collect();
 
-            if valid_directories.len() == 1 {
+            let result = if let Err(err) = unpacked {
+                Err(format!("Failed to extract archive: {}", err))
+            } else if valid_directories.len() == 1 {
                 let first = valid_directories.first().unwrap();
                 let new_compat_tool_vdf = first.join("compatibilitytool.vdf");
                 let new_path = match queue_compatibility_tool.flavor {
@@ -194,29 +253,77 @@ impl WineCask {
                 };
                 std::fs::rename(first, &new_path).unwrap();
 
-                match copy_dir(&temp_dir, &steam_compatibility_tools_directory) {
-                    Ok(_) => debug!("Directory copied successfully."),
-                    Err(e) => error!("Failed to copy directory: {}", e),
-                }
+                // A reinstall copies over an existing tool, only new directories can be rolled back
+                let tool_directory_name = new_path.file_name().unwrap();
+                let new_tool_directories: Vec<PathBuf> = steam_compatibility_tools_directories
+                    .iter()
+                    .map(|directory| directory.join(tool_directory_name))
+                    .filter(|directory| !directory.exists())
+                    .collect();
+                self.set_partial_directories(new_tool_directories.clone())
+                    .await;
 
+                // Every managed Steam installation gets its own copy of the tool
+                let copied = steam_compatibility_tools_directories.iter().try_for_each(
+                    |steam_compatibility_tools_directory| {
+                        copy_dir(&temp_dir, steam_compatibility_tools_directory)
+                            .map(|_| debug!("Directory copied successfully."))
+                            .map_err(|err| {
+                                format!(
+                                    "Failed to copy to {}: {}",
+                                    steam_compatibility_tools_directory.display(),
+                                    err
+                                )
+                            })
+                    },
+                );
+
+                // Don't leave a half copied tool in any of the installations
+                if copied.is_err() {
+                    for new_tool_directory in &new_tool_directories {
+                        if new_tool_directory.exists() {
+                            if let Err(err) = recursive_delete_dir_entry(new_tool_directory) {
+                                error!(
+                                    "Failed to remove {}: {}",
+                                    new_tool_directory.display(),
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
+                self.set_partial_directories(Vec::new()).await;
                 self.sync_backend_with_installed_compat_tools().await;
                 self.broadcast_app_state(peer_map).await;
+                copied
             } else {
-                error!("Failed to find extracted directory");
-            }
+                Err("Failed to find extracted directory".to_string())
+            };
 
             cleanup_temp_directory(&temp_dir);
-
-            // Mark as completed
-            let message = format!("Installation Completed: {}", install.release.name);
-            info!("{}", message);
-            self.broadcast_notification(peer_map, message.as_str())
-                .await;
-            self.app_state.lock().await.in_progress = None;
-            self.broadcast_app_state(peer_map).await;
+            result
         } else {
-            error!("Failed to prepare temp directory");
+            Err("Failed to prepare temp directory".to_string())
+        };
+
+        match &result {
+            Ok(_) => {
+                // Mark as completed
+                let message = format!("Installation Completed: {}", install.release.name);
+                info!("{}", message);
+                self.broadcast_notification(peer_map, message.as_str())
+                    .await;
+            }
+            Err(err) => {
+                let error_message = format!("Installation Failed: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
         }
+        self.app_state.lock().await.in_progress = None;
+        self.broadcast_app_state(peer_map).await;
+        self.update_disk_usage(peer_map).await;
+        result
     }
 }
 
@@ -245,48 +352,65 @@ fn cleanup_temp_directory(temp_dir: &Path) {
     }
 }
 
-pub fn look_for_compressed_archive(install_request: &Install) -> Option<QueueCompatibilityTool> {
-    /*if install_request.flavor == CompatibilityToolFlavor::SteamTinkerLaunch {// fixme: doesn't actually work we need to handle this STL separately
-        return Some(QueueCompatibilityTool {
-            flavor: install_request.flavor.to_owned(),
-            name: install_request.release.tag_name.to_owned(),
-            url: format!("https://codeload.github.com/sonic2kk/steamtinkerlaunch/legacy.tar.gz/refs/tags/{}", install_request.release.tag_name), //install_request.release.tarball_url.to_owned(),
-            state: QueueCompatibilityToolState::Waiting,
-            compress_type: CompressionType::Gzip,
-            progress: 0,
-        });
-    }*/
-
-    let is_compressed = |asset: &Asset| {
-        asset.content_type == "application/gzip"
-            || asset.content_type == "application/x-xz"
-            || asset.name.ends_with(".tar.gz")
-            || asset.name.ends_with(".tar.xz")
+/// Unpacks a downloaded tarball into `destination`.
+pub fn unpack_archive(
+    reader: Cursor<Vec<u8>>,
+    compress_type: &CompressionType,
+    destination: &Path,
+) -> io::Result<()> {
+    let decompressed: Box<dyn Read> = match compress_type {
+        CompressionType::Gzip => Box::new(GzDecoder::new(reader)),
+        CompressionType::Xz => Box::new(XzDecoder::new(reader)),
+        CompressionType::Zstd => Box::new(
+            StreamingDecoder::new(reader)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        ),
+        CompressionType::Unknown => Box::new(reader), // fixme: explosion
     };
+    let mut tar = tar::Archive::new(decompressed);
+    tar.unpack(destination)
+}
 
+/// The first tarball attached to a release, along with how it's compressed.
+pub fn find_compressed_asset(release: &Release) -> Option<(Asset, CompressionType)> {
     let compress_type = |asset: &Asset| {
         if asset.content_type == "application/gzip" || asset.name.ends_with(".tar.gz") {
             CompressionType::Gzip
         } else if asset.content_type == "application/x-xz" || asset.name.ends_with(".tar.xz") {
             CompressionType::Xz
+        } else if asset.content_type == "application/zstd" || asset.name.ends_with(".tar.zst") {
+            CompressionType::Zstd
         } else {
             CompressionType::Unknown
         }
     };
 
-    if let Some(asset) = install_request
-        .release
+    release
         .assets
-        .clone()
-        .into_iter()
-        .find(is_compressed)
-    {
+        .iter()
+        .find(|asset| compress_type(asset) != CompressionType::Unknown)
+        .map(|asset| (asset.clone(), compress_type(asset)))
+}
+
+pub fn look_for_compressed_archive(install_request: &Install) -> Option<QueueCompatibilityTool> {
+    /*if install_request.flavor == CompatibilityToolFlavor::SteamTinkerLaunch {// fixme: doesn't actually work we need to handle this STL separately
+        return Some(QueueCompatibilityTool {
+            flavor: install_request.flavor.to_owned(),
+            name: install_request.release.tag_name.to_owned(),
+            url: format!("https://codeload.github.com/sonic2kk/steamtinkerlaunch/legacy.tar.gz/refs/tags/{}", install_request.release.tag_name), //install_request.release.tarball_url.to_owned(),
+            state: QueueCompatibilityToolState::Waiting,
+            compress_type: CompressionType::Gzip,
+            progress: 0,
+        });
+    }*/
+
+    if let Some((asset, compress_type)) = find_compressed_asset(&install_request.release) {
         return Some(QueueCompatibilityTool {
             flavor: install_request.flavor.to_owned(),
             name: install_request.release.tag_name.to_owned(),
-            url: asset.clone().browser_download_url,
+            url: asset.browser_download_url,
             state: QueueCompatibilityToolState::Waiting,
-            compress_type: compress_type(&asset),
+            compress_type,
             progress: 0,
         });
     }
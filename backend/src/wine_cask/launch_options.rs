@@ -0,0 +1,380 @@
+use crate::steam_util::{SteamUser, SteamUtil};
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_cask::steam_installations::get_active_user;
+use crate::PeerMap;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+/// Environment variables offered as toggles, everything else is kept as typed by the user.
+pub const KNOWN_PROTON_ENVIRONMENT_VARIABLES: &[&str] = &[
+    "PROTON_LOG",
+    "DXVK_HUD",
+    "PROTON_ENABLE_WAYLAND",
+    "PROTON_ENABLE_HDR",
+    "PROTON_USE_WINED3D",
+    "PROTON_NO_ESYNC",
+    "PROTON_NO_FSYNC",
+    "PROTON_ENABLE_NVAPI",
+    "PROTON_HIDE_NVIDIA_GPU",
+    "VKD3D_CONFIG",
+    "MANGOHUD",
+];
+
+const COMMAND_PLACEHOLDER: &str = "%command%";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ProtonEnvironmentVariable {
+    pub name: String,
+    /// `None` removes the variable from the launch options.
+    pub value: Option<String>,
+}
+
+/// Launch options of a game, both as reported to and as requested by the frontend.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GameLaunchOptions {
+    pub app_id: u64,
+    /// The raw launch options, replaces the current ones when set in an update.
+    pub launch_options: Option<String>,
+    /// The known Proton environment variables, changes are applied on top of the launch options.
+    #[serde(default)]
+    pub environment: Vec<ProtonEnvironmentVariable>,
+}
+
+/// Launch options split into `VAR=value` assignments and wrappers before `%command%` and the
+/// arguments after it.
+#[derive(PartialEq, Debug)]
+struct ParsedLaunchOptions {
+    environment: Vec<(String, String)>,
+    wrappers: Vec<String>,
+    arguments: Vec<String>,
+    has_command: bool,
+}
+
+/// Splits at whitespace outside of quotes, keeping the quotes in the arguments.
+fn split_arguments(launch_options: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in launch_options.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match (c, quote) {
+            ('\\', Some('"')) | ('\\', None) => {
+                current.push(c);
+                escaped = true;
+            }
+            ('"', None) | ('\'', None) => {
+                current.push(c);
+                quote = Some(c);
+            }
+            (c, Some(q)) if c == q => {
+                current.push(c);
+                quote = None;
+            }
+            (c, None) if c.is_whitespace() => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+    arguments
+}
+
+fn is_environment_assignment(argument: &str) -> bool {
+    match argument.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')))
+    {
+        value[1..value.len() - 1].replace("\\\"", "\"")
+    } else {
+        value.to_string()
+    }
+}
+
+fn quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.,:/=+@%".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+fn parse_launch_options(launch_options: &str) -> ParsedLaunchOptions {
+    let arguments = split_arguments(launch_options);
+    let Some(command_position) = arguments
+        .iter()
+        .position(|argument| argument == COMMAND_PLACEHOLDER)
+    else {
+        // Without %command% Steam appends everything to the game's command line
+        return ParsedLaunchOptions {
+            environment: Vec::new(),
+            wrappers: Vec::new(),
+            arguments,
+            has_command: false,
+        };
+    };
+
+    let mut environment: Vec<(String, String)> = Vec::new();
+    let mut wrappers: Vec<String> = Vec::new();
+    for argument in &arguments[..command_position] {
+        if wrappers.is_empty() && is_environment_assignment(argument) {
+            let (name, value) = argument.split_once('=').unwrap();
+            environment.push((name.to_string(), unquote(value)));
+        } else {
+            wrappers.push(argument.clone());
+        }
+    }
+    ParsedLaunchOptions {
+        environment,
+        wrappers,
+        arguments: arguments[command_position + 1..].to_vec(),
+        has_command: true,
+    }
+}
+
+fn render_launch_options(parsed: &ParsedLaunchOptions) -> String {
+    if parsed.environment.is_empty() && parsed.wrappers.is_empty() && !parsed.has_command {
+        return parsed.arguments.join(" ");
+    }
+    parsed
+        .environment
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, quote(value)))
+        .chain(parsed.wrappers.iter().cloned())
+        .chain(std::iter::once(COMMAND_PLACEHOLDER.to_string()))
+        .chain(parsed.arguments.iter().cloned())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The current values of the known Proton environment variables.
+pub fn get_proton_environment(launch_options: &str) -> Vec<ProtonEnvironmentVariable> {
+    let parsed = parse_launch_options(launch_options);
+    KNOWN_PROTON_ENVIRONMENT_VARIABLES
+        .iter()
+        .map(|name| ProtonEnvironmentVariable {
+            name: name.to_string(),
+            value: parsed
+                .environment
+                .iter()
+                .find(|(variable, _)| variable == name)
+                .map(|(_, value)| value.clone()),
+        })
+        .collect()
+}
+
+/// The value of an environment variable set before `%command%`, `None` if it isn't set.
+pub fn get_environment_variable(launch_options: &str, name: &str) -> Option<String> {
+    parse_launch_options(launch_options)
+        .environment
+        .into_iter()
+        .find(|(variable, _)| variable == name)
+        .map(|(_, value)| value)
+}
+
+/// Sets or removes environment variables, adding `%command%` if the launch options had none.
+pub fn set_environment_variables(
+    launch_options: &str,
+    environment: &[ProtonEnvironmentVariable],
+) -> String {
+    let mut parsed = parse_launch_options(launch_options);
+    for variable in environment {
+        let position = parsed
+            .environment
+            .iter()
+            .position(|(name, _)| name == &variable.name);
+        match (&variable.value, position) {
+            (Some(value), Some(position)) => parsed.environment[position].1 = value.clone(),
+            (Some(value), None) => {
+                parsed
+                    .environment
+                    .push((variable.name.clone(), value.clone()));
+                parsed.has_command = true;
+            }
+            (None, Some(position)) => {
+                parsed.environment.remove(position);
+            }
+            (None, None) => {}
+        }
+    }
+    render_launch_options(&parsed)
+}
+
+impl WineCask {
+    /// Finds the Steam installation and user whose launch options apply to a game.
+    pub(crate) async fn get_steam_user_for_app(
+        &self,
+        app_id: u64,
+    ) -> Result<(SteamUtil, SteamUser), String> {
+        let steam_installation_id = self
+            .app_state
+            .lock()
+            .await
+            .installed_games
+            .iter()
+            .find(|game| game.app_id == app_id)
+            .map(|game| game.steam_installation.clone());
+        let managed_steam_installations = self.managed_steam_installations();
+        let steam_installation = managed_steam_installations
+            .iter()
+            .find(|installation| Some(&installation.id) == steam_installation_id.as_ref())
+            .or(managed_steam_installations.first())
+            .ok_or_else(|| "No Steam installation found".to_string())?;
+        let steam_util = SteamUtil::new(steam_installation.path.clone());
+        let user = get_active_user(&steam_util)
+            .ok_or_else(|| format!("No Steam user found in {}", steam_installation.id))?;
+        Ok((steam_util, user))
+    }
+
+    pub async fn get_launch_options(&self, app_id: u64, peer_map: &PeerMap) {
+        let result = match self.get_steam_user_for_app(app_id).await {
+            Ok((steam_util, user)) => steam_util
+                .get_launch_options(&user, app_id)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+        let launch_options = match result {
+            Ok(launch_options) => launch_options,
+            Err(err) => {
+                let error_message = format!("Failed to read launch options: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+                return;
+            }
+        };
+
+        self.app_state.lock().await.launch_options = Some(GameLaunchOptions {
+            app_id,
+            environment: get_proton_environment(launch_options.as_deref().unwrap_or_default()),
+            launch_options,
+        });
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    pub async fn update_launch_options(
+        &self,
+        game_launch_options: GameLaunchOptions,
+        peer_map: &PeerMap,
+    ) {
+        let app_id = game_launch_options.app_id;
+        let history_entry = HistoryEntry::start(
+            TaskType::UpdateLaunchOptions,
+            Some(self.get_history_game_name(app_id).await),
+            None,
+        );
+        let result = match self.get_steam_user_for_app(app_id).await {
+            Ok((steam_util, user)) => {
+                let current_launch_options = match game_launch_options.launch_options {
+                    Some(launch_options) => Ok(launch_options),
+                    None => steam_util
+                        .get_launch_options(&user, app_id)
+                        .map(|launch_options| launch_options.unwrap_or_default()),
+                };
+                current_launch_options
+                    .map(|launch_options| {
+                        set_environment_variables(&launch_options, &game_launch_options.environment)
+                    })
+                    .and_then(|launch_options| {
+                        steam_util.set_launch_options(&user, app_id, &launch_options)
+                    })
+                    .map_err(|err| err.to_string())
+            }
+            Err(err) => Err(err),
+        };
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+        if let Err(err) = result {
+            let error_message = format!("Failed to update launch options: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+
+        let message = format!("Launch Options Updated: {}", app_id);
+        info!("{}", message);
+        self.broadcast_notification(peer_map, &message).await;
+        self.get_launch_options(app_id, peer_map).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable(name: &str, value: Option<&str>) -> ProtonEnvironmentVariable {
+        ProtonEnvironmentVariable {
+            name: name.to_string(),
+            value: value.map(|value| value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_set_environment_variables() {
+        assert_eq!(
+            set_environment_variables("", &[variable("PROTON_LOG", Some("1"))]),
+            "PROTON_LOG=1 %command%"
+        );
+        assert_eq!(
+            set_environment_variables("-novid", &[variable("PROTON_LOG", Some("1"))]),
+            "PROTON_LOG=1 %command% -novid"
+        );
+        assert_eq!(
+            set_environment_variables(
+                "PROTON_LOG=1 gamemoderun %command% -novid",
+                &[
+                    variable("PROTON_LOG", None),
+                    variable("DXVK_HUD", Some("fps,frametimes")),
+                ]
+            ),
+            "DXVK_HUD=fps,frametimes gamemoderun %command% -novid"
+        );
+        assert_eq!(
+            set_environment_variables("-novid", &[variable("PROTON_LOG", None)]),
+            "-novid"
+        );
+        assert_eq!(
+            set_environment_variables(
+                "VKD3D_CONFIG=\"dxr11 dxr\" %command%",
+                &[variable("PROTON_ENABLE_WAYLAND", Some("1"))]
+            ),
+            "VKD3D_CONFIG=\"dxr11 dxr\" PROTON_ENABLE_WAYLAND=1 %command%"
+        );
+    }
+
+    #[test]
+    fn test_get_proton_environment() {
+        let environment =
+            get_proton_environment("PROTON_LOG=1 VKD3D_CONFIG=\"dxr11 dxr\" %command%");
+        assert!(environment.contains(&variable("PROTON_LOG", Some("1"))));
+        assert!(environment.contains(&variable("VKD3D_CONFIG", Some("dxr11 dxr"))));
+        assert!(environment.contains(&variable("DXVK_HUD", None)));
+        // Assignments after a wrapper belong to the wrapper's command line
+        let environment = get_proton_environment("gamemoderun PROTON_LOG=1 %command%");
+        assert!(environment.contains(&variable("PROTON_LOG", None)));
+    }
+}
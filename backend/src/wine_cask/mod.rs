@@ -8,12 +8,42 @@ use std::time::Duration;
 use std::{fs, io};
 
 pub mod app;
+pub mod backup;
+pub mod clone;
+pub mod dedup;
+pub mod dependencies;
+pub mod disk_usage;
 pub mod flavors;
+pub mod games;
+pub mod history;
 pub mod install;
+pub mod launch_options;
+pub mod prefix_registry;
+pub mod prefixes;
+pub mod proton_logs;
+pub mod rename;
+pub mod settings;
+pub mod steam_installations;
+pub mod task_state;
+pub mod translation_layers;
 pub mod uninstall;
+pub mod watcher;
 
 pub fn generate_compatibility_tool_vdf(path: PathBuf, internal_name: &str, display_name: &str) {
-    let mut file = File::create(path).expect("Failed to create file");
+    write_compatibility_tool_vdf(&path, internal_name, display_name, "windows", "linux")
+        .expect("Failed to write to file");
+}
+
+/// Writes a `compatibilitytool.vdf` declaring a single tool installed next to it.
+pub fn write_compatibility_tool_vdf(
+    path: &Path,
+    internal_name: &str,
+    display_name: &str,
+    from_os_list: &str,
+    to_os_list: &str,
+) -> io::Result<()> {
+    let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut file = File::create(path)?;
     writeln!(
         file,
         r#""compatibilitytools"
@@ -24,14 +54,16 @@ pub fn generate_compatibility_tool_vdf(path: PathBuf, internal_name: &str, displ
                 {{
                   "install_path" "."
                   "display_name" "{}"
-                  "from_oslist"  "windows"
-                  "to_oslist"    "linux"
+                  "from_oslist"  "{}"
+                  "to_oslist"    "{}"
                 }}
               }}
             }}"#,
-        internal_name, display_name
+        escape(internal_name),
+        escape(display_name),
+        escape(from_os_list),
+        escape(to_os_list)
     )
-    .expect("Failed to write to file");
 }
 
 fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
@@ -48,6 +80,11 @@ fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
         if entry_path.is_dir() {
             copy_dir(&entry_path, &destination_path)?;
         } else {
+            // The destination might be hardlinked to other tools by deduplication, copying over
+            // it would write through to all of them
+            if fs::symlink_metadata(&destination_path).is_ok() {
+                fs::remove_file(&destination_path)?;
+            }
             fs::copy(&entry_path, &destination_path)?;
         }
     }
@@ -56,7 +93,8 @@ fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
 }
 
 fn recursive_delete_dir_entry(entry_path: &Path) -> io::Result<()> {
-    if entry_path.is_dir() {
+    // Never follow symlinks, and hardlinked files only lose this one link
+    if fs::symlink_metadata(entry_path)?.is_dir() {
         for entry in fs::read_dir(entry_path)? {
             let entry = entry?;
             let path = entry.path();
@@ -79,7 +117,22 @@ pub async fn process_queue(wine_cask: Arc<WineCask>, peer_map: PeerMap) {
                     wine_cask
                         .install_compatibility_tool(task.install.unwrap(), &peer_map)
                         .await;
+                } else if task.r#type == TaskType::DeduplicateCompatibilityTools {
+                    wine_cask.deduplicate_compatibility_tools(&peer_map).await;
+                } else if task.r#type == TaskType::SwapTranslationLayer {
+                    wine_cask
+                        .swap_translation_layer(task.translation_layers.unwrap(), &peer_map)
+                        .await;
+                } else if task.r#type == TaskType::BackupCompatDataPrefix {
+                    wine_cask
+                        .backup_compat_data_prefix(task.prefix.unwrap(), &peer_map)
+                        .await;
+                } else if task.r#type == TaskType::RestoreCompatDataPrefix {
+                    wine_cask
+                        .restore_compat_data_prefix(task.prefix.unwrap(), &peer_map)
+                        .await;
                 }
+                wine_cask.finish_running_task().await;
             }
             None => {
                 // Introduce a short delay before the next iteration
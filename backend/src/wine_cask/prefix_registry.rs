@@ -0,0 +1,417 @@
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_registry::{RegistryData, RegistryFile};
+use crate::PeerMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+const DLL_OVERRIDES_KEY: &str = "Software\\Wine\\DllOverrides";
+const WINE_KEY: &str = "Software\\Wine";
+const CURRENT_VERSION_KEY: &str = "Software\\Microsoft\\Windows NT\\CurrentVersion";
+
+/// Versions `winecfg` accepts for the `Version` value.
+const WINDOWS_VERSIONS: &[&str] = &[
+    "win11",
+    "win10",
+    "win81",
+    "win8",
+    "win2008r2",
+    "win7",
+    "win2008",
+    "vista",
+    "win2003",
+    "winxp64",
+    "winxp",
+    "win2k",
+    "winme",
+    "win98",
+    "win95",
+    "nt40",
+    "nt351",
+    "win31",
+    "win30",
+    "win20",
+];
+
+/// Which implementation of a DLL Wine loads, and in what order.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum DllOverrideMode {
+    Native,
+    Builtin,
+    NativeBuiltin,
+    BuiltinNative,
+    Disabled,
+}
+
+impl DllOverrideMode {
+    /// Parses the value Wine stores, which also accepts abbreviations like `n,b`.
+    fn parse(value: &str) -> Option<DllOverrideMode> {
+        let order: Vec<&str> = value
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| match part.to_ascii_lowercase().as_str() {
+                "n" | "native" => Some("n"),
+                "b" | "builtin" => Some("b"),
+                _ => None,
+            })
+            .collect::<Option<Vec<&str>>>()?;
+        match order.as_slice() {
+            [] => Some(DllOverrideMode::Disabled),
+            ["n"] => Some(DllOverrideMode::Native),
+            ["b"] => Some(DllOverrideMode::Builtin),
+            ["n", "b"] => Some(DllOverrideMode::NativeBuiltin),
+            ["b", "n"] => Some(DllOverrideMode::BuiltinNative),
+            _ => None,
+        }
+    }
+
+    fn as_registry_value(&self) -> &'static str {
+        match self {
+            DllOverrideMode::Native => "native",
+            DllOverrideMode::Builtin => "builtin",
+            DllOverrideMode::NativeBuiltin => "native,builtin",
+            DllOverrideMode::BuiltinNative => "builtin,native",
+            DllOverrideMode::Disabled => "",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DllOverride {
+    /// Name of the DLL without `.dll`, a leading `*` also matches DLLs loaded by full path.
+    pub dll: String,
+    /// `None` removes the override when setting overrides.
+    pub mode: Option<DllOverrideMode>,
+}
+
+/// The registry settings of a prefix the frontend can change.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefixRegistry {
+    pub app_id: u64,
+    pub dll_overrides: Vec<DllOverride>,
+    /// Version set for the prefix, `None` if it uses the default of the tool that created it.
+    pub windows_version: Option<String>,
+    /// Product name of the version the prefix was created with, e.g. `Microsoft Windows 10`.
+    pub windows_product_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefixRegistryRequest {
+    pub app_id: u64,
+    #[serde(default)]
+    pub dll_overrides: Vec<DllOverride>,
+    /// Windows version to set, `None` goes back to the prefix default.
+    pub windows_version: Option<String>,
+}
+
+fn normalize_dll_name(dll: &str) -> Option<String> {
+    let dll = dll.trim().to_ascii_lowercase();
+    let dll = dll.strip_suffix(".dll").unwrap_or(&dll).to_string();
+    let name = dll.strip_prefix('*').unwrap_or(&dll);
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+    valid.then_some(dll)
+}
+
+/// Wine creates the registry when the prefix is first used, writing one ourselves would leave
+/// Wine with a prefix it considers initialized.
+fn read_registry(path: &Path) -> io::Result<RegistryFile> {
+    if !path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "{} not found, launch the game once to initialize its prefix",
+                path.display()
+            ),
+        ));
+    }
+    RegistryFile::read(path)
+}
+
+fn get_registry_string(registry: &RegistryFile, key: &str, name: &str) -> Option<String> {
+    match registry.get_value(key, name)? {
+        RegistryData::String(value) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn read_prefix_registry(prefix_path: &Path, app_id: u64) -> io::Result<PrefixRegistry> {
+    let user_registry = read_registry(&prefix_path.join("user.reg"))?;
+    let system_registry = read_registry(&prefix_path.join("system.reg"))?;
+    let dll_overrides = user_registry
+        .values(DLL_OVERRIDES_KEY)
+        .into_iter()
+        .filter_map(|(dll, data)| {
+            let mode = match &data {
+                RegistryData::String(value) => DllOverrideMode::parse(value),
+                _ => None,
+            };
+            if mode.is_none() {
+                warn!("Ignoring unknown override for {}: {:?}", dll, data);
+                return None;
+            }
+            Some(DllOverride { dll, mode })
+        })
+        .collect();
+    Ok(PrefixRegistry {
+        app_id,
+        dll_overrides,
+        windows_version: get_registry_string(&user_registry, WINE_KEY, "Version"),
+        windows_product_name: get_registry_string(
+            &system_registry,
+            CURRENT_VERSION_KEY,
+            "ProductName",
+        ),
+    })
+}
+
+/// Applies the overrides to `user.reg`, overrides without a mode are removed.
+pub fn set_dll_overrides(prefix_path: &Path, dll_overrides: &[DllOverride]) -> io::Result<()> {
+    let user_registry_path = prefix_path.join("user.reg");
+    let mut user_registry = read_registry(&user_registry_path)?;
+    for dll_override in dll_overrides {
+        let dll = normalize_dll_name(&dll_override.dll).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid DLL name: {}", dll_override.dll),
+            )
+        })?;
+        match dll_override.mode {
+            Some(mode) => user_registry.set_value(
+                DLL_OVERRIDES_KEY,
+                &dll,
+                RegistryData::String(mode.as_registry_value().to_string()),
+            ),
+            None => {
+                user_registry.delete_value(DLL_OVERRIDES_KEY, &dll);
+            }
+        }
+    }
+    user_registry.write(&user_registry_path)
+}
+
+/// Sets the Windows version Wine reports for the whole prefix, `None` removes it again.
+pub fn set_windows_version(prefix_path: &Path, windows_version: Option<&str>) -> io::Result<()> {
+    let user_registry_path = prefix_path.join("user.reg");
+    let mut user_registry = read_registry(&user_registry_path)?;
+    match windows_version {
+        Some(windows_version) => {
+            if !WINDOWS_VERSIONS.contains(&windows_version) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown Windows version: {}", windows_version),
+                ));
+            }
+            user_registry.set_value(
+                WINE_KEY,
+                "Version",
+                RegistryData::String(windows_version.to_string()),
+            );
+        }
+        None => {
+            user_registry.delete_value(WINE_KEY, "Version");
+        }
+    }
+    user_registry.write(&user_registry_path)
+}
+
+impl WineCask {
+    fn get_prefix_path(&self, app_id: u64) -> Result<PathBuf, String> {
+        self.find_compat_data_prefix(app_id)
+            .map(|prefix| prefix.path.join("pfx"))
+            .map_err(|err| err.to_string())
+    }
+
+    pub async fn get_prefix_registry(&self, app_id: u64, peer_map: &PeerMap) {
+        let result = match self.get_prefix_path(app_id) {
+            Ok(prefix_path) => tokio::task::spawn_blocking(move || {
+                read_prefix_registry(&prefix_path, app_id).map_err(|err| err.to_string())
+            })
+            .await
+            .unwrap(),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(prefix_registry) => {
+                self.app_state.lock().await.prefix_registry = Some(prefix_registry);
+                self.broadcast_app_state(peer_map).await;
+            }
+            Err(err) => {
+                let error_message = format!("Failed to read prefix registry: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
+        }
+    }
+
+    pub async fn update_dll_overrides(&self, request: PrefixRegistryRequest, peer_map: &PeerMap) {
+        let app_id = request.app_id;
+        self.update_prefix_registry(
+            app_id,
+            TaskType::UpdateDllOverrides,
+            "DLL Overrides Updated",
+            peer_map,
+            move |path| set_dll_overrides(path, &request.dll_overrides),
+        )
+        .await;
+    }
+
+    pub async fn update_windows_version(&self, request: PrefixRegistryRequest, peer_map: &PeerMap) {
+        let app_id = request.app_id;
+        self.update_prefix_registry(
+            app_id,
+            TaskType::UpdateWindowsVersion,
+            "Windows Version Updated",
+            peer_map,
+            move |path| set_windows_version(path, request.windows_version.as_deref()),
+        )
+        .await;
+    }
+
+    /// Changes the registry of a prefix whose game isn't running, Wine writes the registry back
+    /// when the game exits and would undo the change otherwise.
+    async fn update_prefix_registry<F>(
+        &self,
+        app_id: u64,
+        task_type: TaskType,
+        message: &str,
+        peer_map: &PeerMap,
+        update: F,
+    ) where
+        F: FnOnce(&Path) -> io::Result<()> + Send + 'static,
+    {
+        let history_entry = HistoryEntry::start(
+            task_type,
+            Some(self.get_history_game_name(app_id).await),
+            None,
+        );
+        let result = match self.find_modifiable_compat_data_prefix(app_id) {
+            Ok(prefix) => tokio::task::spawn_blocking(move || update(&prefix.path.join("pfx")))
+                .await
+                .unwrap()
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+        if let Err(err) = result {
+            let error_message = format!("Failed to update prefix registry: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+
+        let message = format!("{}: {}", message, app_id);
+        info!("{}", message);
+        self.broadcast_notification(peer_map, &message).await;
+        self.get_prefix_registry(app_id, peer_map).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_update_prefix_registry() {
+        let prefix_dir = tempdir().expect("Failed to create temporary directory");
+        let prefix_path = prefix_dir.path();
+        // An uninitialized prefix is left for Wine to set up
+        assert!(read_prefix_registry(prefix_path, 730).is_err());
+        assert!(set_windows_version(prefix_path, Some("win7")).is_err());
+        assert!(!prefix_path.join("user.reg").exists());
+        let user_reg = "WINE REGISTRY Version 2\n;; All keys relative to \\\\User\\\\S-1-5-21-0-0-0-1000\n\n#arch=win64\n\n[Software\\\\Wine\\\\DllOverrides] 1716900000\n#time=1dab12345678901\n\"d3dcompiler_47\"=\"n,b\"\n\"xinput1_3\"=\"\"\n\"dinput8\"=\"unknown\"\n\n";
+        fs::write(prefix_path.join("user.reg"), user_reg).unwrap();
+        fs::write(
+            prefix_path.join("system.reg"),
+            "WINE REGISTRY Version 2\n\n[Software\\\\Microsoft\\\\Windows NT\\\\CurrentVersion] 1716900000\n\"ProductName\"=\"Microsoft Windows 10\"\n",
+        )
+        .unwrap();
+
+        let prefix_registry = read_prefix_registry(prefix_path, 730).unwrap();
+        assert_eq!(
+            prefix_registry.dll_overrides,
+            vec![
+                DllOverride {
+                    dll: "d3dcompiler_47".to_string(),
+                    mode: Some(DllOverrideMode::NativeBuiltin),
+                },
+                DllOverride {
+                    dll: "xinput1_3".to_string(),
+                    mode: Some(DllOverrideMode::Disabled),
+                },
+            ]
+        );
+        assert_eq!(prefix_registry.windows_version, None);
+        assert_eq!(
+            prefix_registry.windows_product_name.as_deref(),
+            Some("Microsoft Windows 10")
+        );
+
+        set_dll_overrides(
+            prefix_path,
+            &[
+                DllOverride {
+                    dll: "D3D11.dll".to_string(),
+                    mode: Some(DllOverrideMode::Native),
+                },
+                DllOverride {
+                    dll: "xinput1_3".to_string(),
+                    mode: None,
+                },
+            ],
+        )
+        .unwrap();
+        set_windows_version(prefix_path, Some("win7")).unwrap();
+        // The oldest backup holds the original registry
+        let oldest_backup = fs::read_dir(prefix_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|file_name| file_name.starts_with("user.reg.wine-cellar-backup-"))
+            .min()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(prefix_path.join(oldest_backup)).unwrap(),
+            user_reg
+        );
+        assert!(set_windows_version(prefix_path, Some("win12")).is_err());
+        assert!(set_dll_overrides(
+            prefix_path,
+            &[DllOverride {
+                dll: "../d3d11".to_string(),
+                mode: None,
+            }]
+        )
+        .is_err());
+
+        let prefix_registry = read_prefix_registry(prefix_path, 730).unwrap();
+        assert_eq!(prefix_registry.windows_version.as_deref(), Some("win7"));
+        assert!(prefix_registry.dll_overrides.contains(&DllOverride {
+            dll: "d3d11".to_string(),
+            mode: Some(DllOverrideMode::Native),
+        }));
+        assert!(!prefix_registry
+            .dll_overrides
+            .iter()
+            .any(|dll_override| dll_override.dll == "xinput1_3"));
+
+        set_windows_version(prefix_path, None).unwrap();
+        assert_eq!(
+            read_prefix_registry(prefix_path, 730)
+                .unwrap()
+                .windows_version,
+            None
+        );
+        // Values the backend doesn't understand are left alone
+        assert!(fs::read_to_string(prefix_path.join("user.reg"))
+            .unwrap()
+            .contains("\"dinput8\"=\"unknown\""));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::steam_util::{parse_proton_version, CompatDataPrefix, SteamUtil, SteamUtilError};
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::disk_usage::{measure_directory, DiskUsage};
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_cask::recursive_delete_dir_entry;
+use crate::wine_cask::steam_installations::get_active_user;
+use crate::PeerMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SteamCompatDataPrefix {
+    pub app_id: u64,
+    pub path: String,
+    pub game_name: Option<String>,
+    pub prefix_version: Option<String>,
+    pub tool_version: Option<String>,
+    pub disk_usage: Option<DiskUsage>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Prefix {
+    pub app_id: u64,
+    /// Leave shader caches and temporary files out of a backup.
+    #[serde(default)]
+    pub exclude_caches: bool,
+    /// Archive in the backup directory to restore.
+    pub backup_file_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum PrefixVersionWarningKind {
+    /// The mapped tool is older than the one that last upgraded the prefix.
+    Downgrade,
+    /// The prefix was created by a different flavor, e.g. GE-Proton and Valve's Proton.
+    DifferentFlavor,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrefixVersionWarning {
+    pub app_id: u64,
+    pub game_name: String,
+    pub compatibility_tool: String,
+    pub tool_prefix_version: String,
+    pub prefix_version: String,
+    pub kind: PrefixVersionWarningKind,
+}
+
+#[derive(PartialEq, Debug)]
+enum PrefixVersionFlavor {
+    ProtonGE,
+    ProtonExperimental,
+    Proton,
+}
+
+/// Splits prefix versions like `GE-Proton9-20`, `Proton-6.21-GE-2`, `9.0-203` or
+/// `experimental-9.0-20240508` into their flavor and numeric components.
+fn parse_prefix_version(version: &str) -> (PrefixVersionFlavor, Vec<u64>) {
+    let flavor = if version.contains("GE") {
+        PrefixVersionFlavor::ProtonGE
+    } else if version.to_lowercase().starts_with("experimental") {
+        PrefixVersionFlavor::ProtonExperimental
+    } else {
+        PrefixVersionFlavor::Proton
+    };
+    let numbers = version
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|number| number.parse::<u64>().ok())
+        .collect();
+    (flavor, numbers)
+}
+
+/// Compares the version recorded in a prefix with the prefix version a tool would write.
+pub fn check_prefix_version(
+    prefix_version: &str,
+    tool_prefix_version: &str,
+) -> Option<PrefixVersionWarningKind> {
+    let (prefix_flavor, prefix_numbers) = parse_prefix_version(prefix_version);
+    let (tool_flavor, tool_numbers) = parse_prefix_version(tool_prefix_version);
+    if prefix_flavor != tool_flavor {
+        Some(PrefixVersionWarningKind::DifferentFlavor)
+    } else if tool_numbers < prefix_numbers {
+        Some(PrefixVersionWarningKind::Downgrade)
+    } else {
+        None
+    }
+}
+
+/// The prefix version a tool writes, taken from `CURRENT_PREFIX_VERSION` in its `proton` script
+/// and falling back to its `version` file. Tools that aren't Proton based have neither.
+pub fn read_tool_prefix_version(tool_path: &Path) -> Option<String> {
+    if let Ok(proton_script) = fs::read_to_string(tool_path.join("proton")) {
+        let current_prefix_version = proton_script.lines().find_map(|line| {
+            line.trim()
+                .strip_prefix("CURRENT_PREFIX_VERSION=")
+                .map(|version| version.trim_matches('"').to_string())
+        });
+        if current_prefix_version.is_some() {
+            return current_prefix_version;
+        }
+    }
+    fs::read_to_string(tool_path.join("version"))
+        .ok()
+        .and_then(|version| parse_proton_version(&version))
+}
+
+impl WineCask {
+    fn get_installed_game_names(steam_util: &SteamUtil) -> HashMap<u64, String> {
+        steam_util
+            .list_installed_games(get_active_user(steam_util).as_ref())
+            .unwrap_or_else(|err| {
+                warn!("Failed to get list of installed games: {}", err);
+                Vec::new()
+            })
+            .into_iter()
+            .map(|game| (game.app_id, game.name))
+            .collect()
+    }
+
+    pub async fn list_compat_data_prefixes(&self, peer_map: &PeerMap) {
+        let mut prefixes: Vec<CompatDataPrefix> = Vec::new();
+        let mut game_names: HashMap<u64, String> = HashMap::new();
+        for steam_util in self.steam_utils() {
+            match steam_util.list_compat_data_prefixes() {
+                Ok(installation_prefixes) => prefixes.extend(installation_prefixes),
+                Err(err) => {
+                    let error_message = format!("Failed to list compatdata prefixes: {}", err);
+                    error!("{}", error_message);
+                    self.broadcast_notification(peer_map, &error_message).await;
+                    return;
+                }
+            };
+            game_names.extend(Self::get_installed_game_names(&steam_util));
+        }
+
+        // Prefixes can grow to several gigabytes, measure them off the async runtime
+        let steam_compat_data_prefixes = tokio::task::spawn_blocking(move || {
+            prefixes
+                .into_iter()
+                .map(|prefix| SteamCompatDataPrefix {
+                    app_id: prefix.app_id,
+                    path: prefix.path.to_string_lossy().to_string(),
+                    game_name: game_names.get(&prefix.app_id).cloned(),
+                    disk_usage: measure_directory(&prefix.path)
+                        .map_err(|err| {
+                            warn!("Failed to measure {}: {}", prefix.path.display(), err);
+                            err
+                        })
+                        .ok(),
+                    prefix_version: prefix.prefix_version,
+                    tool_version: prefix.tool_version,
+                })
+                .collect::<Vec<SteamCompatDataPrefix>>()
+        })
+        .await
+        .unwrap();
+
+        self.app_state.lock().await.compat_data_prefixes = Some(steam_compat_data_prefixes);
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    /// Looks for the prefix of an app in every managed Steam installation.
+    pub(crate) fn find_compat_data_prefix(
+        &self,
+        app_id: u64,
+    ) -> Result<CompatDataPrefix, SteamUtilError> {
+        for steam_util in self.steam_utils() {
+            if let Ok(prefix) = steam_util.find_compat_data_prefix(app_id) {
+                return Ok(prefix);
+            }
+        }
+        Err(SteamUtilError::CompatDataPrefixNotFound(app_id))
+    }
+
+    /// Finds a prefix which is safe to modify, which means it exists and its game isn't running.
+    pub(crate) fn find_modifiable_compat_data_prefix(
+        &self,
+        app_id: u64,
+    ) -> Result<CompatDataPrefix, String> {
+        let prefix = self
+            .find_compat_data_prefix(app_id)
+            .map_err(|err| err.to_string())?;
+        if SteamUtil::is_app_running(app_id) {
+            return Err(format!(
+                "App {} is running, close it before changing its prefix",
+                app_id
+            ));
+        }
+        Ok(prefix)
+    }
+
+    async fn refresh_compat_data_prefixes(&self, peer_map: &PeerMap) {
+        // Only refresh when the frontend asked for the list before
+        if self.app_state.lock().await.compat_data_prefixes.is_some() {
+            self.list_compat_data_prefixes(peer_map).await;
+        }
+    }
+
+    pub async fn delete_compat_data_prefix(&self, prefix: Prefix, peer_map: &PeerMap) {
+        let history_entry = HistoryEntry::start(
+            TaskType::DeleteCompatDataPrefix,
+            Some(self.get_history_game_name(prefix.app_id).await),
+            None,
+        );
+        let result = self
+            .find_modifiable_compat_data_prefix(prefix.app_id)
+            .and_then(|compat_data_prefix| {
+                recursive_delete_dir_entry(&compat_data_prefix.path)
+                    .map_err(|e| format!("Error while deleting prefix: {}", e))
+            });
+        match &result {
+            Ok(_) => {
+                let message = format!("Prefix Deleted: {}", prefix.app_id);
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+                self.refresh_compat_data_prefixes(peer_map).await;
+            }
+            Err(error_message) => {
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, error_message).await;
+            }
+        }
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+    }
+
+    /// Moves the prefix aside so Steam creates a fresh one on the next launch.
+    pub async fn reset_compat_data_prefix(&self, prefix: Prefix, peer_map: &PeerMap) {
+        let history_entry = HistoryEntry::start(
+            TaskType::ResetCompatDataPrefix,
+            Some(self.get_history_game_name(prefix.app_id).await),
+            None,
+        );
+        let result = self.move_compat_data_prefix_aside(prefix.app_id);
+        match &result {
+            Ok(backup_path) => {
+                let message = format!(
+                    "Prefix Reset: {}, previous prefix kept at {}",
+                    prefix.app_id,
+                    backup_path.display()
+                );
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+                self.refresh_compat_data_prefixes(peer_map).await;
+            }
+            Err(error_message) => {
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, error_message).await;
+            }
+        }
+        self.record_history(history_entry.finish(&result.map(|_| ())), peer_map)
+            .await;
+    }
+
+    fn move_compat_data_prefix_aside(&self, app_id: u64) -> Result<PathBuf, String> {
+        let compat_data_prefix = self.find_modifiable_compat_data_prefix(app_id)?;
+
+        let unix_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to calculate duration")
+            .as_secs();
+        // Steam ignores anything in compatdata that isn't named after an app id
+        let backup_path = compat_data_prefix
+            .path
+            .with_file_name(format!("{}.wine-cellar-backup-{}", app_id, unix_timestamp));
+
+        fs::rename(&compat_data_prefix.path, &backup_path)
+            .map_err(|e| format!("Error while resetting prefix: {}", e))?;
+        Ok(backup_path)
+    }
+
+    /// Flags games whose mapped tool would downgrade their prefix or comes from a different
+    /// flavor than the tool that created it.
+    pub async fn update_prefix_version_warnings(&self) {
+        let mut prefix_version_warnings: Vec<PrefixVersionWarning> = Vec::new();
+        for steam_installation in self.managed_steam_installations() {
+            let steam_util = SteamUtil::new(steam_installation.path.clone());
+            let compat_tools_mapping = steam_util
+                .get_compatibility_tools_mappings()
+                .unwrap_or_else(|err| {
+                    warn!("Failed to get compatibility tools mappings: {}", err);
+                    HashMap::new()
+                });
+            let prefixes = steam_util
+                .list_compat_data_prefixes()
+                .unwrap_or_else(|err| {
+                    warn!("Failed to list compatdata prefixes: {}", err);
+                    Vec::new()
+                });
+            let game_names = Self::get_installed_game_names(&steam_util);
+
+            let app_state = self.app_state.lock().await;
+            for prefix in prefixes {
+                let (Some(prefix_version), Some(tool_name)) = (
+                    prefix.prefix_version.as_ref(),
+                    compat_tools_mapping.get(&prefix.app_id),
+                ) else {
+                    continue;
+                };
+                // Mappings only refer to tools of the same installation
+                let Some(tool) = app_state.installed_compatibility_tools.iter().find(|tool| {
+                    tool.steam_installation == steam_installation.id
+                        && (&tool.internal_name == tool_name || &tool.display_name == tool_name)
+                }) else {
+                    continue;
+                };
+                let Some(tool_prefix_version) = tool.prefix_version.as_ref() else {
+                    continue;
+                };
+                if let Some(kind) = check_prefix_version(prefix_version, tool_prefix_version) {
+                    prefix_version_warnings.push(PrefixVersionWarning {
+                        app_id: prefix.app_id,
+                        game_name: game_names
+                            .get(&prefix.app_id)
+                            .cloned()
+                            .unwrap_or_else(|| prefix.app_id.to_string()),
+                        compatibility_tool: tool.display_name.clone(),
+                        tool_prefix_version: tool_prefix_version.clone(),
+                        prefix_version: prefix_version.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+        self.app_state.lock().await.prefix_version_warnings = prefix_version_warnings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_prefix_version() {
+        assert_eq!(check_prefix_version("GE-Proton9-20", "GE-Proton9-20"), None);
+        assert_eq!(check_prefix_version("GE-Proton9-20", "GE-Proton9-22"), None);
+        assert_eq!(check_prefix_version("GE-Proton8-32", "GE-Proton9-1"), None);
+        assert_eq!(
+            check_prefix_version("GE-Proton9-20", "GE-Proton9-4"),
+            Some(PrefixVersionWarningKind::Downgrade)
+        );
+        assert_eq!(
+            check_prefix_version("9.0-203", "8.0-105"),
+            Some(PrefixVersionWarningKind::Downgrade)
+        );
+        assert_eq!(
+            check_prefix_version("GE-Proton9-20", "9.0-203"),
+            Some(PrefixVersionWarningKind::DifferentFlavor)
+        );
+        assert_eq!(
+            check_prefix_version("Proton-6.21-GE-2", "GE-Proton7-1"),
+            None
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use crate::steam_util::SteamUtil;
+use crate::wine_cask::app::WineCask;
+use crate::wine_cask::launch_options::get_environment_variable;
+use crate::wine_cask::steam_installations::get_active_user;
+use crate::PeerMap;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, io};
+
+const REPORT_FILE_NAME: &str = "wine-cellar-report.json";
+
+/// A log Proton wrote for a game launched with `PROTON_LOG=1`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProtonLog {
+    pub app_id: u64,
+    pub game_name: Option<String>,
+    pub file_name: String,
+    pub path: String,
+    pub size: u64,
+    /// Seconds since the epoch, `None` if the filesystem doesn't record it.
+    pub created: Option<u64>,
+    pub modified: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProtonLogRequest {
+    /// Path of a log from the list, only logs found by the backend are bundled.
+    pub path: String,
+}
+
+/// Stored next to the log in a bundle, so whoever reads it knows what the game ran with.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProtonLogReport {
+    pub app_id: u64,
+    pub game_name: Option<String>,
+    pub created: u64,
+    pub compatibility_tool: Option<String>,
+    pub compatibility_tool_display_name: Option<String>,
+    pub prefix_version: Option<String>,
+    pub tool_version: Option<String>,
+    pub launch_options: Option<String>,
+}
+
+/// Proton names its logs `steam-<appid>.log`.
+fn parse_log_file_name(file_name: &str) -> Option<u64> {
+    file_name
+        .strip_prefix("steam-")?
+        .strip_suffix(".log")?
+        .parse()
+        .ok()
+}
+
+fn to_timestamp(time: io::Result<SystemTime>) -> Option<u64> {
+    time.ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
+fn get_user_home() -> Option<PathBuf> {
+    env::var_os("DECKY_USER_HOME")
+        .or_else(|| env::var_os("HOME"))
+        .map(PathBuf::from)
+}
+
+/// Launch options go through a shell, so the directory may start with `~` or `$HOME`. Relative
+/// directories depend on the game's working directory and are skipped.
+fn expand_log_directory(log_directory: &str, home: Option<&Path>) -> Option<PathBuf> {
+    let relative_to_home = ["~", "$HOME", "${HOME}"].iter().find_map(|prefix| {
+        let rest = log_directory.strip_prefix(prefix)?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| rest.trim_start_matches('/'))
+    });
+    let path = match relative_to_home {
+        Some(rest) => home?.join(rest),
+        None => PathBuf::from(log_directory),
+    };
+    path.is_absolute().then_some(path)
+}
+
+/// Proton writes its logs to `PROTON_LOG_DIR`, or the home directory if that isn't set. Games
+/// usually set it in their launch options rather than the environment Steam runs in.
+fn get_log_directories(launch_options: &[String]) -> Vec<PathBuf> {
+    let home = get_user_home();
+    let mut directories: Vec<PathBuf> = home.iter().cloned().collect();
+    let log_directories =
+        env::var("PROTON_LOG_DIR")
+            .into_iter()
+            .chain(launch_options.iter().filter_map(|launch_options| {
+                get_environment_variable(launch_options, "PROTON_LOG_DIR")
+            }));
+    for log_directory in log_directories {
+        let Some(log_directory) = expand_log_directory(&log_directory, home.as_deref()) else {
+            warn!("Skipping Proton log directory {}", log_directory);
+            continue;
+        };
+        if !directories.contains(&log_directory) {
+            directories.push(log_directory);
+        }
+    }
+    directories
+}
+
+fn get_log_bundle_directory() -> PathBuf {
+    PathBuf::from(
+        env::var("DECKY_PLUGIN_RUNTIME_DIR").unwrap_or("/tmp/decky-wine-cellar".to_string()),
+    )
+    .join("log-bundles")
+}
+
+/// Finds the Proton logs in the given directories, newest first.
+pub fn find_proton_logs(
+    directories: &[PathBuf],
+    game_names: &HashMap<u64, String>,
+) -> Vec<ProtonLog> {
+    let mut logs: Vec<ProtonLog> = directories
+        .iter()
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let app_id = parse_log_file_name(&file_name)?;
+            let metadata = entry
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())?;
+            Some(ProtonLog {
+                app_id,
+                game_name: game_names.get(&app_id).cloned(),
+                file_name,
+                path: entry.path().to_string_lossy().to_string(),
+                size: metadata.len(),
+                created: to_timestamp(metadata.created()),
+                modified: to_timestamp(metadata.modified()),
+            })
+        })
+        .collect();
+    logs.sort_by_key(|log| std::cmp::Reverse(log.modified));
+    logs
+}
+
+fn append_file<W: io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+    mtime: u64,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, contents)
+}
+
+/// Packs a log together with its report into a gzipped tarball. Like prefix backups, the
+/// bundle is written next to its destination first, so a partial bundle never shows up.
+pub fn create_log_bundle(
+    log_path: &Path,
+    bundle_path: &Path,
+    report: &ProtonLogReport,
+) -> io::Result<()> {
+    let partial_path = bundle_path.with_extension("partial");
+    let result = (|| {
+        let encoder = GzEncoder::new(File::create(&partial_path)?, Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        append_file(
+            &mut builder,
+            REPORT_FILE_NAME,
+            &serde_json::to_vec_pretty(report)?,
+            report.created,
+        )?;
+        // The game might still be writing to the log, take whatever is there right now
+        let log = fs::read(log_path)?;
+        let file_name = log_path
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("steam-{}.log", report.app_id));
+        append_file(&mut builder, &file_name, &log, report.created)?;
+        builder.into_inner()?.finish()?;
+        fs::rename(&partial_path, bundle_path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial_path);
+    }
+    result
+}
+
+impl WineCask {
+    /// The launch options of all games of the active user of each Steam installation.
+    fn list_all_launch_options(&self) -> Vec<String> {
+        self.managed_steam_installations()
+            .iter()
+            .filter_map(|steam_installation| {
+                let steam_util = SteamUtil::new(steam_installation.path.clone());
+                let user = get_active_user(&steam_util)?;
+                steam_util
+                    .list_launch_options(&user)
+                    .map_err(|err| {
+                        warn!(
+                            "Failed to read launch options in {}: {}",
+                            steam_installation.id, err
+                        )
+                    })
+                    .ok()
+            })
+            .flat_map(|launch_options| launch_options.into_values())
+            .collect()
+    }
+
+    pub async fn list_proton_logs(&self, peer_map: &PeerMap) {
+        let game_names: HashMap<u64, String> = self
+            .app_state
+            .lock()
+            .await
+            .installed_games
+            .iter()
+            .map(|game| (game.app_id, game.name.clone()))
+            .collect();
+        let launch_options = self.list_all_launch_options();
+        let logs = tokio::task::spawn_blocking(move || {
+            find_proton_logs(&get_log_directories(&launch_options), &game_names)
+        })
+        .await
+        .unwrap();
+
+        self.app_state.lock().await.proton_logs = Some(logs);
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    /// Collects what is known about the game a log belongs to, anything missing is left empty
+    /// rather than failing the bundle.
+    async fn create_log_report(&self, app_id: u64, created: u64) -> ProtonLogReport {
+        let app_state = self.app_state.lock().await;
+        let game = app_state
+            .installed_games
+            .iter()
+            .find(|game| game.app_id == app_id);
+        let game_name = game.map(|game| game.name.clone());
+        let compatibility_tool = game.and_then(|game| game.compatibility_tool.clone());
+        let compatibility_tool_display_name =
+            compatibility_tool.as_ref().and_then(|internal_name| {
+                app_state
+                    .installed_compatibility_tools
+                    .iter()
+                    .find(|tool| &tool.internal_name == internal_name)
+                    .map(|tool| tool.display_name.clone())
+            });
+        drop(app_state);
+
+        let (prefix_version, tool_version) = match self.find_compat_data_prefix(app_id) {
+            Ok(prefix) => (prefix.prefix_version, prefix.tool_version),
+            Err(err) => {
+                warn!("No prefix to report for app {}: {}", app_id, err);
+                (None, None)
+            }
+        };
+        let launch_options = match self.get_steam_user_for_app(app_id).await {
+            Ok((steam_util, user)) => steam_util
+                .get_launch_options(&user, app_id)
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        }
+        .unwrap_or_else(|err| {
+            warn!("Failed to read launch options of app {}: {}", app_id, err);
+            None
+        });
+
+        ProtonLogReport {
+            app_id,
+            game_name,
+            created,
+            compatibility_tool,
+            compatibility_tool_display_name,
+            prefix_version,
+            tool_version,
+            launch_options,
+        }
+    }
+
+    /// Bundles a log with the game's tool, prefix version and launch options into an archive in
+    /// the `log-bundles` directory of the plugin runtime directory.
+    pub async fn bundle_proton_log(&self, request: ProtonLogRequest, peer_map: &PeerMap) {
+        // Only bundle logs we found ourselves, the path comes from the frontend
+        let launch_options = self.list_all_launch_options();
+        let log = tokio::task::spawn_blocking(move || {
+            find_proton_logs(&get_log_directories(&launch_options), &HashMap::new())
+        })
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|log| log.path == request.path);
+        let Some(log) = log else {
+            let error_message = format!("Proton log not found: {}", request.path);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        };
+        if SteamUtil::is_app_running(log.app_id) {
+            warn!("App {} is running while bundling its log", log.app_id);
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Failed to calculate duration")
+            .as_secs();
+        let report = self.create_log_report(log.app_id, created).await;
+        let bundle_path =
+            get_log_bundle_directory().join(format!("steam-{}-{}.tar.gz", log.app_id, created));
+
+        let bundle_path_clone = bundle_path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            if let Some(parent) = bundle_path_clone.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            create_log_bundle(Path::new(&log.path), &bundle_path_clone, &report)
+        })
+        .await
+        .unwrap();
+
+        match result {
+            Ok(_) => {
+                let message = format!("Log Bundle Created: {}", bundle_path.display());
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+            }
+            Err(err) => {
+                let error_message = format!("Failed to bundle Proton log: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_and_bundle_proton_logs() {
+        let home_dir = tempdir().expect("Failed to create temporary directory");
+        let log_dir = tempdir().expect("Failed to create temporary directory");
+        fs::write(
+            home_dir.path().join("steam-730.log"),
+            "err:module:import_dll\n",
+        )
+        .unwrap();
+        fs::write(log_dir.path().join("steam-440.log"), "fixme:d3d\n").unwrap();
+        fs::write(home_dir.path().join("steam-abc.log"), "").unwrap();
+        fs::write(home_dir.path().join("steam-730.txt"), "").unwrap();
+        fs::create_dir(home_dir.path().join("steam-1.log")).unwrap();
+
+        let game_names = HashMap::from([(730, "Counter-Strike 2".to_string())]);
+        let logs = find_proton_logs(
+            &[home_dir.path().to_path_buf(), log_dir.path().to_path_buf()],
+            &game_names,
+        );
+        assert_eq!(logs.len(), 2);
+        let log = logs.iter().find(|log| log.app_id == 730).unwrap();
+        assert_eq!(log.game_name.as_deref(), Some("Counter-Strike 2"));
+        assert_eq!(log.size, 22);
+        assert!(logs
+            .iter()
+            .any(|log| log.app_id == 440 && log.game_name.is_none()));
+
+        let report = ProtonLogReport {
+            app_id: 730,
+            game_name: log.game_name.clone(),
+            created: 1,
+            compatibility_tool: Some("GE-Proton9-20".to_string()),
+            compatibility_tool_display_name: Some("GE-Proton9-20".to_string()),
+            prefix_version: Some("GE-Proton9-20".to_string()),
+            tool_version: None,
+            launch_options: Some("PROTON_LOG=1 %command%".to_string()),
+        };
+        let bundle_path = home_dir.path().join("steam-730-1.tar.gz");
+        create_log_bundle(Path::new(&log.path), &bundle_path, &report)
+            .expect("Failed to create bundle");
+        assert!(!bundle_path.with_extension("partial").exists());
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&bundle_path).unwrap()));
+        let entries: HashMap<String, String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (
+                    entry.path().unwrap().to_string_lossy().to_string(),
+                    contents,
+                )
+            })
+            .collect();
+        assert_eq!(entries["steam-730.log"], "err:module:import_dll\n");
+        let bundled_report: ProtonLogReport =
+            serde_json::from_str(&entries[REPORT_FILE_NAME]).unwrap();
+        assert_eq!(
+            bundled_report.launch_options.as_deref(),
+            Some("PROTON_LOG=1 %command%")
+        );
+    }
+
+    #[test]
+    fn test_get_log_directories() {
+        let home = Path::new("/home/deck");
+        assert_eq!(
+            expand_log_directory("~/proton-logs", Some(home)),
+            Some(PathBuf::from("/home/deck/proton-logs"))
+        );
+        assert_eq!(
+            expand_log_directory("${HOME}/proton-logs", Some(home)),
+            Some(PathBuf::from("/home/deck/proton-logs"))
+        );
+        assert_eq!(
+            expand_log_directory("$HOME", Some(home)),
+            Some(PathBuf::from("/home/deck"))
+        );
+        assert_eq!(
+            expand_log_directory("/tmp/logs", None),
+            Some(PathBuf::from("/tmp/logs"))
+        );
+        assert_eq!(expand_log_directory("~/proton-logs", None), None);
+        assert_eq!(expand_log_directory("~deck/logs", Some(home)), None);
+        assert_eq!(expand_log_directory("logs", Some(home)), None);
+
+        let directories = get_log_directories(&[
+            "PROTON_LOG=1 PROTON_LOG_DIR=/tmp/proton-logs %command%".to_string(),
+            "PROTON_LOG=1 PROTON_LOG_DIR=\"/tmp/proton-logs\" %command% -novid".to_string(),
+            "PROTON_LOG=1 %command%".to_string(),
+        ]);
+        assert_eq!(
+            directories
+                .iter()
+                .filter(|directory| *directory == Path::new("/tmp/proton-logs"))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_parse_log_file_name() {
+        assert_eq!(parse_log_file_name("steam-730.log"), Some(730));
+        assert_eq!(
+            parse_log_file_name("steam-3141592653.log"),
+            Some(3141592653)
+        );
+        assert_eq!(parse_log_file_name("steam-.log"), None);
+        assert_eq!(parse_log_file_name("steam-730.log.old"), None);
+    }
+}
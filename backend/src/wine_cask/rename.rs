@@ -0,0 +1,93 @@
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::flavors::SteamCompatibilityTool;
+use crate::wine_cask::history::HistoryEntry;
+use crate::PeerMap;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Rename {
+    pub steam_compatibility_tool: SteamCompatibilityTool,
+    /// The new display name, `None` restores the name the tool shipped with.
+    pub display_name: Option<String>,
+}
+
+impl WineCask {
+    /// Changes the display name of an installed tool, its internal name stays the same so games
+    /// mapped to it keep using it.
+    pub async fn rename_compatibility_tool(&self, rename: Rename, peer_map: &PeerMap) {
+        let history_entry = HistoryEntry::start(
+            TaskType::RenameCompatibilityTool,
+            Some(rename.steam_compatibility_tool.display_name.clone()),
+            rename
+                .display_name
+                .as_deref()
+                .map(|display_name| display_name.trim().to_string()),
+        );
+        let result = self.set_display_name(&rename).await;
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+        if let Err(err) = result {
+            let error_message = format!("Failed to rename compatibility tool: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+
+        let message = match &rename.display_name {
+            Some(display_name) => format!(
+                "Compatibility Tool Renamed: {}, restart Steam to see the new name",
+                display_name.trim()
+            ),
+            None => format!(
+                "Compatibility Tool Name Restored: {}, restart Steam to see the new name",
+                rename.steam_compatibility_tool.internal_name
+            ),
+        };
+        info!("{}", message);
+        self.broadcast_notification(peer_map, &message).await;
+        self.sync_backend_with_installed_compat_tools().await;
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    async fn set_display_name(&self, rename: &Rename) -> Result<(), String> {
+        let display_name = match rename.display_name.as_deref().map(str::trim) {
+            Some("") => return Err("The display name can't be empty".to_string()),
+            display_name => display_name,
+        };
+
+        // Only rename tools we know about, the path comes from the frontend
+        let tool = self
+            .app_state
+            .lock()
+            .await
+            .installed_compatibility_tools
+            .iter()
+            .find(|tool| {
+                tool.path == rename.steam_compatibility_tool.path
+                    && tool.internal_name == rename.steam_compatibility_tool.internal_name
+            })
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Compatibility tool not found: {}",
+                    rename.steam_compatibility_tool.display_name
+                )
+            })?;
+        if tool.read_only {
+            return Err(format!("{} is managed by Steam", tool.display_name));
+        }
+
+        let steam_util = self
+            .steam_util_for_installation(&tool.steam_installation)
+            .ok_or_else(|| format!("Steam installation not found: {}", tool.steam_installation))?;
+        steam_util
+            .set_compatibility_tool_display_name(
+                &PathBuf::from(&tool.path).join("compatibilitytool.vdf"),
+                &tool.internal_name,
+                display_name,
+            )
+            .map_err(|err| err.to_string())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::wine_cask::app::WineCask;
+use crate::PeerMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+/// Backend settings, persisted as json in the plugin settings directory.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Where prefix backups are stored, defaults to the plugin runtime directory.
+    pub backup_directory: Option<String>,
+    /// Ids of the Steam installations to manage, the first one found is used if empty.
+    pub managed_steam_installations: Vec<String>,
+}
+
+fn get_settings_file() -> PathBuf {
+    let path =
+        env::var("DECKY_PLUGIN_SETTINGS_DIR").unwrap_or("/tmp/decky-wine-cellar".to_string());
+    PathBuf::from(path).join("wine_cask.json")
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        let settings_file = get_settings_file();
+        if !settings_file.exists() {
+            return Settings::default();
+        }
+        match fs::read_to_string(&settings_file)
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+        {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!("Failed to read settings, using defaults: {}", err);
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let settings_file = get_settings_file();
+        if let Some(parent) = settings_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        // Write to a temporary file first so a crash can't leave truncated settings behind
+        let temporary_file = settings_file.with_extension("json.tmp");
+        fs::write(&temporary_file, json)?;
+        fs::rename(&temporary_file, &settings_file)
+    }
+
+    pub fn get_backup_directory(&self) -> PathBuf {
+        match &self.backup_directory {
+            Some(backup_directory) if !backup_directory.is_empty() => {
+                PathBuf::from(backup_directory)
+            }
+            _ => PathBuf::from(
+                env::var("DECKY_PLUGIN_RUNTIME_DIR")
+                    .unwrap_or("/tmp/decky-wine-cellar".to_string()),
+            )
+            .join("backups"),
+        }
+    }
+}
+
+impl WineCask {
+    pub async fn update_settings(&self, settings: Settings, peer_map: &PeerMap) {
+        if let Err(err) = settings.save() {
+            let error_message = format!("Failed to save settings: {}", err);
+            error!("{}", error_message);
+            self.broadcast_notification(peer_map, &error_message).await;
+            return;
+        }
+        info!("Settings saved");
+        self.app_state.lock().await.settings = settings;
+        self.update_managed_steam_installations().await;
+        self.sync_backend_with_installed_compat_tools().await;
+        self.update_used_by_games(peer_map).await;
+        self.update_disk_usage(peer_map).await;
+    }
+}
@@ -0,0 +1,247 @@
+use crate::steam_util::{SteamInstallation, SteamUser, SteamUtil, SteamUtilError};
+use crate::wine_cask::app::{Task, TaskType, WineCask};
+use crate::PeerMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often to look for Steam again while running without an installation, and to check
+/// whether Steam is running.
+const STEAM_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A Steam account together with the installation it logged in on.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SteamInstallationUser {
+    pub steam_installation: String,
+    /// Whether per-user data like shortcuts is read for this account.
+    pub active: bool,
+    #[serde(flatten)]
+    pub user: SteamUser,
+}
+
+/// The account whose per-user data is read for an installation, if anyone logged in yet.
+pub fn get_active_user(steam_util: &SteamUtil) -> Option<SteamUser> {
+    steam_util
+        .get_active_user()
+        .map_err(|err| warn!("Failed to get active Steam user: {}", err))
+        .ok()
+}
+
+/// Finds the Steam installations in the home directory of the user Decky runs for.
+pub fn find_steam_installations() -> Result<Vec<SteamInstallation>, SteamUtilError> {
+    match env::var("DECKY_USER_HOME") {
+        Ok(value) => {
+            info!("Using DECKY_USER_HOME: {}", value);
+            SteamUtil::find_steam_installations(Some(value))
+        }
+        Err(_) => {
+            error!(
+                "Couldn't find environment variable DECKY_USER_HOME, using default steam directory"
+            );
+            SteamUtil::find_steam_installations(None)
+        }
+    }
+}
+
+/// Picks the installations the user selected, or the first one found if none of the selected
+/// installations are present (or nothing was selected yet).
+pub fn select_managed_steam_installations(
+    steam_installations: &[SteamInstallation],
+    selected_ids: &[String],
+) -> Vec<SteamInstallation> {
+    let selected: Vec<SteamInstallation> = steam_installations
+        .iter()
+        .filter(|installation| selected_ids.contains(&installation.id))
+        .cloned()
+        .collect();
+    if selected.is_empty() {
+        steam_installations.iter().take(1).cloned().collect()
+    } else {
+        selected
+    }
+}
+
+impl WineCask {
+    /// The Steam installations currently managed by the backend.
+    pub fn managed_steam_installations(&self) -> Vec<SteamInstallation> {
+        self.managed_steam_installations.read().unwrap().clone()
+    }
+
+    pub fn steam_utils(&self) -> Vec<SteamUtil> {
+        self.managed_steam_installations()
+            .into_iter()
+            .map(|installation| SteamUtil::new(installation.path))
+            .collect()
+    }
+
+    /// The managed installation with the given id, official tools and prefixes can live in a
+    /// library folder outside of the installation itself.
+    pub fn steam_util_for_installation(&self, steam_installation: &str) -> Option<SteamUtil> {
+        self.managed_steam_installations()
+            .into_iter()
+            .find(|installation| installation.id == steam_installation)
+            .map(|installation| SteamUtil::new(installation.path))
+    }
+
+    /// Applies the selection from the settings to the detected Steam installations.
+    pub async fn update_managed_steam_installations(&self) {
+        let mut app_state = self.app_state.lock().await;
+        let managed = select_managed_steam_installations(
+            &app_state.steam_installations,
+            &app_state.settings.managed_steam_installations,
+        );
+        for installation in &managed {
+            info!(
+                "Managing Steam installation {} at {}",
+                installation.id,
+                installation.path.display()
+            );
+        }
+        app_state.managed_steam_installations = managed
+            .iter()
+            .map(|installation| installation.id.clone())
+            .collect();
+        *self.managed_steam_installations.write().unwrap() = managed;
+    }
+
+    /// The reason Steam wasn't found, if the task can't run without a Steam installation.
+    pub async fn steam_not_found_for_task(&self, task: &Task) -> Option<String> {
+        match task.r#type {
+            TaskType::CheckForFlavorUpdates
+            | TaskType::CancelCompatibilityToolInstall
+            | TaskType::UpdateSettings => None,
+            _ => self.app_state.lock().await.steam_not_found.clone(),
+        }
+    }
+
+    /// Ids of the managed installations whose Steam client is running.
+    fn get_running_steam_installations(&self) -> Vec<String> {
+        self.managed_steam_installations()
+            .into_iter()
+            .filter(|installation| {
+                SteamUtil::new(installation.path.clone())
+                    .get_running_steam_pid()
+                    .is_some()
+            })
+            .map(|installation| installation.id)
+            .collect()
+    }
+
+    /// Checks which Steam clients are running and notifies the frontend when that changed.
+    pub async fn update_running_steam_installations(&self, peer_map: &PeerMap) {
+        let running_steam_installations = self.get_running_steam_installations();
+        let mut app_state = self.app_state.lock().await;
+        if app_state.running_steam_installations == running_steam_installations {
+            return;
+        }
+        info!(
+            "Running Steam installations: {:?}",
+            running_steam_installations
+        );
+        app_state.running_steam_installations = running_steam_installations;
+        drop(app_state);
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    /// Leaves the degraded mode the backend starts in when no Steam installation was found.
+    async fn apply_found_steam_installations(
+        &self,
+        steam_installations: Vec<SteamInstallation>,
+        peer_map: &PeerMap,
+    ) {
+        let mut app_state = self.app_state.lock().await;
+        app_state.steam_installations = steam_installations;
+        app_state.steam_not_found = None;
+        drop(app_state);
+        self.update_managed_steam_installations().await;
+        self.sync_backend_with_installed_compat_tools().await;
+        self.update_used_by_games(peer_map).await;
+        self.broadcast_notification(peer_map, "Steam Found: Compatibility tools loaded")
+            .await;
+        self.update_disk_usage(peer_map).await;
+    }
+}
+
+/// Polls for a Steam installation until one appears, e.g. after the first login on a fresh
+/// install which is when Steam writes `libraryfolders.vdf`.
+pub async fn wait_for_steam_installations(wine_cask: Arc<WineCask>, peer_map: PeerMap) {
+    loop {
+        tokio::time::sleep(STEAM_POLL_INTERVAL).await;
+        match find_steam_installations() {
+            Ok(steam_installations) => {
+                wine_cask
+                    .apply_found_steam_installations(steam_installations, &peer_map)
+                    .await;
+                return;
+            }
+            Err(err) => {
+                let reason = err.to_string();
+                let mut app_state = wine_cask.app_state.lock().await;
+                if app_state.steam_not_found.as_ref() == Some(&reason) {
+                    continue;
+                }
+                error!("Still waiting for Steam: {}", reason);
+                app_state.steam_not_found = Some(reason);
+                drop(app_state);
+                wine_cask.broadcast_app_state(&peer_map).await;
+            }
+        }
+    }
+}
+
+/// Keeps track of whether Steam is running, so the frontend can tell why config changes are
+/// refused.
+pub async fn watch_steam_process(wine_cask: Arc<WineCask>, peer_map: PeerMap) {
+    loop {
+        wine_cask
+            .update_running_steam_installations(&peer_map)
+            .await;
+        tokio::time::sleep(STEAM_POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_select_managed_steam_installations() {
+        let steam_installations = vec![
+            SteamInstallation {
+                id: ".local/share/Steam".to_string(),
+                path: PathBuf::from("/home/deck/.local/share/Steam"),
+                flatpak: false,
+            },
+            SteamInstallation {
+                id: ".var/app/com.valvesoftware.Steam/data/Steam".to_string(),
+                path: PathBuf::from("/home/deck/.var/app/com.valvesoftware.Steam/data/Steam"),
+                flatpak: true,
+            },
+        ];
+
+        let managed = select_managed_steam_installations(&steam_installations, &[]);
+        assert_eq!(managed, steam_installations[..1]);
+
+        let managed = select_managed_steam_installations(
+            &steam_installations,
+            &[".var/app/com.valvesoftware.Steam/data/Steam".to_string()],
+        );
+        assert_eq!(managed, steam_installations[1..]);
+
+        let managed = select_managed_steam_installations(
+            &steam_installations,
+            &[
+                ".local/share/Steam".to_string(),
+                ".var/app/com.valvesoftware.Steam/data/Steam".to_string(),
+            ],
+        );
+        assert_eq!(managed, steam_installations);
+
+        let managed =
+            select_managed_steam_installations(&steam_installations, &["missing".to_string()]);
+        assert_eq!(managed, steam_installations[..1]);
+    }
+}
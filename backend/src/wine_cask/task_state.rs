@@ -0,0 +1,262 @@
+use crate::wine_cask::app::{Task, TaskType, WineCask};
+use crate::wine_cask::dedup::remove_temporary_links;
+use crate::wine_cask::recursive_delete_dir_entry;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Scratch directories of running tasks, anything left in them belongs to an interrupted task.
+const TEMP_DIRECTORIES: &[&str] = &["temp", "temp_translation_layer"];
+
+/// The task queue as saved to the plugin runtime directory, so queued tasks survive the backend
+/// being restarted.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TaskState {
+    pub task_queue: VecDeque<Task>,
+    /// Task that was being processed when the state was saved.
+    pub running_task: Option<Task>,
+    /// Directories the running task created and hadn't finished writing yet.
+    pub partial_directories: Vec<PathBuf>,
+}
+
+fn get_runtime_directory() -> PathBuf {
+    PathBuf::from(
+        env::var("DECKY_PLUGIN_RUNTIME_DIR").unwrap_or("/tmp/decky-wine-cellar".to_string()),
+    )
+}
+
+fn get_task_state_file() -> PathBuf {
+    get_runtime_directory().join("task_queue.json")
+}
+
+impl TaskState {
+    pub fn load(path: &Path) -> Option<TaskState> {
+        let json = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json)
+            .map_err(|err| warn!("Failed to read task queue, starting empty: {}", err))
+            .ok()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        // Write to a temporary file first so a crash can't leave a truncated queue behind
+        let temporary_file = path.with_extension("json.tmp");
+        fs::write(&temporary_file, json)?;
+        fs::rename(&temporary_file, path)
+    }
+}
+
+/// Rolls back what an interrupted task left half written and puts the task back at the front of
+/// the queue, tasks start over from the beginning when resumed.
+pub fn recover_task_queue(
+    task_state: TaskState,
+    compatibility_tools_directories: &[PathBuf],
+) -> VecDeque<Task> {
+    for partial_directory in &task_state.partial_directories {
+        if fs::symlink_metadata(partial_directory).is_err() {
+            continue;
+        }
+        match recursive_delete_dir_entry(partial_directory) {
+            Ok(_) => info!(
+                "Rolled back interrupted task: removed {}",
+                partial_directory.display()
+            ),
+            Err(err) => error!(
+                "Failed to remove {} left by an interrupted task: {}",
+                partial_directory.display(),
+                err
+            ),
+        }
+    }
+
+    // Deduplication links files in place, only its temporary links can be left over
+    if task_state
+        .running_task
+        .as_ref()
+        .is_some_and(|task| task.r#type == TaskType::DeduplicateCompatibilityTools)
+    {
+        for directory in compatibility_tools_directories {
+            match remove_temporary_links(directory) {
+                Ok(removed) => info!(
+                    "Rolled back interrupted deduplication: removed {} files from {}",
+                    removed,
+                    directory.display()
+                ),
+                Err(err) => error!(
+                    "Failed to clean up interrupted deduplication in {}: {}",
+                    directory.display(),
+                    err
+                ),
+            }
+        }
+    }
+
+    let mut task_queue = task_state.task_queue;
+    if let Some(running_task) = task_state.running_task {
+        info!("Resuming interrupted task: {:?}", running_task.r#type);
+        task_queue.push_front(running_task);
+    }
+    task_queue
+}
+
+/// Removes `compatibilitytool.vdf` rewrites that were interrupted before being renamed into
+/// place, the tool keeps its previous name.
+pub fn remove_unfinished_config_writes(compatibility_tools_directories: &[PathBuf]) {
+    let tool_directories = compatibility_tools_directories
+        .iter()
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir());
+    for tool_directory in tool_directories {
+        let temporary_file = tool_directory.join("compatibilitytool.vdf.wine-cellar-tmp");
+        if temporary_file.exists() {
+            match fs::remove_file(&temporary_file) {
+                Ok(_) => info!(
+                    "Rolled back interrupted change: removed {}",
+                    temporary_file.display()
+                ),
+                Err(err) => error!("Failed to remove {}: {}", temporary_file.display(), err),
+            }
+        }
+    }
+}
+
+impl WineCask {
+    pub async fn save_task_state(&self) {
+        let app_state = self.app_state.lock().await;
+        let task_state = TaskState {
+            task_queue: app_state.task_queue.clone(),
+            running_task: app_state.running_task.clone(),
+            partial_directories: app_state.partial_directories.clone(),
+        };
+        drop(app_state);
+        if let Err(err) = task_state.save(&get_task_state_file()) {
+            error!("Failed to save task queue: {}", err);
+        }
+    }
+
+    /// Loads the queue saved by a previous run of the backend, before the queue is processed.
+    pub async fn restore_task_state(&self) {
+        for temp_directory in TEMP_DIRECTORIES {
+            let temp_directory = get_runtime_directory().join(temp_directory);
+            if temp_directory.exists() {
+                warn!("Found stale {}, cleaning up...", temp_directory.display());
+                if let Err(err) = recursive_delete_dir_entry(&temp_directory) {
+                    error!("Failed to clean up {}: {}", temp_directory.display(), err);
+                }
+            }
+        }
+
+        let compatibility_tools_directories: Vec<PathBuf> = self
+            .steam_utils()
+            .iter()
+            .map(|steam_util| steam_util.get_steam_compatibility_tools_directory())
+            .collect();
+        remove_unfinished_config_writes(&compatibility_tools_directories);
+
+        let Some(task_state) = TaskState::load(&get_task_state_file()) else {
+            return;
+        };
+        let task_queue = tokio::task::spawn_blocking(move || {
+            recover_task_queue(task_state, &compatibility_tools_directories)
+        })
+        .await
+        .unwrap();
+        if !task_queue.is_empty() {
+            info!("Restored {} queued tasks", task_queue.len());
+        }
+        self.app_state.lock().await.task_queue = task_queue;
+        self.save_task_state().await;
+    }
+
+    /// Marks the task taken from the queue as done.
+    pub(crate) async fn finish_running_task(&self) {
+        let mut app_state = self.app_state.lock().await;
+        app_state.running_task = None;
+        app_state.partial_directories.clear();
+        drop(app_state);
+        self.save_task_state().await;
+    }
+
+    /// Records directories the running task is about to create, so they are removed again if
+    /// the backend stops before the task finishes.
+    pub(crate) async fn set_partial_directories(&self, partial_directories: Vec<PathBuf>) {
+        self.app_state.lock().await.partial_directories = partial_directories;
+        self.save_task_state().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn task(r#type: TaskType) -> Task {
+        serde_json::from_value(serde_json::json!({ "type": r#type })).unwrap()
+    }
+
+    #[test]
+    fn test_recover_task_queue() {
+        let runtime_dir = tempdir().expect("Failed to create temporary directory");
+        let partial_tool = runtime_dir
+            .path()
+            .join("compatibilitytools.d/GE-Proton9-20");
+        fs::create_dir_all(partial_tool.join("files")).unwrap();
+        fs::write(partial_tool.join("files/half-copied"), "").unwrap();
+
+        let task_state_file = runtime_dir.path().join("task_queue.json");
+        TaskState {
+            task_queue: VecDeque::from([task(TaskType::SwapTranslationLayer)]),
+            running_task: Some(task(TaskType::DeduplicateCompatibilityTools)),
+            partial_directories: vec![
+                partial_tool.clone(),
+                runtime_dir.path().join("already-gone"),
+            ],
+        }
+        .save(&task_state_file)
+        .expect("Failed to save task state");
+
+        // Leftovers of an interrupted deduplication and rename
+        let compatibility_tools_directory = runtime_dir.path().join("compatibilitytools.d");
+        let tool = compatibility_tools_directory.join("GE-Proton9-19");
+        fs::create_dir_all(tool.join("files")).unwrap();
+        fs::write(tool.join("files/.font.ttf.wine-cellar-dedup"), "").unwrap();
+        fs::write(tool.join("compatibilitytool.vdf"), "").unwrap();
+        fs::write(tool.join("compatibilitytool.vdf.wine-cellar-tmp"), "").unwrap();
+
+        let compatibility_tools_directories = vec![compatibility_tools_directory];
+        remove_unfinished_config_writes(&compatibility_tools_directories);
+        assert!(!tool.join("compatibilitytool.vdf.wine-cellar-tmp").exists());
+        assert!(tool.join("compatibilitytool.vdf").exists());
+
+        let task_queue = recover_task_queue(
+            TaskState::load(&task_state_file).unwrap(),
+            &compatibility_tools_directories,
+        );
+        assert!(!tool.join("files/.font.ttf.wine-cellar-dedup").exists());
+        assert!(!partial_tool.exists());
+        assert!(runtime_dir.path().join("compatibilitytools.d").exists());
+        assert_eq!(
+            task_queue
+                .iter()
+                .map(|task| task.r#type.clone())
+                .collect::<Vec<TaskType>>(),
+            vec![
+                TaskType::DeduplicateCompatibilityTools,
+                TaskType::SwapTranslationLayer
+            ]
+        );
+
+        fs::write(&task_state_file, "{ not json").unwrap();
+        assert!(TaskState::load(&task_state_file).is_none());
+        assert!(TaskState::load(&runtime_dir.path().join("missing.json")).is_none());
+    }
+}
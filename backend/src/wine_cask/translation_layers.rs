@@ -0,0 +1,686 @@
+use crate::github_util::Release;
+use crate::wine_cask::app::{TaskType, WineCask};
+use crate::wine_cask::flavors::{CompatibilityToolFlavor, SteamCompatibilityTool};
+use crate::wine_cask::history::HistoryEntry;
+use crate::wine_cask::install::{
+    find_compressed_asset, unpack_archive, QueueCompatibilityTool, QueueCompatibilityToolState,
+};
+use crate::wine_cask::recursive_delete_dir_entry;
+use crate::PeerMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+/// Where the DLLs a tool shipped with are kept while other versions are swapped in.
+const ORIGINALS_DIRECTORY: &str = "wine-cellar-originals";
+const SWAPPED_VERSION_FILE: &str = "swapped_version";
+const ORIGINAL_VERSION_FILE: &str = "original_version";
+
+/// Direct3D translation layers bundled with Proton builds.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum TranslationLayer {
+    Dxvk,
+    Vkd3dProton,
+}
+
+impl TranslationLayer {
+    const ALL: [TranslationLayer; 2] = [TranslationLayer::Dxvk, TranslationLayer::Vkd3dProton];
+
+    fn repository(&self) -> (&'static str, &'static str) {
+        match self {
+            TranslationLayer::Dxvk => ("doitsujin", "dxvk"),
+            TranslationLayer::Vkd3dProton => ("HansKristian-Work", "vkd3d-proton"),
+        }
+    }
+
+    /// The directory below `files/lib*/wine` Proton keeps the layer's DLLs in.
+    fn directory_name(&self) -> &'static str {
+        match self {
+            TranslationLayer::Dxvk => "dxvk",
+            TranslationLayer::Vkd3dProton => "vkd3d-proton",
+        }
+    }
+
+    /// The DLLs carrying the layer's version string.
+    fn versioned_dlls(&self) -> &'static [&'static str] {
+        match self {
+            TranslationLayer::Dxvk => &["d3d11.dll", "dxgi.dll"],
+            TranslationLayer::Vkd3dProton => &["d3d12core.dll", "d3d12.dll"],
+        }
+    }
+}
+
+impl std::fmt::Display for TranslationLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranslationLayer::Dxvk => write!(f, "DXVK"),
+            TranslationLayer::Vkd3dProton => write!(f, "VKD3D-Proton"),
+        }
+    }
+}
+
+/// A translation layer as bundled with an installed tool.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BundledTranslationLayer {
+    pub layer: TranslationLayer,
+    /// Version of the DLLs in use, `None` if it couldn't be determined.
+    pub version: Option<String>,
+    /// Whether the DLLs the tool shipped with are swapped out.
+    pub swapped: bool,
+    /// Version of the DLLs the tool shipped with, while they're swapped out.
+    pub original_version: Option<String>,
+    /// Tags of the releases that can be swapped in, newest first.
+    pub available_releases: Vec<String>,
+}
+
+/// The translation layers of the tool the frontend asked about.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ToolTranslationLayers {
+    pub path: String,
+    pub internal_name: String,
+    pub layers: Vec<BundledTranslationLayer>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TranslationLayerRequest {
+    pub steam_compatibility_tool: SteamCompatibilityTool,
+    /// The layer to swap, not needed to list the layers of a tool.
+    pub layer: Option<TranslationLayer>,
+    /// Release to swap in, `None` restores the DLLs the tool shipped with.
+    pub tag_name: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Architecture {
+    X86,
+    X86_64,
+}
+
+/// Reads the machine type from a PE header, Proton's directory layout for 32 and 64-bit DLLs
+/// changed between versions but the DLLs themselves always tell.
+fn read_pe_architecture(path: &Path) -> Option<Architecture> {
+    let mut file = File::open(path).ok()?;
+    let mut dos_header = [0u8; 64];
+    file.read_exact(&mut dos_header).ok()?;
+    if &dos_header[..2] != b"MZ" {
+        return None;
+    }
+    let pe_header_offset = u32::from_le_bytes(dos_header[0x3c..0x40].try_into().ok()?);
+    file.seek(SeekFrom::Start(pe_header_offset as u64)).ok()?;
+    let mut pe_header = [0u8; 6];
+    file.read_exact(&mut pe_header).ok()?;
+    if &pe_header[..4] != b"PE\0\0" {
+        return None;
+    }
+    match u16::from_le_bytes([pe_header[4], pe_header[5]]) {
+        0x14c => Some(Architecture::X86),
+        0x8664 => Some(Architecture::X86_64),
+        _ => None,
+    }
+}
+
+/// Whether a string looks like a version from `git describe`, e.g. `v2.3.1` or `v2.3-26-gabc123`.
+fn is_version_string(value: &str) -> bool {
+    let Some(version) = value.strip_prefix('v') else {
+        return false;
+    };
+    value.len() <= 40
+        && version.starts_with(|c: char| c.is_ascii_digit())
+        && version.contains('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Finds the version string the layer's build embeds in its DLLs.
+fn find_version_string(data: &[u8]) -> Option<String> {
+    data.split(|byte| *byte == 0)
+        .filter_map(|segment| std::str::from_utf8(segment).ok())
+        .find(|segment| is_version_string(segment))
+        .map(|version| version.to_string())
+}
+
+fn collect_dlls(path: &Path, dlls: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_dlls(&entry.path(), dlls)?;
+        } else if file_type.is_file()
+            && entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("dll"))
+        {
+            dlls.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// The layer's DLLs in all `files/lib*/wine/<layer>` directories of a tool.
+fn find_layer_dlls(tool_path: &Path, layer: TranslationLayer) -> Vec<PathBuf> {
+    let mut dlls: Vec<PathBuf> = Vec::new();
+    let Ok(entries) = fs::read_dir(tool_path.join("files")) else {
+        return dlls;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        if !entry.file_name().to_string_lossy().starts_with("lib") {
+            continue;
+        }
+        let layer_directory = entry.path().join("wine").join(layer.directory_name());
+        if layer_directory.is_dir() {
+            if let Err(err) = collect_dlls(&layer_directory, &mut dlls) {
+                warn!("Failed to read {}: {}", layer_directory.display(), err);
+            }
+        }
+    }
+    dlls.sort();
+    dlls
+}
+
+fn detect_version(dlls: &[PathBuf], layer: TranslationLayer) -> Option<String> {
+    layer.versioned_dlls().iter().find_map(|versioned_dll| {
+        dlls.iter()
+            .filter(|dll| {
+                dll.file_name()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(versioned_dll))
+            })
+            .find_map(|dll| {
+                fs::read(dll)
+                    .ok()
+                    .and_then(|data| find_version_string(&data))
+            })
+    })
+}
+
+fn get_originals_directory(tool_path: &Path, layer: TranslationLayer) -> PathBuf {
+    tool_path
+        .join(ORIGINALS_DIRECTORY)
+        .join(layer.directory_name())
+}
+
+fn read_version_file(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|version| version.trim().to_string())
+        .filter(|version| !version.is_empty())
+}
+
+/// The layer as bundled with a tool, `None` if the tool doesn't bundle it.
+fn detect_translation_layer(
+    tool_path: &Path,
+    layer: TranslationLayer,
+) -> Option<BundledTranslationLayer> {
+    let dlls = find_layer_dlls(tool_path, layer);
+    if dlls.is_empty() {
+        return None;
+    }
+    let originals_directory = get_originals_directory(tool_path, layer);
+    let swapped = originals_directory.is_dir();
+    let (version, original_version) = if swapped {
+        (
+            read_version_file(&originals_directory.join(SWAPPED_VERSION_FILE)),
+            read_version_file(&originals_directory.join(ORIGINAL_VERSION_FILE)),
+        )
+    } else {
+        (detect_version(&dlls, layer), None)
+    };
+    Some(BundledTranslationLayer {
+        layer,
+        version,
+        swapped,
+        original_version,
+        available_releases: Vec::new(),
+    })
+}
+
+/// Replaces the layer's DLLs in a tool with the ones from an extracted release. The DLLs the
+/// tool shipped with are moved aside on the first swap so it can be undone, and new files are
+/// copied in rather than written into the existing ones, which may be hardlinked to other tools.
+fn swap_dlls(
+    tool_path: &Path,
+    layer: TranslationLayer,
+    release_directory: &Path,
+    tag_name: &str,
+) -> io::Result<usize> {
+    let mut release_dlls: Vec<PathBuf> = Vec::new();
+    collect_dlls(release_directory, &mut release_dlls)?;
+    let release_dlls: HashMap<(String, Architecture), PathBuf> = release_dlls
+        .into_iter()
+        .filter_map(|dll| {
+            let name = dll.file_name()?.to_string_lossy().to_lowercase();
+            Some(((name, read_pe_architecture(&dll)?), dll))
+        })
+        .collect();
+
+    let mut dlls = find_layer_dlls(tool_path, layer);
+    let originals_directory = get_originals_directory(tool_path, layer);
+    let first_swap = !originals_directory.is_dir();
+    if first_swap {
+        fs::create_dir_all(&originals_directory)?;
+        if let Some(original_version) = detect_version(&dlls, layer) {
+            fs::write(
+                originals_directory.join(ORIGINAL_VERSION_FILE),
+                original_version,
+            )?;
+        }
+    } else {
+        // A swap interrupted between moving a DLL aside and copying the new one in leaves it
+        // missing from the tool
+        let mut originals: Vec<PathBuf> = Vec::new();
+        collect_dlls(&originals_directory, &mut originals)?;
+        for original in originals {
+            let relative_path = original.strip_prefix(&originals_directory).unwrap();
+            let dll = tool_path.join(relative_path);
+            if !dlls.contains(&dll) {
+                dlls.push(dll);
+            }
+        }
+    }
+
+    let mut swapped = 0;
+    for dll in &dlls {
+        let relative_path = dll.strip_prefix(tool_path).unwrap_or(dll);
+        let original_path = originals_directory.join(relative_path);
+        let (Some(name), Some(architecture)) = (
+            dll.file_name()
+                .map(|name| name.to_string_lossy().to_lowercase()),
+            read_pe_architecture(dll).or_else(|| read_pe_architecture(&original_path)),
+        ) else {
+            continue;
+        };
+        let Some(release_dll) = release_dlls.get(&(name, architecture)) else {
+            continue;
+        };
+        // Every DLL is moved aside the first time it's replaced, even when a previous swap
+        // didn't get to it, otherwise it couldn't be restored
+        if original_path.exists() {
+            if fs::symlink_metadata(dll).is_ok() {
+                fs::remove_file(dll)?;
+            }
+        } else {
+            if let Some(parent) = original_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(dll, &original_path)?;
+        }
+        fs::copy(release_dll, dll)?;
+        swapped += 1;
+    }
+
+    if swapped == 0 {
+        if first_swap {
+            recursive_delete_dir_entry(&originals_directory)?;
+            let _ = fs::remove_dir(tool_path.join(ORIGINALS_DIRECTORY));
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("The release has no {} DLLs matching the tool's", layer),
+        ));
+    }
+    fs::write(originals_directory.join(SWAPPED_VERSION_FILE), tag_name)?;
+    Ok(swapped)
+}
+
+/// Moves the DLLs the tool shipped with back in place.
+fn restore_dlls(tool_path: &Path, layer: TranslationLayer) -> io::Result<usize> {
+    let originals_directory = get_originals_directory(tool_path, layer);
+    if !originals_directory.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} wasn't swapped", layer),
+        ));
+    }
+    let mut originals: Vec<PathBuf> = Vec::new();
+    collect_dlls(&originals_directory, &mut originals)?;
+    for original in &originals {
+        let relative_path = original
+            .strip_prefix(&originals_directory)
+            .unwrap_or(original);
+        // Renaming replaces the swapped in DLL without writing into it
+        fs::rename(original, tool_path.join(relative_path))?;
+    }
+    recursive_delete_dir_entry(&originals_directory)?;
+    // Only succeeds once no other layer is swapped
+    let _ = fs::remove_dir(tool_path.join(ORIGINALS_DIRECTORY));
+    Ok(originals.len())
+}
+
+fn prepare_release_directory() -> io::Result<PathBuf> {
+    let release_directory = PathBuf::from(
+        env::var("DECKY_PLUGIN_RUNTIME_DIR").unwrap_or("/tmp/decky-wine-cellar".to_string()),
+    )
+    .join("temp_translation_layer");
+    if release_directory.exists() {
+        recursive_delete_dir_entry(&release_directory)?;
+    }
+    fs::create_dir_all(&release_directory)?;
+    Ok(release_directory)
+}
+
+impl WineCask {
+    /// Finds a tool the frontend refers to among the installed ones, the path comes from the
+    /// frontend.
+    async fn find_installed_tool(
+        &self,
+        steam_compatibility_tool: &SteamCompatibilityTool,
+    ) -> Result<SteamCompatibilityTool, String> {
+        self.app_state
+            .lock()
+            .await
+            .installed_compatibility_tools
+            .iter()
+            .find(|tool| {
+                tool.path == steam_compatibility_tool.path
+                    && tool.internal_name == steam_compatibility_tool.internal_name
+            })
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Compatibility tool not found: {}",
+                    steam_compatibility_tool.display_name
+                )
+            })
+    }
+
+    async fn get_layer_releases(&self, layer: TranslationLayer) -> Vec<Release> {
+        let (owner, repository) = layer.repository();
+        self.get_releases(owner, repository, false)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|release| !release.draft && find_compressed_asset(release).is_some())
+            .collect()
+    }
+
+    /// Detects the DXVK and VKD3D-Proton versions bundled with a tool.
+    pub async fn get_translation_layers(
+        &self,
+        steam_compatibility_tool: &SteamCompatibilityTool,
+        peer_map: &PeerMap,
+    ) {
+        let tool = match self.find_installed_tool(steam_compatibility_tool).await {
+            Ok(tool) => tool,
+            Err(err) => {
+                error!("{}", err);
+                self.broadcast_notification(peer_map, &err).await;
+                return;
+            }
+        };
+
+        let tool_path = PathBuf::from(&tool.install_path);
+        let mut layers = tokio::task::spawn_blocking(move || {
+            TranslationLayer::ALL
+                .iter()
+                .filter_map(|layer| detect_translation_layer(&tool_path, *layer))
+                .collect::<Vec<BundledTranslationLayer>>()
+        })
+        .await
+        .unwrap();
+        for bundled_layer in &mut layers {
+            bundled_layer.available_releases = self
+                .get_layer_releases(bundled_layer.layer)
+                .await
+                .into_iter()
+                .map(|release| release.tag_name)
+                .collect();
+        }
+
+        self.app_state.lock().await.translation_layers = Some(ToolTranslationLayers {
+            path: tool.path,
+            internal_name: tool.internal_name,
+            layers,
+        });
+        self.broadcast_app_state(peer_map).await;
+    }
+
+    /// Swaps a release of DXVK or VKD3D-Proton into a tool, or restores the original DLLs.
+    pub async fn swap_translation_layer(
+        &self,
+        request: TranslationLayerRequest,
+        peer_map: &PeerMap,
+    ) {
+        let mut history_entry = HistoryEntry::start(
+            TaskType::SwapTranslationLayer,
+            Some(request.steam_compatibility_tool.display_name.clone()),
+            request.tag_name.clone(),
+        );
+        let result = match request.layer {
+            Some(layer) => {
+                self.swap_or_restore(&request, layer, &mut history_entry, peer_map)
+                    .await
+            }
+            None => Err("No translation layer given".to_string()),
+        };
+        let history_entry = match &result {
+            Ok(Some(_)) => history_entry.finish(&Ok(())),
+            Ok(None) => history_entry.cancel(),
+            Err(err) => history_entry.finish(&Err(err.clone())),
+        };
+        self.record_history(history_entry, peer_map).await;
+        match result {
+            Ok(Some(message)) => {
+                info!("{}", message);
+                self.broadcast_notification(peer_map, &message).await;
+            }
+            Ok(None) => info!("Translation layer download cancelled"),
+            Err(err) => {
+                let error_message = format!("Failed to swap translation layer: {}", err);
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, &error_message).await;
+            }
+        }
+        self.invalidate_disk_usage_cache().await;
+        self.get_translation_layers(&request.steam_compatibility_tool, peer_map)
+            .await;
+        self.update_disk_usage(peer_map).await;
+    }
+
+    /// Returns the message to show, `None` if the download was cancelled.
+    async fn swap_or_restore(
+        &self,
+        request: &TranslationLayerRequest,
+        layer: TranslationLayer,
+        history_entry: &mut HistoryEntry,
+        peer_map: &PeerMap,
+    ) -> Result<Option<String>, String> {
+        let tool = self
+            .find_installed_tool(&request.steam_compatibility_tool)
+            .await?;
+        if tool.read_only {
+            return Err(format!(
+                "{} is managed by Steam, clone it to swap {}",
+                tool.display_name, layer
+            ));
+        }
+        let tool_path = PathBuf::from(&tool.install_path);
+
+        let Some(tag_name) = request.tag_name.clone() else {
+            let restored = tokio::task::spawn_blocking(move || restore_dlls(&tool_path, layer))
+                .await
+                .unwrap()
+                .map_err(|err| err.to_string())?;
+            return Ok(Some(format!(
+                "{} Restored: {} DLLs of {} put back",
+                layer, restored, tool.display_name
+            )));
+        };
+
+        let release = self
+            .get_layer_releases(layer)
+            .await
+            .into_iter()
+            .find(|release| release.tag_name == tag_name)
+            .ok_or_else(|| format!("{} release not found: {}", layer, tag_name))?;
+        let (asset, compress_type) = find_compressed_asset(&release)
+            .ok_or_else(|| format!("{} {} has no archive", layer, tag_name))?;
+
+        // Shows up in the queue like an install, so it can be followed and cancelled the same way
+        let mut queue_compatibility_tool = QueueCompatibilityTool {
+            flavor: CompatibilityToolFlavor::Unknown,
+            name: format!("{} {}", layer, tag_name),
+            url: asset.browser_download_url,
+            state: QueueCompatibilityToolState::Waiting,
+            compress_type: compress_type.clone(),
+            progress: 0,
+        };
+        self.broadcast_notification(peer_map, &format!("Downloading {} {}...", layer, tag_name))
+            .await;
+        let Some(archive) = self
+            .download_archive(&mut queue_compatibility_tool, history_entry, peer_map)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        queue_compatibility_tool.state = QueueCompatibilityToolState::Extracting;
+        self.app_state.lock().await.in_progress = Some(queue_compatibility_tool);
+        self.broadcast_app_state(peer_map).await;
+        let swap_tag_name = tag_name.clone();
+        let swapped = tokio::task::spawn_blocking(move || {
+            let release_directory = prepare_release_directory()?;
+            let result = unpack_archive(Cursor::new(archive), &compress_type, &release_directory)
+                .and_then(|_| swap_dlls(&tool_path, layer, &release_directory, &swap_tag_name));
+            if let Err(err) = recursive_delete_dir_entry(&release_directory) {
+                error!("Failed to clean up temp directory: {}", err);
+            }
+            result
+        })
+        .await
+        .unwrap();
+        self.app_state.lock().await.in_progress = None;
+        self.broadcast_app_state(peer_map).await;
+        let swapped = swapped.map_err(|err| err.to_string())?;
+
+        Ok(Some(format!(
+            "{} Swapped: {} DLLs of {} replaced with {}",
+            layer, swapped, tool.display_name, tag_name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::tempdir;
+
+    /// A file with just enough of a PE header to tell its architecture.
+    fn write_dll(path: &Path, architecture: Architecture, contents: &str) {
+        let mut data = vec![0u8; 64];
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&64u32.to_le_bytes());
+        data.extend_from_slice(b"PE\0\0");
+        let machine: u16 = match architecture {
+            Architecture::X86 => 0x14c,
+            Architecture::X86_64 => 0x8664,
+        };
+        data.extend_from_slice(&machine.to_le_bytes());
+        data.push(0);
+        data.extend_from_slice(contents.as_bytes());
+        data.push(0);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_swap_and_restore_dlls() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let tool_path = temp_dir.path().join("GE-Proton9-20");
+        let other_tool_path = temp_dir.path().join("GE-Proton9-19");
+        let release_directory = temp_dir.path().join("release");
+        let d3d11_64 = tool_path.join("files/lib64/wine/dxvk/d3d11.dll");
+        let d3d11_32 = tool_path.join("files/lib/wine/dxvk/d3d11.dll");
+        write_dll(&d3d11_64, Architecture::X86_64, "v2.3.1");
+        write_dll(&d3d11_32, Architecture::X86, "v2.3.1");
+        write_dll(
+            &tool_path.join("files/lib64/wine/vkd3d-proton/d3d12core.dll"),
+            Architecture::X86_64,
+            "v2.12",
+        );
+        // Deduplication hardlinked the DLL into another tool
+        let other_d3d11_64 = other_tool_path.join("files/lib64/wine/dxvk/d3d11.dll");
+        fs::create_dir_all(other_d3d11_64.parent().unwrap()).unwrap();
+        fs::hard_link(&d3d11_64, &other_d3d11_64).unwrap();
+        write_dll(
+            &release_directory.join("dxvk-2.4/x64/d3d11.dll"),
+            Architecture::X86_64,
+            "v2.4",
+        );
+        write_dll(
+            &release_directory.join("dxvk-2.4/x32/d3d11.dll"),
+            Architecture::X86,
+            "v2.4",
+        );
+
+        let dxvk = detect_translation_layer(&tool_path, TranslationLayer::Dxvk).unwrap();
+        assert_eq!(dxvk.version.as_deref(), Some("v2.3.1"));
+        assert!(!dxvk.swapped);
+        let vkd3d = detect_translation_layer(&tool_path, TranslationLayer::Vkd3dProton).unwrap();
+        assert_eq!(vkd3d.version.as_deref(), Some("v2.12"));
+
+        let original_inode = fs::metadata(&d3d11_64).unwrap().ino();
+        assert_eq!(
+            swap_dlls(
+                &tool_path,
+                TranslationLayer::Dxvk,
+                &release_directory,
+                "v2.4"
+            )
+            .unwrap(),
+            2
+        );
+        assert_eq!(read_pe_architecture(&d3d11_32), Some(Architecture::X86));
+        assert_eq!(
+            find_version_string(&fs::read(&d3d11_64).unwrap()).as_deref(),
+            Some("v2.4")
+        );
+        assert_ne!(fs::metadata(&d3d11_64).unwrap().ino(), original_inode);
+        assert_eq!(
+            find_version_string(&fs::read(&other_d3d11_64).unwrap()).as_deref(),
+            Some("v2.3.1")
+        );
+        let dxvk = detect_translation_layer(&tool_path, TranslationLayer::Dxvk).unwrap();
+        assert_eq!(dxvk.version.as_deref(), Some("v2.4"));
+        assert_eq!(dxvk.original_version.as_deref(), Some("v2.3.1"));
+        assert!(dxvk.swapped);
+
+        // Swapping again keeps the DLLs the tool shipped with, and puts back a DLL an
+        // interrupted swap moved aside without copying its replacement in
+        fs::remove_file(&d3d11_32).unwrap();
+        swap_dlls(
+            &tool_path,
+            TranslationLayer::Dxvk,
+            &release_directory,
+            "v2.4",
+        )
+        .unwrap();
+        assert_eq!(
+            find_version_string(&fs::read(&d3d11_32).unwrap()).as_deref(),
+            Some("v2.4")
+        );
+        assert_eq!(restore_dlls(&tool_path, TranslationLayer::Dxvk).unwrap(), 2);
+        assert_eq!(fs::metadata(&d3d11_64).unwrap().ino(), original_inode);
+        assert_eq!(
+            find_version_string(&fs::read(&d3d11_32).unwrap()).as_deref(),
+            Some("v2.3.1")
+        );
+        assert!(!tool_path.join(ORIGINALS_DIRECTORY).exists());
+        assert!(restore_dlls(&tool_path, TranslationLayer::Dxvk).is_err());
+    }
+
+    #[test]
+    fn test_is_version_string() {
+        assert!(is_version_string("v2.3.1"));
+        assert!(is_version_string("v2.3-26-g1234abc"));
+        assert!(!is_version_string("v"));
+        assert!(!is_version_string("vkd3d"));
+        assert!(!is_version_string("2.3.1"));
+        assert!(!is_version_string("v2 3"));
+    }
+}
@@ -1,10 +1,11 @@
-use crate::wine_cask::app::WineCask;
+use crate::wine_cask::app::{TaskType, WineCask};
 use crate::wine_cask::flavors::{CompatibilityToolFlavor, SteamCompatibilityTool};
+use crate::wine_cask::history::HistoryEntry;
 use crate::wine_cask::recursive_delete_dir_entry;
 use crate::PeerMap;
-use log::error;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Uninstall {
@@ -19,6 +20,37 @@ impl WineCask {
         steam_compatibility_tool: SteamCompatibilityTool,
         peer_map: &PeerMap,
     ) {
+        let history_entry = HistoryEntry::start(
+            TaskType::UninstallCompatibilityTool,
+            Some(steam_compatibility_tool.display_name.clone()),
+            steam_compatibility_tool
+                .github_release
+                .as_ref()
+                .map(|release| release.tag_name.clone()),
+        );
+        let result = self
+            .remove_compatibility_tool(&steam_compatibility_tool)
+            .await;
+        match &result {
+            Ok(_) => {
+                // Update the app state to reflect the uninstalled tool and broadcast changes
+                self.sync_backend_with_installed_compat_tools().await;
+                self.broadcast_app_state(peer_map).await;
+                self.update_disk_usage(peer_map).await;
+            }
+            Err(error_message) => {
+                error!("{}", error_message);
+                self.broadcast_notification(peer_map, error_message).await;
+            }
+        }
+        self.record_history(history_entry.finish(&result), peer_map)
+            .await;
+    }
+
+    async fn remove_compatibility_tool(
+        &self,
+        steam_compatibility_tool: &SteamCompatibilityTool,
+    ) -> Result<(), String> {
         // Validate that the compatibility tool is installed for security reason we don't want to delete something else.
         // Find the compatibility tool to uninstall
         let matching_tools: Vec<SteamCompatibilityTool> = self
@@ -37,40 +69,90 @@ impl WineCask {
 
         // Handle cases when no matching tool is found
         if matching_tools.is_empty() {
-            let error_message = format!(
+            return Err(format!(
                 "Compatibility tool not found: {}",
                 steam_compatibility_tool.display_name
-            );
-            error!("{}", error_message);
-            self.broadcast_notification(peer_map, &error_message).await;
-            return;
+            ));
         }
 
         // Handle cases when multiple matching tools are found
         if matching_tools.len() != 1 {
-            let error_message = format!(
+            return Err(format!(
                 "Invalid number of matching tools found: {}",
                 matching_tools.len()
-            );
-            error!("{}", error_message);
-            self.broadcast_notification(peer_map, &error_message).await;
-            return;
+            ));
         }
 
         // Get the tool to uninstall (only one at this point)
         let tool_to_uninstall = &matching_tools[0];
 
+        if tool_to_uninstall.read_only {
+            return Err(format!(
+                "{} is managed by Steam, uninstall it from the Steam library",
+                tool_to_uninstall.display_name
+            ));
+        }
+
+        // Tools declared in the same compatibilitytool.vdf share its directory
+        let remaining_tools: Vec<SteamCompatibilityTool> = self
+            .app_state
+            .lock()
+            .await
+            .installed_compatibility_tools
+            .iter()
+            .filter(|tool| {
+                tool.path == tool_to_uninstall.path
+                    && tool.internal_name != tool_to_uninstall.internal_name
+            })
+            .cloned()
+            .collect();
+        if !remaining_tools.is_empty() {
+            return self
+                .remove_tool_from_shared_directory(tool_to_uninstall, &remaining_tools)
+                .map_err(|err| format!("Error during uninstallation: {}", err));
+        }
+
         // Uninstall the compatibility tool by deleting its directory
         let directory_path = PathBuf::from(&tool_to_uninstall.path);
-        if let Err(e) = recursive_delete_dir_entry(&directory_path) {
-            let error_message = format!("Error during uninstallation: {}", e);
-            error!("{}", error_message);
-            self.broadcast_notification(peer_map, &error_message).await;
-            return;
-        }
+        recursive_delete_dir_entry(&directory_path)
+            .map_err(|err| format!("Error during uninstallation: {}", err))
+    }
 
-        // Update the app state to reflect the uninstalled tool and broadcast changes
-        self.sync_backend_with_installed_compat_tools().await;
-        self.broadcast_app_state(peer_map).await;
+    /// Removes a tool from the `compatibilitytool.vdf` it shares with other tools, along with
+    /// its install path unless another tool is installed in it.
+    fn remove_tool_from_shared_directory(
+        &self,
+        tool: &SteamCompatibilityTool,
+        remaining_tools: &[SteamCompatibilityTool],
+    ) -> Result<(), String> {
+        let directory_path = PathBuf::from(&tool.path);
+        let steam_util = self
+            .steam_util_for_installation(&tool.steam_installation)
+            .ok_or_else(|| format!("Steam installation not found: {}", tool.steam_installation))?;
+        steam_util
+            .remove_compatibility_tool_from_vdf(
+                &directory_path.join("compatibilitytool.vdf"),
+                &tool.internal_name,
+            )
+            .map_err(|err| err.to_string())?;
+
+        let install_path = PathBuf::from(&tool.install_path);
+        let install_path_needed = remaining_tools.iter().any(|remaining_tool| {
+            Path::new(&remaining_tool.install_path).starts_with(&install_path)
+        });
+        if install_path != directory_path
+            && install_path.starts_with(&directory_path)
+            && !install_path_needed
+            && install_path.exists()
+        {
+            recursive_delete_dir_entry(&install_path).map_err(|err| err.to_string())?;
+        }
+        info!(
+            "Uninstalled {}, {} other tools remain in {}",
+            tool.display_name,
+            remaining_tools.len(),
+            directory_path.display()
+        );
+        Ok(())
     }
 }
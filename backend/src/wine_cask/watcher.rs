@@ -0,0 +1,175 @@
+use crate::wine_cask::app::WineCask;
+use crate::PeerMap;
+use log::{debug, error, info, warn};
+use notify::event::{Event, EventKind};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time::timeout;
+
+/// Steam and tool installers touch many files at once, wait for them to settle down.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+/// How often to check for new library folders or Steam installations to watch.
+const WATCHED_PATHS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether a changed path affects installed tools, tool mappings or installed games.
+fn is_relevant_path(path: &Path) -> bool {
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    let parent_name = path
+        .parent()
+        .and_then(|parent| parent.file_name())
+        .and_then(|name| name.to_str());
+    parent_name == Some("compatibilitytools.d")
+        || file_name == Some("compatibilitytool.vdf")
+        || file_name == Some("config.vdf")
+        || file_name == Some("libraryfolders.vdf")
+        || file_name == Some("loginusers.vdf")
+        || path.extension().is_some_and(|extension| extension == "acf")
+}
+
+fn is_relevant_event(event: &Event) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event.paths.iter().any(|path| is_relevant_path(path))
+}
+
+impl WineCask {
+    /// The compatibility tools, config and library `steamapps` directories of every managed
+    /// Steam installation. Each tool directory is watched too, so renames of a tool in its
+    /// `compatibilitytool.vdf` are noticed.
+    fn get_watched_paths(&self) -> HashSet<PathBuf> {
+        let mut watched_paths: HashSet<PathBuf> = HashSet::new();
+        for steam_util in self.steam_utils() {
+            let compatibility_tools_directory =
+                steam_util.get_steam_compatibility_tools_directory();
+            if let Ok(entries) = fs::read_dir(&compatibility_tools_directory) {
+                watched_paths.extend(entries.filter_map(Result::ok).map(|entry| entry.path()));
+            }
+            watched_paths.insert(compatibility_tools_directory);
+            watched_paths.insert(steam_util.get_steam_config_directory());
+            match steam_util.list_library_folders() {
+                Ok(library_folders) => watched_paths.extend(
+                    library_folders
+                        .into_iter()
+                        .map(|library_folder| library_folder.path.join("steamapps")),
+                ),
+                Err(err) => warn!("Failed to list library folders to watch: {}", err),
+            }
+        }
+        watched_paths.retain(|path| path.is_dir());
+        watched_paths
+    }
+}
+
+fn update_watched_paths(
+    watcher: &mut RecommendedWatcher,
+    watched_paths: &mut HashSet<PathBuf>,
+    new_watched_paths: HashSet<PathBuf>,
+) {
+    for path in watched_paths.difference(&new_watched_paths) {
+        debug!("No longer watching {}", path.display());
+        // Fails when the directory was removed, which also removed the watch
+        let _ = watcher.unwatch(path);
+    }
+    let mut result: HashSet<PathBuf> = watched_paths
+        .intersection(&new_watched_paths)
+        .cloned()
+        .collect();
+    for path in new_watched_paths.difference(watched_paths) {
+        match watcher.watch(path, RecursiveMode::NonRecursive) {
+            Ok(_) => {
+                info!("Watching {}", path.display());
+                result.insert(path.clone());
+            }
+            Err(err) => error!("Failed to watch {}: {}", path.display(), err),
+        }
+    }
+    *watched_paths = result;
+}
+
+/// Watches the Steam directories for changes made by Steam, other tool managers or the user and
+/// pushes the new state to the frontend.
+pub async fn watch_steam_directories(wine_cask: Arc<WineCask>, peer_map: PeerMap) {
+    let (sender, mut receiver) = unbounded_channel::<Event>();
+    let mut watcher = match notify::recommended_watcher(move |event: notify::Result<Event>| {
+        match event {
+            Ok(event) => {
+                // Only fails once the receiving task is gone
+                let _ = sender.send(event);
+            }
+            Err(err) => warn!("Filesystem watcher error: {}", err),
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!("Failed to create filesystem watcher: {}", err);
+            return;
+        }
+    };
+    let mut watched_paths: HashSet<PathBuf> = HashSet::new();
+
+    loop {
+        update_watched_paths(
+            &mut watcher,
+            &mut watched_paths,
+            wine_cask.get_watched_paths(),
+        );
+
+        let event = match timeout(WATCHED_PATHS_REFRESH_INTERVAL, receiver.recv()).await {
+            Ok(Some(event)) => event,
+            Ok(None) => return,
+            Err(_) => continue,
+        };
+        if !is_relevant_event(&event) {
+            continue;
+        }
+        debug!("Steam directories changed: {:?}", event.paths);
+
+        // Wait until no more events arrive
+        loop {
+            match timeout(DEBOUNCE_INTERVAL, receiver.recv()).await {
+                Ok(Some(_)) => {}
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+
+        info!("Steam directories changed, refreshing state");
+        wine_cask.sync_backend_with_installed_compat_tools().await;
+        wine_cask.update_used_by_games(&peer_map).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_relevant_path() {
+        let steam_dir = Path::new("/home/deck/.local/share/Steam");
+        assert!(is_relevant_path(
+            &steam_dir.join("compatibilitytools.d/GE-Proton9-20")
+        ));
+        assert!(is_relevant_path(&steam_dir.join(
+            "compatibilitytools.d/GE-Proton9-20/compatibilitytool.vdf"
+        )));
+        assert!(!is_relevant_path(
+            &steam_dir.join("compatibilitytools.d/GE-Proton9-20/proton")
+        ));
+        assert!(is_relevant_path(&steam_dir.join("config/config.vdf")));
+        assert!(is_relevant_path(
+            &steam_dir.join("steamapps/libraryfolders.vdf")
+        ));
+        assert!(is_relevant_path(
+            &steam_dir.join("steamapps/appmanifest_730.acf")
+        ));
+        assert!(is_relevant_path(&steam_dir.join("config/loginusers.vdf")));
+        assert!(!is_relevant_path(
+            &steam_dir.join("config/coplay_12345678.vdf")
+        ));
+        assert!(!is_relevant_path(&steam_dir.join("steamapps/downloading")));
+    }
+}
@@ -0,0 +1,643 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds between 1601-01-01, where Windows time starts, and the Unix epoch.
+const FILETIME_UNIX_EPOCH_OFFSET: u64 = 11_644_473_600;
+/// Backups kept of each registry file, older ones are removed when a new one is taken.
+const REGISTRY_BACKUP_COUNT: usize = 5;
+
+/// Data of a registry value, types other than strings and dwords are kept in their text form.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryData {
+    String(String),
+    Dword(u32),
+    Raw(String),
+}
+
+impl RegistryData {
+    fn parse(data: &str) -> RegistryData {
+        if data.starts_with('"') {
+            if let Some((value, rest)) = parse_quoted(data) {
+                if rest.trim().is_empty() {
+                    return RegistryData::String(value);
+                }
+            }
+        } else if let Some(dword) = data.strip_prefix("dword:") {
+            if let Ok(dword) = u32::from_str_radix(dword.trim(), 16) {
+                return RegistryData::Dword(dword);
+            }
+        }
+        RegistryData::Raw(data.to_string())
+    }
+}
+
+impl fmt::Display for RegistryData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryData::String(value) => write!(f, "\"{}\"", escape(value)),
+            RegistryData::Dword(value) => write!(f, "dword:{:08x}", value),
+            RegistryData::Raw(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum RegistryLine {
+    /// `raw_name` is the name as written in the file, `@` for the default value.
+    Value {
+        name: Option<String>,
+        raw_name: String,
+        data: String,
+    },
+    /// Metadata such as `#time=`, comments and blank lines, kept as they are.
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RegistryKey {
+    name: String,
+    raw_name: String,
+    /// Whatever follows the closing bracket, usually the modification time.
+    suffix: String,
+    lines: Vec<RegistryLine>,
+}
+
+impl RegistryKey {
+    fn find_value(&self, name: &str) -> Option<usize> {
+        self.lines.iter().position(|line| match line {
+            RegistryLine::Value {
+                name: Some(value_name),
+                ..
+            } => value_name.eq_ignore_ascii_case(name),
+            _ => false,
+        })
+    }
+
+    /// Updates the modification time, Wine prefers the `#time=` line as a Windows `FILETIME`
+    /// over the seconds after the key name.
+    fn touch(&mut self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        self.suffix = format!(" {}", now);
+        let file_time = (now + FILETIME_UNIX_EPOCH_OFFSET) * 10_000_000;
+        for line in &mut self.lines {
+            if let RegistryLine::Other(line) = line {
+                if line.starts_with("#time=") {
+                    *line = format!("#time={:x}", file_time);
+                }
+            }
+        }
+    }
+}
+
+/// A registry file in Wine's text format, such as `user.reg` and `system.reg` of a prefix.
+/// Anything not touched through the API is written back exactly as it was read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryFile {
+    header: Vec<String>,
+    keys: Vec<RegistryKey>,
+}
+
+/// Escapes a string the way Wine does, characters outside printable ASCII become `\x` escapes
+/// of their UTF-16 code units.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            ' '..='~' => escaped.push(c),
+            _ => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\x{:04x}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut units: Vec<u16> = Vec::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    let mut buffer = [0u16; 2];
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            units.extend_from_slice(c.encode_utf16(&mut buffer));
+            continue;
+        }
+        let Some(escaped) = chars.next() else {
+            units.push('\\' as u16);
+            break;
+        };
+        let unit = match escaped {
+            'a' => 0x07,
+            'b' => 0x08,
+            'e' => 0x1b,
+            'f' => 0x0c,
+            'n' => 0x0a,
+            'r' => 0x0d,
+            't' => 0x09,
+            'v' => 0x0b,
+            'x' => {
+                let mut unit = 0u16;
+                for _ in 0..4 {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => {
+                            unit = unit * 16 + digit as u16;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                unit
+            }
+            '0'..='7' => {
+                let mut unit = escaped.to_digit(8).unwrap() as u16;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            unit = unit * 8 + digit as u16;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                unit
+            }
+            other => {
+                units.extend_from_slice(other.encode_utf16(&mut buffer));
+                continue;
+            }
+        };
+        units.push(unit);
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Splits a quoted string at the start of `text` into its unescaped value and the rest.
+fn parse_quoted(text: &str) -> Option<(String, &str)> {
+    let inner = text.strip_prefix('"')?;
+    let mut escaped = false;
+    for (index, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((unescape(&inner[..index]), &inner[index + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Parses a `"name"=data` or `@=data` line.
+fn parse_value_line(line: &str) -> Option<RegistryLine> {
+    let (name, rest) = if let Some(rest) = line.strip_prefix('@') {
+        (None, rest)
+    } else {
+        let (name, rest) = parse_quoted(line)?;
+        (Some(name), rest)
+    };
+    let raw_name = &line[..line.len() - rest.len()];
+    let data = rest.strip_prefix('=')?;
+    Some(RegistryLine::Value {
+        name,
+        raw_name: raw_name.to_string(),
+        data: data.to_string(),
+    })
+}
+
+impl RegistryFile {
+    pub fn parse(contents: &str) -> io::Result<RegistryFile> {
+        let mut lines = contents.lines();
+        if !lines
+            .clone()
+            .next()
+            .is_some_and(|line| line.starts_with("WINE REGISTRY Version"))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a Wine registry file",
+            ));
+        }
+
+        let mut registry = RegistryFile {
+            header: Vec::new(),
+            keys: Vec::new(),
+        };
+        while let Some(line) = lines.next() {
+            if let Some(key_line) = line.strip_prefix('[') {
+                // Wine doesn't escape brackets in key names, only a timestamp follows the last one
+                if let Some(end) = key_line.rfind(']') {
+                    let raw_name = &key_line[..end];
+                    registry.keys.push(RegistryKey {
+                        name: unescape(raw_name),
+                        raw_name: raw_name.to_string(),
+                        suffix: key_line[end + 1..].to_string(),
+                        lines: Vec::new(),
+                    });
+                    continue;
+                }
+            }
+            let Some(key) = registry.keys.last_mut() else {
+                registry.header.push(line.to_string());
+                continue;
+            };
+            match parse_value_line(line) {
+                Some(RegistryLine::Value {
+                    name,
+                    raw_name,
+                    mut data,
+                }) => {
+                    // Binary data is wrapped over several lines ending in a backslash
+                    if !data.starts_with('"') {
+                        while data.ends_with('\\') {
+                            let Some(next_line) = lines.next() else {
+                                break;
+                            };
+                            data.push('\n');
+                            data.push_str(next_line);
+                        }
+                    }
+                    key.lines.push(RegistryLine::Value {
+                        name,
+                        raw_name,
+                        data,
+                    });
+                }
+                _ => key.lines.push(RegistryLine::Other(line.to_string())),
+            }
+        }
+        Ok(registry)
+    }
+
+    pub fn read(path: &Path) -> io::Result<RegistryFile> {
+        RegistryFile::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Writes the registry to a temporary file which is renamed into place once it is complete.
+    /// Every write first keeps the current file as `<file>.wine-cellar-backup-<timestamp>`,
+    /// unlike Steam config files a prefix is changed often enough to want more than the original.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No file name"))?
+            .to_string_lossy()
+            .to_string();
+        if path.exists() {
+            backup_registry_file(path, &file_name)?;
+        }
+        let temporary_file = path.with_file_name(format!("{}.wine-cellar-tmp", file_name));
+        let mut file = fs::File::create(&temporary_file)?;
+        file.write_all(self.to_string().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary_file, path)
+    }
+
+    fn find_key(&self, key: &str) -> Option<&RegistryKey> {
+        self.keys
+            .iter()
+            .find(|registry_key| registry_key.name.eq_ignore_ascii_case(key))
+    }
+
+    /// All named values of a key, in file order.
+    pub fn values(&self, key: &str) -> Vec<(String, RegistryData)> {
+        let Some(registry_key) = self.find_key(key) else {
+            return Vec::new();
+        };
+        registry_key
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                RegistryLine::Value {
+                    name: Some(name),
+                    data,
+                    ..
+                } => Some((name.clone(), RegistryData::parse(data))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Looks up a value, key and value names are case-insensitive like in Windows.
+    pub fn get_value(&self, key: &str, name: &str) -> Option<RegistryData> {
+        let registry_key = self.find_key(key)?;
+        match &registry_key.lines[registry_key.find_value(name)?] {
+            RegistryLine::Value { data, .. } => Some(RegistryData::parse(data)),
+            RegistryLine::Other(_) => None,
+        }
+    }
+
+    /// Sets a value, creating the key if needed.
+    pub fn set_value(&mut self, key: &str, name: &str, data: RegistryData) {
+        let index = match self
+            .keys
+            .iter()
+            .position(|registry_key| registry_key.name.eq_ignore_ascii_case(key))
+        {
+            Some(index) => index,
+            None => {
+                // Keys are separated by a blank line
+                if let Some(last_key) = self.keys.last_mut() {
+                    if !matches!(last_key.lines.last(), Some(RegistryLine::Other(line)) if line.is_empty())
+                    {
+                        last_key.lines.push(RegistryLine::Other(String::new()));
+                    }
+                }
+                self.keys.push(RegistryKey {
+                    name: key.to_string(),
+                    raw_name: escape(key),
+                    suffix: String::new(),
+                    lines: vec![RegistryLine::Other(String::new())],
+                });
+                self.keys.len() - 1
+            }
+        };
+
+        let registry_key = &mut self.keys[index];
+        let value = RegistryLine::Value {
+            name: Some(name.to_string()),
+            raw_name: format!("\"{}\"", escape(name)),
+            data: data.to_string(),
+        };
+        match registry_key.find_value(name) {
+            Some(position) => registry_key.lines[position] = value,
+            None => {
+                // New values go after the existing ones, ahead of the blank line ending the key
+                let position = registry_key
+                    .lines
+                    .iter()
+                    .rposition(|line| matches!(line, RegistryLine::Value { .. }))
+                    .map(|position| position + 1)
+                    .unwrap_or_else(|| {
+                        registry_key
+                            .lines
+                            .iter()
+                            .take_while(|line| matches!(line, RegistryLine::Other(line) if line.starts_with('#')))
+                            .count()
+                    });
+                registry_key.lines.insert(position, value);
+            }
+        }
+        registry_key.touch();
+    }
+
+    /// Removes a value, returns whether it existed.
+    pub fn delete_value(&mut self, key: &str, name: &str) -> bool {
+        let Some(registry_key) = self
+            .keys
+            .iter_mut()
+            .find(|registry_key| registry_key.name.eq_ignore_ascii_case(key))
+        else {
+            return false;
+        };
+        let Some(position) = registry_key.find_value(name) else {
+            return false;
+        };
+        registry_key.lines.remove(position);
+        registry_key.touch();
+        true
+    }
+}
+
+/// Copies a registry file to a timestamped backup and removes all but the newest
+/// `REGISTRY_BACKUP_COUNT` backups of it.
+fn backup_registry_file(path: &Path, file_name: &str) -> io::Result<()> {
+    let backup_prefix = format!("{}.wine-cellar-backup-", file_name);
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Failed to calculate duration")
+        .as_secs();
+    let backup_file = path.with_file_name(format!("{}{}", backup_prefix, created));
+    // A backup taken earlier in the same second already holds the older contents
+    if !backup_file.exists() {
+        fs::copy(path, &backup_file)?;
+    }
+
+    let Some(directory) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    else {
+        return Ok(());
+    };
+    let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(directory)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let created = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix(&backup_prefix)?
+                .parse()
+                .ok()?;
+            Some((created, entry.path()))
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(REGISTRY_BACKUP_COUNT);
+    for (_, backup) in backups.drain(..excess) {
+        fs::remove_file(backup)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for RegistryFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.header {
+            writeln!(f, "{}", line)?;
+        }
+        for key in &self.keys {
+            writeln!(f, "[{}]{}", key.raw_name, key.suffix)?;
+            for line in &key.lines {
+                match line {
+                    RegistryLine::Value { raw_name, data, .. } => {
+                        writeln!(f, "{}={}", raw_name, data)?
+                    }
+                    RegistryLine::Other(line) => writeln!(f, "{}", line)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_REG: &str = r#"WINE REGISTRY Version 2
+;; All keys relative to \\User\\S-1-5-21-0-0-0-1000
+
+#arch=win64
+
+[Software\\Wine] 1716900000
+#time=1dab12345678901
+"Version"="win7"
+
+[Software\\Wine\\DllOverrides] 1716900000
+#time=1dab12345678901
+"*d3d11"="native"
+"d3dcompiler_47"="native,builtin"
+"xinput1_3"=""
+
+[Software\\Wine\\Fonts] 1716900000
+#time=1dab12345678901
+"Blob"=hex:01,02,03,\
+  04,05
+"Caf\x00e9 \"Bar\""=dword:0000000a
+@="default"
+"#;
+
+    #[test]
+    fn test_parse_and_write_registry() {
+        let registry = RegistryFile::parse(USER_REG).expect("Failed to parse registry");
+        assert_eq!(registry.to_string(), USER_REG);
+
+        assert_eq!(
+            registry.get_value("software\\wine", "version"),
+            Some(RegistryData::String("win7".to_string()))
+        );
+        assert_eq!(
+            registry.values("Software\\Wine\\DllOverrides"),
+            vec![
+                (
+                    "*d3d11".to_string(),
+                    RegistryData::String("native".to_string())
+                ),
+                (
+                    "d3dcompiler_47".to_string(),
+                    RegistryData::String("native,builtin".to_string())
+                ),
+                ("xinput1_3".to_string(), RegistryData::String(String::new())),
+            ]
+        );
+        assert_eq!(
+            registry.get_value("Software\\Wine\\Fonts", "Café \"Bar\""),
+            Some(RegistryData::Dword(10))
+        );
+        assert_eq!(
+            registry.get_value("Software\\Wine\\Fonts", "Blob"),
+            Some(RegistryData::Raw("hex:01,02,03,\\\n  04,05".to_string()))
+        );
+        assert!(RegistryFile::parse("[Software]\n").is_err());
+    }
+
+    #[test]
+    fn test_set_and_delete_registry_values() {
+        let mut registry = RegistryFile::parse(USER_REG).expect("Failed to parse registry");
+        registry.set_value(
+            "Software\\Wine\\DllOverrides",
+            "dxgi",
+            RegistryData::String("native,builtin".to_string()),
+        );
+        registry.set_value(
+            "Software\\Wine\\DllOverrides",
+            "*D3D11",
+            RegistryData::String("builtin".to_string()),
+        );
+        assert!(registry.delete_value("Software\\Wine\\DllOverrides", "xinput1_3"));
+        assert!(!registry.delete_value("Software\\Wine\\DllOverrides", "xinput1_3"));
+        registry.set_value(
+            "Software\\Wine\\X11 Driver",
+            "Decorated",
+            RegistryData::String("N".to_string()),
+        );
+
+        // Changed keys get a new modification time
+        assert_ne!(registry.keys[1].suffix, " 1716900000");
+        assert_ne!(
+            registry.keys[1].lines[0],
+            RegistryLine::Other("#time=1dab12345678901".to_string())
+        );
+        registry.keys[3].suffix = " 1".to_string();
+
+        let written = registry.to_string();
+        assert!(written.contains(
+            "\"*D3D11\"=\"builtin\"\n\"d3dcompiler_47\"=\"native,builtin\"\n\"dxgi\"=\"native,builtin\"\n\n[Software\\\\Wine\\\\Fonts] 1716900000"
+        ));
+        assert!(!written.contains("xinput1_3"));
+        assert!(written.ends_with(
+            "@=\"default\"\n\n[Software\\\\Wine\\\\X11 Driver] 1\n\"Decorated\"=\"N\"\n\n"
+        ));
+        // Keys that didn't change keep their timestamps
+        assert!(written.contains("[Software\\\\Wine] 1716900000\n#time=1dab12345678901\n"));
+
+        let reparsed = RegistryFile::parse(&written).expect("Failed to parse written registry");
+        assert_eq!(
+            reparsed.get_value("Software\\Wine\\DllOverrides", "dxgi"),
+            Some(RegistryData::String("native,builtin".to_string()))
+        );
+        assert_eq!(
+            reparsed.get_value("Software\\Wine\\X11 Driver", "Decorated"),
+            Some(RegistryData::String("N".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_escape_registry_strings() {
+        for value in ["C:\\windows\\system32", "Caf\u{e9} \"Bar\"\n", "\u{1f377}"] {
+            assert_eq!(unescape(&escape(value)), value);
+        }
+        assert_eq!(escape("Caf\u{e9}"), "Caf\\x00e9");
+        assert_eq!(unescape("\\101\\x41\\x00410"), "AAA0");
+    }
+
+    #[test]
+    fn test_write_registry_backups() {
+        let prefix_dir = tempfile::tempdir().expect("Failed to create temporary directory");
+        let path = prefix_dir.path().join("user.reg");
+        fs::write(&path, USER_REG).unwrap();
+        for created in 1..=REGISTRY_BACKUP_COUNT {
+            fs::write(
+                prefix_dir
+                    .path()
+                    .join(format!("user.reg.wine-cellar-backup-{}", created)),
+                "",
+            )
+            .unwrap();
+        }
+
+        let mut registry = RegistryFile::parse(USER_REG).expect("Failed to parse registry");
+        registry.set_value(
+            "Software\\Wine",
+            "Version",
+            RegistryData::String("win10".to_string()),
+        );
+        registry.write(&path).expect("Failed to write registry");
+
+        let mut backups: Vec<String> = fs::read_dir(prefix_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|file_name| file_name.starts_with("user.reg.wine-cellar-backup-"))
+            .collect();
+        backups.sort();
+        assert_eq!(backups.len(), REGISTRY_BACKUP_COUNT);
+        assert!(!backups.contains(&"user.reg.wine-cellar-backup-1".to_string()));
+        let newest_backup = backups
+            .iter()
+            .max_by_key(|file_name| {
+                file_name
+                    .rsplit('-')
+                    .next()
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            })
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(prefix_dir.path().join(newest_backup)).unwrap(),
+            USER_REG
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), registry.to_string());
+        assert!(!prefix_dir.path().join("user.reg.wine-cellar-tmp").exists());
+    }
+}
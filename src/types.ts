@@ -33,12 +33,115 @@ export type AppState = {
   task_queue: Task[];
   updater_state: UpdaterState;
   updater_last_check?: number;
+  compatibility_tools_disk_usage?: DiskUsage;
+  last_deduplication?: DeduplicationReport;
+  compat_data_prefixes?: SteamCompatDataPrefix[];
+  prefix_backups: PrefixBackup[];
+  prefix_version_warnings: PrefixVersionWarning[];
+  settings: Settings;
+  steam_installations: SteamInstallation[];
+  managed_steam_installations: string[];
+  steam_not_found?: string;
+  installed_games: SteamGame[];
+  library_folders: SteamLibraryFolder[];
+  steam_users: SteamInstallationUser[];
+  running_steam_installations: string[];
+  launch_options?: GameLaunchOptions;
+  translation_layers?: ToolTranslationLayers;
+  proton_logs?: ProtonLog[];
+  prefix_registry?: PrefixRegistry;
+  history?: HistoryPage;
+};
+
+export type GameLaunchOptions = {
+  app_id: number;
+  launch_options?: string;
+  environment: ProtonEnvironmentVariable[];
+};
+
+export type ProtonEnvironmentVariable = {
+  name: string;
+  value?: string;
+};
+
+export type SteamInstallationUser = {
+  steam_installation: string;
+  active: boolean;
+  steam_id: string;
+  account_id: number;
+  account_name: string;
+  persona_name: string;
+  most_recent: boolean;
+  timestamp: number;
+};
+
+export type SteamLibraryFolder = {
+  path: string;
+  label: string;
+  content_id?: string;
+  apps: { [app_id: string]: number };
+  mounted: boolean;
+  capacity?: number;
+  free_space?: number;
+  steam_installation: string;
+  status: LibraryFolderStatus;
+  error?: string;
+};
+
+export enum LibraryFolderStatus {
+  Ok = "Ok",
+  Missing = "Missing",
+  Unmounted = "Unmounted",
+  ParseError = "ParseError",
+}
+
+export type SteamGame = {
+  app_id: number;
+  name: string;
+  steam_installation: string;
+  native_linux: boolean;
+  compatibility_tool?: string;
+  app_info?: AppInfo;
+};
+
+export type AppInfo = {
+  os_list: string[];
+  steam_deck_compatibility: SteamDeckCompatibility;
+  recommended_compat_tool?: string;
+  install_dir?: string;
+};
+
+export enum SteamDeckCompatibility {
+  Unknown = "Unknown",
+  Unsupported = "Unsupported",
+  Playable = "Playable",
+  Verified = "Verified",
+}
+
+export type Settings = {
+  backup_directory?: string;
+  managed_steam_installations?: string[];
+};
+
+export type SteamInstallation = {
+  id: string;
+  path: string;
+  flatpak: boolean;
 };
 
 export type Task = {
   type: TaskType;
   install?: Install;
   uninstall?: Uninstall;
+  prefix?: Prefix;
+  settings?: Settings;
+  launch_options?: GameLaunchOptions;
+  rename?: Rename;
+  clone_tool?: CloneCompatibilityTool;
+  translation_layers?: TranslationLayerRequest;
+  proton_log?: ProtonLogRequest;
+  prefix_registry?: PrefixRegistryRequest;
+  history?: HistoryQuery;
 };
 
 export enum TaskType {
@@ -46,6 +149,25 @@ export enum TaskType {
   InstallCompatibilityTool = "InstallCompatibilityTool",
   CancelCompatibilityToolInstall = "CancelCompatibilityToolInstall",
   UninstallCompatibilityTool = "UninstallCompatibilityTool",
+  DeduplicateCompatibilityTools = "DeduplicateCompatibilityTools",
+  ListCompatDataPrefixes = "ListCompatDataPrefixes",
+  DeleteCompatDataPrefix = "DeleteCompatDataPrefix",
+  ResetCompatDataPrefix = "ResetCompatDataPrefix",
+  BackupCompatDataPrefix = "BackupCompatDataPrefix",
+  RestoreCompatDataPrefix = "RestoreCompatDataPrefix",
+  UpdateSettings = "UpdateSettings",
+  GetLaunchOptions = "GetLaunchOptions",
+  UpdateLaunchOptions = "UpdateLaunchOptions",
+  RenameCompatibilityTool = "RenameCompatibilityTool",
+  CloneCompatibilityTool = "CloneCompatibilityTool",
+  GetTranslationLayers = "GetTranslationLayers",
+  SwapTranslationLayer = "SwapTranslationLayer",
+  ListProtonLogs = "ListProtonLogs",
+  BundleProtonLog = "BundleProtonLog",
+  GetPrefixRegistry = "GetPrefixRegistry",
+  UpdateDllOverrides = "UpdateDllOverrides",
+  UpdateWindowsVersion = "UpdateWindowsVersion",
+  GetHistory = "GetHistory",
 }
 
 export type Flavor = {
@@ -71,16 +193,189 @@ export type Uninstall = {
   steam_compatibility_tool: SteamCompatibilityTool;
 };
 
+export type Rename = {
+  steam_compatibility_tool: SteamCompatibilityTool;
+  display_name?: string;
+};
+
+export type CloneCompatibilityTool = {
+  steam_compatibility_tool: SteamCompatibilityTool;
+  internal_name: string;
+  display_name: string;
+};
+
+export type TranslationLayerRequest = {
+  steam_compatibility_tool: SteamCompatibilityTool;
+  layer?: TranslationLayer;
+  tag_name?: string;
+};
+
+export type ToolTranslationLayers = {
+  path: string;
+  internal_name: string;
+  layers: BundledTranslationLayer[];
+};
+
+export type BundledTranslationLayer = {
+  layer: TranslationLayer;
+  version?: string;
+  swapped: boolean;
+  original_version?: string;
+  available_releases: string[];
+};
+
+export enum TranslationLayer {
+  Dxvk = "Dxvk",
+  Vkd3dProton = "Vkd3dProton",
+}
+
+export type ProtonLog = {
+  app_id: number;
+  game_name?: string;
+  file_name: string;
+  path: string;
+  size: number;
+  created?: number;
+  modified?: number;
+};
+
+export type ProtonLogRequest = {
+  path: string;
+};
+
+export type PrefixRegistry = {
+  app_id: number;
+  dll_overrides: DllOverride[];
+  windows_version?: string;
+  windows_product_name?: string;
+};
+
+export type PrefixRegistryRequest = {
+  app_id: number;
+  dll_overrides?: DllOverride[];
+  windows_version?: string;
+};
+
+export type DllOverride = {
+  dll: string;
+  mode?: DllOverrideMode;
+};
+
+export enum DllOverrideMode {
+  Native = "Native",
+  Builtin = "Builtin",
+  NativeBuiltin = "NativeBuiltin",
+  BuiltinNative = "BuiltinNative",
+  Disabled = "Disabled",
+}
+
+export type HistoryEntry = {
+  id: number;
+  task_type: TaskType;
+  tool?: string;
+  release?: string;
+  started: number;
+  finished: number;
+  result: HistoryResult;
+  error?: string;
+  bytes_downloaded: number;
+};
+
+export type HistoryQuery = {
+  offset?: number;
+  limit?: number;
+  task_type?: TaskType;
+  result?: HistoryResult;
+  search?: string;
+  since?: number;
+  until?: number;
+};
+
+export type HistoryPage = {
+  query: HistoryQuery;
+  entries: HistoryEntry[];
+  total: number;
+};
+
+export enum HistoryResult {
+  Succeeded = "Succeeded",
+  Failed = "Failed",
+  Cancelled = "Cancelled",
+}
+
+export type Prefix = {
+  app_id: number;
+  exclude_caches?: boolean;
+  backup_file_name?: string;
+};
+
+export type PrefixBackup = {
+  file_name: string;
+  app_id: number;
+  created: number;
+  size: number;
+};
+
+export type SteamCompatDataPrefix = {
+  app_id: number;
+  path: string;
+  game_name?: string;
+  prefix_version?: string;
+  tool_version?: string;
+  disk_usage?: DiskUsage;
+};
+
 export type SteamCompatibilityTool = {
   path: string;
+  install_path: string;
   //name: string;
   directory_name: string;
   internal_name: string;
   display_name: string;
+  original_display_name?: string;
+  from_os_list: string;
+  to_os_list: string;
   used_by_games: string[];
   requires_restart: boolean;
   flavor: CompatibilityToolFlavor;
   github_release?: GitHubRelease;
+  disk_usage?: DiskUsage;
+  prefix_version?: string;
+  steam_installation: string;
+  app_id?: number;
+  read_only: boolean;
+  missing_dependency?: ToolDependency;
+};
+
+export type ToolDependency = {
+  app_id: number;
+  name: string;
+};
+
+export type PrefixVersionWarning = {
+  app_id: number;
+  game_name: string;
+  compatibility_tool: string;
+  tool_prefix_version: string;
+  prefix_version: string;
+  kind: PrefixVersionWarningKind;
+};
+
+export enum PrefixVersionWarningKind {
+  Downgrade = "Downgrade",
+  DifferentFlavor = "DifferentFlavor",
+}
+
+export type DeduplicationReport = {
+  files_scanned: number;
+  files_hardlinked: number;
+  files_reflinked: number;
+  bytes_reclaimed: number;
+};
+
+export type DiskUsage = {
+  apparent_bytes: number;
+  allocated_bytes: number;
 };
 
 export type QueueCompatibilityTool = {